@@ -99,6 +99,9 @@ mod macos {
             "SpriteInputIndex".into(),
             "MonochromeSprite".into(),
             "PolychromeSprite".into(),
+            "BlendMode".into(),
+            "Sampling".into(),
+            "Rotation".into(),
             "PathSprite".into(),
             "SurfaceInputIndex".into(),
             "SurfaceBounds".into(),
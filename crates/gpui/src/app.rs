@@ -28,13 +28,17 @@ use util::{
 };
 
 use crate::{
-    current_platform, init_app_menus, Action, ActionRegistry, Any, AnyView, AnyWindowHandle,
-    AppMetadata, AssetCache, AssetSource, BackgroundExecutor, ClipboardItem, Context,
-    DispatchPhase, DisplayId, Entity, EventEmitter, ForegroundExecutor, Global, KeyBinding, Keymap,
-    Keystroke, LayoutId, Menu, PathPromptOptions, Pixels, Platform, PlatformDisplay, Point,
-    PromptBuilder, PromptHandle, PromptLevel, Render, RenderablePromptHandle, Reservation,
-    SharedString, SubscriberSet, Subscription, SvgRenderer, Task, TextSystem, View, ViewContext,
-    Window, WindowAppearance, WindowContext, WindowHandle, WindowId,
+    current_platform, init_app_menus, reload_svg_fontdb, Action, ActionRegistry, Any, AnyView,
+    AnyWindowHandle, AppMetadata, AssetCache, AssetSource, BackgroundExecutor, ClipboardItem,
+    Context, DevicePixels, DispatchPhase, DisplayId, Entity, EventEmitter, ForegroundExecutor,
+    Global, Hsla, ImageData, ImageDiskCache, ImageEvent, ImageFetches, ImageInstrumentation,
+    ImageInterner, ImageLoadProgress, ImageLoadQueue, KeyBinding, Keymap, Keystroke, LayoutId,
+    LoadingAsset, Menu, PathPromptOptions, Pixels, Platform, PlatformDisplay, Point, PromptBuilder,
+    PromptHandle, PromptLevel, Render, RenderSvgParams, RenderablePromptHandle, Reservation,
+    RetryPolicy, SharedString, Size, SubscriberSet, Subscription, SvgFailurePolicy, SvgFontLoading,
+    SvgOptions, SvgRenderer, Task, TextSystem, View, ViewContext, Window, WindowAppearance,
+    WindowContext, WindowHandle, WindowId, DEFAULT_IMAGE_FETCH_CONCURRENCY,
+    DEFAULT_MAX_IMAGE_PIXELS,
 };
 
 mod async_context;
@@ -126,7 +130,23 @@ impl App {
         let mut context_lock = self.0.borrow_mut();
         let asset_source = Arc::new(asset_source);
         context_lock.asset_source = asset_source.clone();
-        context_lock.svg_renderer = SvgRenderer::new(asset_source);
+        context_lock.svg_renderer.set_asset_source(asset_source);
+        drop(context_lock);
+        self
+    }
+
+    /// Render the text in SVGs with the given font files from the app's asset source,
+    /// rather than with the system fonts. Fonts that fail to load are logged and skipped.
+    pub fn with_svg_fonts(self, font_paths: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        let mut context_lock = self.0.borrow_mut();
+        let asset_source = context_lock.svg_renderer.asset_source().clone();
+        let mut fontdb = usvg::fontdb::Database::new();
+        for path in font_paths {
+            if let Some(font) = asset_source.load(path.as_ref()).log_err() {
+                fontdb.load_font_data(font.into_owned());
+            }
+        }
+        context_lock.svg_renderer.set_fontdb(Arc::new(fontdb));
         drop(context_lock);
         self
     }
@@ -217,10 +237,23 @@ pub struct AppContext {
     pub(crate) active_drag: Option<AnyDrag>,
     pub(crate) background_executor: BackgroundExecutor,
     pub(crate) foreground_executor: ForegroundExecutor,
-    pub(crate) loading_assets: FxHashMap<(TypeId, u64), Box<dyn Any>>,
+    pub(crate) loading_assets: FxHashMap<(TypeId, u64), LoadingAsset>,
     pub(crate) asset_cache: AssetCache,
     asset_source: Arc<dyn AssetSource>,
     pub(crate) svg_renderer: SvgRenderer,
+    image_fetch_policy: RetryPolicy,
+    image_disk_cache: Option<ImageDiskCache>,
+    reduce_motion: bool,
+    pub(crate) image_load_progress: ImageLoadProgress,
+    pub(crate) image_instrumentation: ImageInstrumentation,
+    pub(crate) image_fetches: ImageFetches,
+    /// Images that failed with a transient error and are waiting for a cooldown to pass
+    /// before they're fetched again, keyed by the hash of their source.
+    pub(crate) image_retries: FxHashSet<u64>,
+    image_interner: Option<ImageInterner>,
+    image_fetch_queue: ImageLoadQueue,
+    image_decode_queue: ImageLoadQueue,
+    image_max_pixels: u64,
     http_client: Arc<dyn HttpClient>,
     pub(crate) globals_by_type: FxHashMap<TypeId, Box<dyn Any>>,
     pub(crate) entities: EntityMap,
@@ -282,6 +315,17 @@ impl AppContext {
                 foreground_executor,
                 svg_renderer: SvgRenderer::new(asset_source.clone()),
                 asset_cache: AssetCache::new(),
+                image_fetch_policy: RetryPolicy::default(),
+                image_disk_cache: None,
+                reduce_motion: false,
+                image_load_progress: ImageLoadProgress::default(),
+                image_instrumentation: ImageInstrumentation::default(),
+                image_fetches: ImageFetches::default(),
+                image_retries: FxHashSet::default(),
+                image_interner: None,
+                image_fetch_queue: ImageLoadQueue::new(DEFAULT_IMAGE_FETCH_CONCURRENCY),
+                image_decode_queue: ImageLoadQueue::new(num_cpus::get()),
+                image_max_pixels: DEFAULT_MAX_IMAGE_PIXELS,
                 loading_assets: Default::default(),
                 asset_source,
                 http_client,
@@ -661,6 +705,177 @@ impl AppContext {
         self.svg_renderer.clone()
     }
 
+    /// Returns the cache GPUI stores loaded assets, such as images, in.
+    pub fn asset_cache(&self) -> AssetCache {
+        self.asset_cache.clone()
+    }
+
+    /// Registers a listener for the steps of loading images, such as fetching and decoding
+    /// them, along with how long they took, for profiling. Only remote images are fetched.
+    /// The listener is called from background threads, and replaces any previous one.
+    pub fn on_image_event(&mut self, listener: impl Fn(&ImageEvent) + Send + Sync + 'static) {
+        self.image_instrumentation = ImageInstrumentation(Some(Arc::new(listener)));
+    }
+
+    /// Sets the timeout and retry behavior used when fetching remote images.
+    pub fn set_image_fetch_policy(&mut self, policy: RetryPolicy) {
+        self.image_fetch_policy = policy;
+    }
+
+    /// Returns the timeout and retry behavior used when fetching remote images.
+    pub fn image_fetch_policy(&self) -> RetryPolicy {
+        self.image_fetch_policy
+    }
+
+    /// Persists fetched remote images in the given directory, so they're read from disk
+    /// instead of being downloaded again, even after the app restarts. Once the directory
+    /// holds more than `max_bytes`, the least recently used images are removed from it.
+    pub fn set_image_disk_cache(&mut self, directory: impl Into<PathBuf>, max_bytes: u64) {
+        self.image_disk_cache = Some(ImageDiskCache::new(directory, max_bytes));
+    }
+
+    pub(crate) fn image_disk_cache(&self) -> Option<ImageDiskCache> {
+        self.image_disk_cache.clone()
+    }
+
+    /// Sets whether images with identical pixels share a single decoded buffer, even when
+    /// they're loaded from different sources, such as mirrors of the same image. This
+    /// costs a hash of each image's pixels after it's decoded, so it's off by default.
+    /// The [`AssetCache`]'s memory budget still counts each source's image separately.
+    pub fn set_image_deduplication(&mut self, enabled: bool) {
+        if enabled != self.image_interner.is_some() {
+            self.image_interner = enabled.then(ImageInterner::default);
+        }
+    }
+
+    pub(crate) fn image_interner(&self) -> Option<ImageInterner> {
+        self.image_interner.clone()
+    }
+
+    /// Sets how many remote images can be downloaded at once. Further images wait for one
+    /// of them to finish, and are downloaded in order of their [`crate::LoadPriority`], so
+    /// that the images in view aren't held up by those scrolled out of it. Images read from
+    /// the disk cache don't wait. Defaults to 6. Lowering the limit doesn't interrupt
+    /// downloads that already started.
+    pub fn set_image_fetch_concurrency(&mut self, concurrency: usize) {
+        self.image_fetch_queue.set_concurrency(concurrency);
+    }
+
+    pub(crate) fn image_fetch_queue(&self) -> ImageLoadQueue {
+        self.image_fetch_queue.clone()
+    }
+
+    /// Sets how many images can be decoded or rasterized at once. Further images wait for
+    /// one of them to finish, rather than all competing for the CPU and memory at once, and
+    /// are decoded in order of their [`crate::LoadPriority`]. Defaults to the number of
+    /// CPUs. Downloads are limited separately, by [`Self::set_image_fetch_concurrency`], so
+    /// images keep downloading while others wait to be decoded. Lowering the limit doesn't
+    /// interrupt decodes that already started.
+    pub fn set_image_decode_concurrency(&mut self, concurrency: usize) {
+        self.image_decode_queue.set_concurrency(concurrency);
+    }
+
+    pub(crate) fn image_decode_queue(&self) -> ImageLoadQueue {
+        self.image_decode_queue.clone()
+    }
+
+    /// Limits how many pixels a raster image can have, so that a small file that
+    /// decompresses to a huge image can't exhaust memory. Each image's dimensions are read
+    /// from its header before it's decoded, and larger images fail to load with
+    /// [`crate::ImageCacheError::TooLarge`]. The limit applies to the frames of an
+    /// animation between them, which are counted as they're decoded. Defaults to 128
+    /// megapixels, which take 512 MiB once decoded. Images that have already loaded aren't
+    /// affected.
+    pub fn set_image_max_pixels(&mut self, max_pixels: u64) {
+        self.image_max_pixels = max_pixels;
+    }
+
+    pub(crate) fn image_max_pixels(&self) -> u64 {
+        self.image_max_pixels
+    }
+
+    /// Limits the width and height at which SVG images are rasterized, so that an SVG
+    /// declaring a huge size can't exhaust memory. Larger SVGs are rendered at this size,
+    /// preserving their aspect ratio, and scaled up when painted. Defaults to 4096
+    /// device pixels.
+    pub fn set_max_svg_rasterization_size(&mut self, max_size: DevicePixels) {
+        self.svg_renderer.set_max_rasterization_size(max_size);
+    }
+
+    /// Sets what happens when an SVG image can't be rasterized because of its size, such as
+    /// an SVG declaring a size of zero. Defaults to [`SvgFailurePolicy::Error`]. Images that
+    /// have already failed to load aren't loaded again.
+    pub fn set_svg_failure_policy(&mut self, failure_policy: SvgFailurePolicy) {
+        self.svg_renderer.set_failure_policy(failure_policy);
+    }
+
+    /// Sets the options used to parse SVGs, both for `svg` elements and for images.
+    /// Individual images can override them with [`crate::Img::svg_options`].
+    pub fn set_svg_options(&mut self, options: SvgOptions) {
+        self.svg_renderer.set_options(options);
+    }
+
+    /// Sets which fonts are loaded to render text in SVGs, replacing any set with
+    /// [`App::with_svg_fonts`]. Apps whose SVGs have no text can use
+    /// [`SvgFontLoading::None`] to avoid loading the system fonts, which can be slow.
+    /// SVGs that have already been rasterized keep their previous text until they're
+    /// rendered again.
+    pub fn set_svg_font_loading(&mut self, font_loading: SvgFontLoading) {
+        self.svg_renderer.set_font_loading(font_loading);
+    }
+
+    /// Renders the SVG asset at `path` in full color at the given size, composited over
+    /// `background` to flatten its transparency, or left transparent if it's `None`.
+    /// Unlike the `svg` element, which fills the SVG's shape with a single color, this keeps
+    /// the colors the SVG was authored with.
+    pub fn render_svg_rgba(
+        &self,
+        path: impl Into<SharedString>,
+        size: Size<DevicePixels>,
+        background: Option<Hsla>,
+    ) -> Result<ImageData> {
+        let params = RenderSvgParams {
+            path: path.into(),
+            logical_size: size.map(|pixels| Pixels(pixels.0 as f32)),
+            size,
+        };
+        self.svg_renderer.render_rgba(&params, background)
+    }
+
+    /// Loads the fonts used to render text in SVGs on a background thread, so that the
+    /// first SVG with text doesn't stall a frame while they load. Call this at startup,
+    /// after choosing the fonts with [`AppContext::set_svg_font_loading`]. SVGs rendered
+    /// while the fonts are loading wait for them rather than loading them again, and every
+    /// later render uses the loaded fonts.
+    pub fn warm_svg_fontdb(&self) -> Task<()> {
+        let svg_renderer = self.svg_renderer();
+        self.background_executor()
+            .spawn(async move { svg_renderer.warm_fontdb() })
+    }
+
+    /// Reloads the system fonts used to render text in SVGs, for example after the user
+    /// installs a new font. SVGs that have already been rasterized, whether cached as
+    /// images or in a window's sprite atlas, keep their previous text until they're
+    /// rendered again. This has no effect on apps using [`App::with_svg_fonts`].
+    pub fn reload_svg_fontdb(&mut self) {
+        reload_svg_fontdb();
+    }
+
+    /// Sets whether the user prefers reduced motion. While it's set, animated images are
+    /// paused on their first frame, unless they opt out with
+    /// [`crate::Img::respect_reduced_motion`].
+    pub fn set_reduce_motion(&mut self, reduce_motion: bool) {
+        if self.reduce_motion != reduce_motion {
+            self.reduce_motion = reduce_motion;
+            self.refresh();
+        }
+    }
+
+    /// Returns whether the user prefers reduced motion.
+    pub fn reduce_motion(&self) -> bool {
+        self.reduce_motion
+    }
+
     pub(crate) fn push_effect(&mut self, effect: Effect) {
         match &effect {
             Effect::Notify { emitter } => {
@@ -1,21 +1,85 @@
-use crate::{SharedUri, WindowContext};
-use collections::FxHashMap;
+use crate::{ImageReader, SharedString, SharedUri, Task, UriOptions, WindowContext};
+use collections::{BTreeMap, FxHashMap};
 use futures::Future;
 use parking_lot::Mutex;
 use std::any::TypeId;
 use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{any::Any, path::PathBuf};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub(crate) enum UriOrPath {
-    Uri(SharedUri),
+    Uri {
+        uri: SharedUri,
+        options: UriOptions,
+    },
     Path(Arc<PathBuf>),
+    Asset(SharedString),
+    Bytes(Arc<[u8]>),
+    Reader(ImageReader),
+    Scoped {
+        scope: SharedString,
+        source: Box<UriOrPath>,
+    },
+}
+
+impl UriOrPath {
+    /// A short description of where the image comes from, for reporting.
+    pub(crate) fn description(&self) -> SharedString {
+        match self {
+            UriOrPath::Uri { uri, .. } => uri.to_string().into(),
+            UriOrPath::Path(path) => path.to_string_lossy().into_owned().into(),
+            UriOrPath::Asset(path) => path.clone(),
+            UriOrPath::Bytes(bytes) => format!("{} bytes", bytes.len()).into(),
+            UriOrPath::Reader(reader) => reader.id.clone(),
+            UriOrPath::Scoped { source, .. } => source.description(),
+        }
+    }
+
+    /// Cache this source within `scope`, replacing any scope it already has.
+    pub(crate) fn scoped(self, scope: SharedString) -> Self {
+        let source = match self {
+            UriOrPath::Scoped { source, .. } => source,
+            source => Box::new(source),
+        };
+        UriOrPath::Scoped { scope, source }
+    }
+
+    /// The source to read the image from, without its scope.
+    pub(crate) fn unscoped(&self) -> &UriOrPath {
+        match self {
+            UriOrPath::Scoped { source, .. } => source,
+            source => source,
+        }
+    }
+
+    /// The scope this source is cached in, if any.
+    pub(crate) fn scope(&self) -> Option<SharedString> {
+        match self {
+            UriOrPath::Scoped { scope, .. } => Some(scope.clone()),
+            _ => None,
+        }
+    }
+
+    /// The URI the image is fetched from, if it isn't read from a file, asset or bytes.
+    pub(crate) fn uri(&self) -> Option<SharedUri> {
+        match self {
+            UriOrPath::Uri { uri, .. } => Some(uri.clone()),
+            UriOrPath::Scoped { source, .. } => source.uri(),
+            _ => None,
+        }
+    }
 }
 
 impl From<SharedUri> for UriOrPath {
     fn from(value: SharedUri) -> Self {
-        Self::Uri(value)
+        Self::Uri {
+            uri: value,
+            options: UriOptions::default(),
+        }
     }
 }
 
@@ -38,6 +102,70 @@ pub trait Asset {
         source: Self::Source,
         cx: &mut WindowContext,
     ) -> impl Future<Output = Self::Output> + Send + 'static;
+
+    /// An estimate of the memory occupied by a loaded asset, used to enforce the
+    /// [`AssetCache`]'s memory budget. Assets with a size of zero are never evicted.
+    fn size_in_bytes(_output: &Self::Output) -> usize {
+        0
+    }
+
+    /// The scope a source's asset is cached in, if any, so it can be removed along with the
+    /// rest of its scope by [`AssetCache::clear_scope`].
+    fn scope(_source: &Self::Source) -> Option<SharedString> {
+        None
+    }
+
+    /// The URI a source's asset is fetched from, if any, so it can be removed along with
+    /// other assets from the same place by [`AssetCache::retain_uris`].
+    fn uri(_source: &Self::Source) -> Option<SharedUri> {
+        None
+    }
+}
+
+/// An asset that's still being loaded by [`WindowContext::use_asset`].
+pub(crate) struct LoadingAsset {
+    /// The `Shared<Task<A::Output>>` producing the asset.
+    pub(crate) task: Box<dyn Any>,
+    /// Redraws the window that requested the asset once it has loaded.
+    pub(crate) notify: Option<Task<()>>,
+    guards: Weak<()>,
+    pinned: bool,
+}
+
+/// Keeps an asset that's still loading from being cancelled. See
+/// [`WindowContext::with_asset_load_guards`].
+#[derive(Clone)]
+pub struct AssetLoadGuard(#[allow(unused)] Rc<()>);
+
+impl LoadingAsset {
+    pub(crate) fn new(task: Box<dyn Any>) -> Self {
+        Self {
+            task,
+            notify: None,
+            guards: Weak::new(),
+            pinned: false,
+        }
+    }
+
+    /// Get a guard that keeps this load alive, creating the first one if necessary.
+    pub(crate) fn guard(&mut self) -> AssetLoadGuard {
+        let guard = self.guards.upgrade().unwrap_or_else(|| {
+            let guard = Rc::new(());
+            self.guards = Rc::downgrade(&guard);
+            guard
+        });
+        AssetLoadGuard(guard)
+    }
+
+    /// Keep this load alive until it completes, regardless of guards.
+    pub(crate) fn pin(&mut self) {
+        self.pinned = true;
+    }
+
+    /// Whether everything that requested this asset has stopped waiting for it.
+    pub(crate) fn is_abandoned(&self) -> bool {
+        !self.pinned && self.guards.strong_count() == 0
+    }
 }
 
 /// Use a quick, non-cryptographically secure hash function to get an identifier from data
@@ -48,40 +176,378 @@ pub fn hash<T: Hash>(data: &T) -> u64 {
 }
 
 /// A cache for assets.
+///
+/// The cache can be given a memory budget, in which case the least recently used assets are
+/// evicted once the estimated size of all cached assets exceeds it. Only assets that have
+/// finished loading are stored here, so eviction never affects a load that's still in flight.
 #[derive(Clone)]
 pub struct AssetCache {
-    assets: Arc<Mutex<FxHashMap<(TypeId, u64), Box<dyn Any + Send>>>>,
+    state: Arc<Mutex<AssetCacheState>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+/// A snapshot of how much an [`AssetCache`] holds and how often it's been useful.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of assets in the cache.
+    pub entry_count: usize,
+    /// The estimated memory occupied by the cached assets, as reported by
+    /// [`Asset::size_in_bytes`].
+    pub approx_bytes: usize,
+    /// The number of times [`AssetCache::get`] found the asset it was asked for.
+    pub hits: u64,
+    /// The number of times [`AssetCache::get`] didn't find the asset it was asked for.
+    pub misses: u64,
+}
+
+type AssetKey = (TypeId, u64);
+
+#[derive(Default)]
+struct AssetCacheState {
+    assets: FxHashMap<AssetKey, AssetCacheEntry>,
+    /// The keys of the assets that can be evicted, ordered from least to most recently used
+    /// by when they were last accessed.
+    evictable: BTreeMap<u64, AssetKey>,
+    memory_budget: Option<usize>,
+    total_bytes: usize,
+    access_count: u64,
+}
+
+struct AssetCacheEntry {
+    source: Box<dyn Any + Send>,
+    output: Box<dyn Any + Send>,
+    size_in_bytes: usize,
+    last_access: u64,
+    inserted_at: Instant,
+    scope: Option<SharedString>,
+    uri: Option<SharedUri>,
 }
 
 impl AssetCache {
     pub(crate) fn new() -> Self {
         Self {
-            assets: Default::default(),
+            state: Default::default(),
+            hits: Default::default(),
+            misses: Default::default(),
         }
     }
 
     /// Get the asset from the cache, if it exists.
     pub fn get<A: Asset + 'static>(&self, source: &A::Source) -> Option<A::Output> {
-        self.assets
+        let mut state = self.state.lock();
+        let Some(entry) = state.touch((TypeId::of::<A>(), hash(&source))) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        entry.output.downcast_ref::<A::Output>().cloned()
+    }
+
+    /// Whether the asset is in the cache, without counting as a lookup in [`Self::stats`] or
+    /// as a use of the asset.
+    pub(crate) fn contains<A: Asset + 'static>(&self, source: &A::Source) -> bool {
+        self.state
             .lock()
+            .assets
+            .contains_key(&(TypeId::of::<A>(), hash(&source)))
+    }
+
+    /// When the asset was inserted into the cache, if it's there, without counting as a
+    /// lookup in [`Self::stats`] or as a use of the asset.
+    pub(crate) fn inserted_at<A: Asset + 'static>(&self, source: &A::Source) -> Option<Instant> {
+        self.state
+            .lock()
+            .assets
             .get(&(TypeId::of::<A>(), hash(&source)))
-            .and_then(|task| task.downcast_ref::<A::Output>())
-            .cloned()
+            .map(|entry| entry.inserted_at)
+    }
+
+    /// Get the number of cached assets, their estimated size in memory, and how many lookups
+    /// have found or missed an asset since the cache was created.
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock();
+        CacheStats {
+            entry_count: state.assets.len(),
+            approx_bytes: state.total_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
     }
 
     /// Insert the asset into the cache.
-    pub fn insert<A: Asset + 'static>(&mut self, source: A::Source, output: A::Output) {
-        self.assets
-            .lock()
-            .insert((TypeId::of::<A>(), hash(&source)), Box::new(output));
+    pub fn insert<A: Asset + 'static>(&self, source: A::Source, output: A::Output) {
+        let size_in_bytes = A::size_in_bytes(&output);
+        let scope = A::scope(&source);
+        let uri = A::uri(&source);
+        let key = (TypeId::of::<A>(), hash(&source));
+        let mut state = self.state.lock();
+        state.remove(&key);
+        state.access_count += 1;
+        let entry = AssetCacheEntry {
+            source: Box::new(source),
+            output: Box::new(output),
+            size_in_bytes,
+            last_access: state.access_count,
+            inserted_at: Instant::now(),
+            scope,
+            uri,
+        };
+        if size_in_bytes > 0 {
+            state.evictable.insert(entry.last_access, key);
+        }
+        state.total_bytes += size_in_bytes;
+        state.assets.insert(key, entry);
+        state.evict_to_budget();
     }
 
     /// Remove an entry from the asset cache
-    pub fn remove<A: Asset + 'static>(&mut self, source: &A::Source) -> Option<A::Output> {
-        self.assets
+    pub fn remove<A: Asset + 'static>(&self, source: &A::Source) -> Option<A::Output> {
+        let entry = self
+            .state
             .lock()
-            .remove(&(TypeId::of::<A>(), hash(&source)))
-            .and_then(|any| any.downcast::<A::Output>().ok())
+            .remove(&(TypeId::of::<A>(), hash(&source)))?;
+        entry
+            .output
+            .downcast::<A::Output>()
+            .ok()
             .map(|boxed| *boxed)
     }
+
+    /// Remove all entries from the asset cache. Assets that are still loading are unaffected,
+    /// and will be cached once they finish.
+    pub fn clear(&self) {
+        let mut state = self.state.lock();
+        state.assets.clear();
+        state.evictable.clear();
+        state.total_bytes = 0;
+    }
+
+    /// Remove every entry cached within the given scope, such as images loaded with
+    /// [`crate::ImageSource::scoped`]. Assets in other scopes, or in none, are unaffected.
+    pub fn clear_scope(&self, scope: &str) {
+        self.state.lock().retain(|_, entry| {
+            entry
+                .scope
+                .as_ref()
+                .map_or(true, |entry_scope| *entry_scope != *scope)
+        });
+    }
+
+    /// Keep only the assets of type `A` whose source satisfies `f`, removing the rest. Assets
+    /// of other types, and assets that are still loading, are unaffected.
+    pub fn retain<A: Asset + 'static>(&self, f: impl Fn(&A::Source) -> bool) {
+        let type_id = TypeId::of::<A>();
+        self.state.lock().retain(|(entry_type_id, _), entry| {
+            *entry_type_id != type_id || entry.source.downcast_ref::<A::Source>().map_or(true, &f)
+        });
+    }
+
+    /// Keep only the assets fetched from URIs that satisfy `f`, of any type, such as
+    /// images loaded from an API host that are no longer authorized once the user logs out.
+    /// Assets that aren't fetched from a URI, like those loaded from files or the app's
+    /// [`crate::AssetSource`], are always kept.
+    pub fn retain_uris(&self, f: impl Fn(&SharedUri) -> bool) {
+        self.state
+            .lock()
+            .retain(|_, entry| entry.uri.as_ref().map_or(true, &f));
+    }
+
+    /// Set the approximate number of bytes the cached assets may occupy before the least
+    /// recently used ones are evicted, or `None` to let the cache grow without bound.
+    pub fn set_memory_budget(&self, budget: Option<usize>) {
+        let mut state = self.state.lock();
+        state.memory_budget = budget;
+        state.evict_to_budget();
+    }
+}
+
+impl AssetCacheState {
+    /// Get the entry with the given key, marking it as the most recently used.
+    fn touch(&mut self, key: AssetKey) -> Option<&AssetCacheEntry> {
+        self.access_count += 1;
+        let access_count = self.access_count;
+        let entry = self.assets.get_mut(&key)?;
+        if entry.size_in_bytes > 0 {
+            self.evictable.remove(&entry.last_access);
+            self.evictable.insert(access_count, key);
+        }
+        entry.last_access = access_count;
+        Some(entry)
+    }
+
+    fn remove(&mut self, key: &AssetKey) -> Option<AssetCacheEntry> {
+        let entry = self.assets.remove(key)?;
+        self.evictable.remove(&entry.last_access);
+        self.total_bytes -= entry.size_in_bytes;
+        Some(entry)
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(&AssetKey, &AssetCacheEntry) -> bool) {
+        let mut removed_bytes = 0;
+        let evictable = &mut self.evictable;
+        self.assets.retain(|key, entry| {
+            let keep = f(key, entry);
+            if !keep {
+                removed_bytes += entry.size_in_bytes;
+                evictable.remove(&entry.last_access);
+            }
+            keep
+        });
+        self.total_bytes -= removed_bytes;
+    }
+
+    fn evict_to_budget(&mut self) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+
+        while self.total_bytes > budget {
+            let Some((_, key)) = self.evictable.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.assets.remove(&key) {
+                self.total_bytes -= entry.size_in_bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum TestAsset {}
+
+    impl Asset for TestAsset {
+        type Source = u64;
+        type Output = usize;
+
+        fn load(
+            _source: Self::Source,
+            _cx: &mut WindowContext,
+        ) -> impl Future<Output = Self::Output> + Send + 'static {
+            async { unreachable!() }
+        }
+
+        fn size_in_bytes(output: &Self::Output) -> usize {
+            *output
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_over_budget() {
+        let cache = AssetCache::new();
+        cache.set_memory_budget(Some(100));
+        cache.insert::<TestAsset>(1, 40);
+        cache.insert::<TestAsset>(2, 40);
+        assert_eq!(cache.get::<TestAsset>(&1), Some(40));
+
+        cache.insert::<TestAsset>(3, 40);
+        assert_eq!(cache.get::<TestAsset>(&1), Some(40));
+        assert_eq!(cache.get::<TestAsset>(&2), None);
+        assert_eq!(cache.get::<TestAsset>(&3), Some(40));
+
+        cache.set_memory_budget(Some(40));
+        assert_eq!(cache.get::<TestAsset>(&1), None);
+        assert_eq!(cache.get::<TestAsset>(&3), Some(40));
+    }
+
+    #[test]
+    fn test_stats() {
+        let cache = AssetCache::new();
+        cache.insert::<TestAsset>(1, 10);
+        cache.insert::<TestAsset>(2, 20);
+        cache.get::<TestAsset>(&1);
+        cache.get::<TestAsset>(&1);
+        cache.get::<TestAsset>(&3);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                entry_count: 2,
+                approx_bytes: 30,
+                hits: 2,
+                misses: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_loading_asset_is_abandoned_once_unguarded() {
+        let mut asset = LoadingAsset::new(Box::new(()));
+        let guard = asset.guard();
+        let second_guard = asset.guard();
+        drop(guard);
+        assert!(!asset.is_abandoned());
+        drop(second_guard);
+        assert!(asset.is_abandoned());
+
+        // A new requester can pick the load back up before it's cancelled.
+        let guard = asset.guard();
+        assert!(!asset.is_abandoned());
+
+        asset.pin();
+        drop(guard);
+        assert!(!asset.is_abandoned());
+    }
+
+    #[test]
+    fn test_remove_and_clear() {
+        let cache = AssetCache::new();
+        cache.insert::<TestAsset>(1, 10);
+        cache.insert::<TestAsset>(2, 20);
+
+        assert_eq!(cache.remove::<TestAsset>(&1), Some(10));
+        assert_eq!(cache.remove::<TestAsset>(&1), None);
+
+        cache.clear();
+        assert_eq!(cache.get::<TestAsset>(&2), None);
+    }
+
+    enum UriAsset {}
+
+    impl Asset for UriAsset {
+        type Source = SharedUri;
+        type Output = usize;
+
+        fn load(
+            _source: Self::Source,
+            _cx: &mut WindowContext,
+        ) -> impl Future<Output = Self::Output> + Send + 'static {
+            async { unreachable!() }
+        }
+
+        fn size_in_bytes(output: &Self::Output) -> usize {
+            *output
+        }
+
+        fn uri(source: &Self::Source) -> Option<SharedUri> {
+            Some(source.clone())
+        }
+    }
+
+    #[test]
+    fn test_retain() {
+        let cache = AssetCache::new();
+        for source in 1..=4 {
+            cache.insert::<TestAsset>(source, 10);
+        }
+        let api = SharedUri::from("https://api.example.com/avatar.png");
+        let cdn = SharedUri::from("https://cdn.example.com/logo.png");
+        cache.insert::<UriAsset>(api.clone(), 5);
+        cache.insert::<UriAsset>(cdn.clone(), 5);
+
+        cache.retain::<TestAsset>(|source| source % 2 == 0);
+        assert_eq!(cache.get::<TestAsset>(&1), None);
+        assert_eq!(cache.get::<TestAsset>(&2), Some(10));
+        assert_eq!(cache.stats().entry_count, 4);
+        assert_eq!(cache.stats().approx_bytes, 30);
+
+        cache.retain_uris(|uri| !uri.starts_with("https://api.example.com/"));
+        assert_eq!(cache.get::<UriAsset>(&api), None);
+        assert_eq!(cache.get::<UriAsset>(&cdn), Some(5));
+        assert_eq!(cache.get::<TestAsset>(&4), Some(10));
+        assert_eq!(cache.stats().approx_bytes, 25);
+    }
 }
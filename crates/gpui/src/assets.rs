@@ -1,11 +1,17 @@
 use crate::{size, DevicePixels, Result, SharedString, Size};
 use anyhow::anyhow;
-use image::{Bgra, ImageBuffer};
+use collections::FxHasher;
+use image::{Bgra, ImageBuffer, ImageFormat};
+use smallvec::SmallVec;
 use std::{
     borrow::Cow,
     fmt,
-    hash::Hash,
-    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc,
+    },
+    time::Duration,
 };
 
 /// A source of assets for this app to use.
@@ -37,43 +43,376 @@ pub struct ImageId(usize);
 #[derive(PartialEq, Eq, Hash, Clone)]
 pub(crate) struct RenderImageParams {
     pub(crate) image_id: ImageId,
+    pub(crate) frame_index: usize,
 }
 
-/// A cached and processed image.
+/// How many times an animated image plays before it stops on its last frame.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LoopCount {
+    /// The animation repeats forever.
+    #[default]
+    Infinite,
+    /// The animation plays the given number of times.
+    Finite(u32),
+}
+
+/// The color space an image declares its pixels are in. Images are always displayed as
+/// if they were sRGB, without converting their colors.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ColorSpace {
+    /// The image doesn't declare a color space, so it's assumed to be sRGB.
+    #[default]
+    Unspecified,
+    /// sRGB, declared with a PNG `sRGB` chunk or an ICC profile, or implied by the format,
+    /// as with SVG.
+    Srgb,
+    /// Display P3, declared with an ICC profile.
+    DisplayP3,
+    /// Some other color space, described by the image's ICC profile.
+    Other,
+}
+
+struct ImageFrame {
+    buffer: ImageBuffer<Bgra<u8>, Vec<u8>>,
+    delay: Duration,
+}
+
+/// A cached and processed image, made up of one or more frames.
 pub struct ImageData {
     /// The ID associated with this image
     pub id: ImageId,
-    data: ImageBuffer<Bgra<u8>, Vec<u8>>,
+    frames: SmallVec<[ImageFrame; 1]>,
+    loop_count: LoopCount,
+    partial: bool,
+    color_space: ColorSpace,
+    icc_profile: Option<Arc<[u8]>>,
+    opaque: bool,
+    natural_size: Option<Size<DevicePixels>>,
+    source_format: Option<ImageFormat>,
+    embedded_sizes: Option<Arc<[Size<DevicePixels>]>>,
 }
 
 impl ImageData {
-    /// Create a new image from the given data.
+    /// Create a new image from the given data. Pixels are expected to use straight alpha,
+    /// meaning their color channels aren't premultiplied by their alpha.
     pub fn new(data: ImageBuffer<Bgra<u8>, Vec<u8>>) -> Self {
+        Self::from_frames([(data, Duration::ZERO)], LoopCount::Infinite)
+    }
+
+    /// Create a new animated image from the given frames, each paired with how long it
+    /// should be displayed for. All frames are expected to have the same dimensions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no frames are given.
+    pub fn from_frames(
+        frames: impl IntoIterator<Item = (ImageBuffer<Bgra<u8>, Vec<u8>>, Duration)>,
+        loop_count: LoopCount,
+    ) -> Self {
         static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
 
+        let frames = frames
+            .into_iter()
+            .map(|(buffer, delay)| ImageFrame { buffer, delay })
+            .collect::<SmallVec<_>>();
+        assert!(!frames.is_empty(), "an image must have at least one frame");
+        let opaque = frames
+            .iter()
+            .all(|frame| frame.buffer.pixels().all(|pixel| pixel.0[3] == u8::MAX));
+
         Self {
             id: ImageId(NEXT_ID.fetch_add(1, SeqCst)),
-            data,
+            frames,
+            loop_count,
+            partial: false,
+            color_space: ColorSpace::Unspecified,
+            icc_profile: None,
+            opaque,
+            natural_size: None,
+            source_format: None,
+            embedded_sizes: None,
         }
     }
 
-    /// Convert this image into a byte slice.
+    /// Mark this image as only partially decoded. See [`Self::is_partial`].
+    pub(crate) fn with_partial(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    /// Record the color space the image was encoded in. See [`Self::color_space`].
+    pub(crate) fn with_color_space(
+        mut self,
+        color_space: ColorSpace,
+        icc_profile: Option<Arc<[u8]>>,
+    ) -> Self {
+        self.color_space = color_space;
+        self.icc_profile = icc_profile;
+        self
+    }
+
+    /// Record the size of the image this one was made from, such as before it was scaled
+    /// down, without changing its pixels. See [`Self::natural_size`].
+    pub fn with_natural_size(mut self, natural_size: Size<DevicePixels>) -> Self {
+        self.natural_size = Some(natural_size);
+        self
+    }
+
+    /// Record the format this image was decoded from, without changing its pixels. See
+    /// [`Self::source_format`].
+    pub fn with_source_format(mut self, source_format: ImageFormat) -> Self {
+        self.source_format = Some(source_format);
+        self
+    }
+
+    /// Record the sizes of every image embedded in the file this one was decoded from,
+    /// such as the resolutions of an ICO. See [`Self::embedded_sizes`].
+    pub fn with_embedded_sizes(mut self, embedded_sizes: Vec<Size<DevicePixels>>) -> Self {
+        self.embedded_sizes = Some(embedded_sizes.into());
+        self
+    }
+
+    /// Keep the natural size, source format and embedded sizes of another image, such as
+    /// the one this image was made from.
+    pub(crate) fn with_metadata_of(mut self, other: &ImageData) -> Self {
+        self.natural_size = other.natural_size;
+        self.source_format = other.source_format;
+        self.embedded_sizes = other.embedded_sizes.clone();
+        self
+    }
+
+    /// Create a new image by transforming each frame of this one, keeping the frame
+    /// delays and loop count.
+    pub(crate) fn map_frames(
+        self,
+        mut f: impl FnMut(ImageBuffer<Bgra<u8>, Vec<u8>>) -> ImageBuffer<Bgra<u8>, Vec<u8>>,
+    ) -> Self {
+        let Self {
+            frames,
+            loop_count,
+            partial,
+            color_space,
+            icc_profile,
+            natural_size,
+            source_format,
+            embedded_sizes,
+            ..
+        } = self;
+        let mut data = Self::from_frames(
+            frames
+                .into_iter()
+                .map(|frame| (f(frame.buffer), frame.delay)),
+            loop_count,
+        )
+        .with_partial(partial)
+        .with_color_space(color_space, icc_profile);
+        data.natural_size = natural_size;
+        data.source_format = source_format;
+        data.embedded_sizes = embedded_sizes;
+        data
+    }
+
+    /// Copy one frame of this image into a still image. Indices past the last frame copy
+    /// the last frame.
+    pub(crate) fn single_frame(&self, frame_index: usize) -> Self {
+        let frame = &self.frames[frame_index.min(self.frames.len() - 1)];
+        Self::new(frame.buffer.clone())
+            .with_partial(self.partial)
+            .with_color_space(self.color_space, self.icc_profile.clone())
+            .with_metadata_of(self)
+    }
+
+    /// A hash of this image's frames, which is the same for images with identical pixels
+    /// regardless of their id.
+    pub(crate) fn content_hash(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        for frame in &self.frames {
+            frame.buffer.dimensions().hash(&mut hasher);
+            frame.buffer.as_raw().hash(&mut hasher);
+            frame.delay.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Whether this image is displayed identically to the other one, ignoring their ids.
+    pub(crate) fn has_same_content(&self, other: &Self) -> bool {
+        self.frames.len() == other.frames.len()
+            && self.frames.iter().zip(&other.frames).all(|(a, b)| {
+                a.delay == b.delay
+                    && a.buffer.dimensions() == b.buffer.dimensions()
+                    && a.buffer.as_raw() == b.buffer.as_raw()
+            })
+            && self.loop_count == other.loop_count
+            && self.partial == other.partial
+            && self.color_space == other.color_space
+            && self.icc_profile == other.icc_profile
+            && self.natural_size == other.natural_size
+            && self.source_format == other.source_format
+            && self.embedded_sizes == other.embedded_sizes
+    }
+
+    /// Convert the first frame of this image into a byte slice.
     pub fn as_bytes(&self) -> &[u8] {
-        &self.data
+        &self.frames[0].buffer
+    }
+
+    /// Convert the given frame of this image into a byte slice.
+    pub fn frame_as_bytes(&self, frame_index: usize) -> Option<&[u8]> {
+        self.frames
+            .get(frame_index)
+            .map(|frame| frame.buffer.as_raw().as_slice())
+    }
+
+    /// Get the number of bytes the pixels of every frame of this image occupy in memory.
+    pub fn size_in_bytes(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| frame.buffer.as_raw().len())
+            .sum()
     }
 
     /// Get the size of this image, in pixels
     pub fn size(&self) -> Size<DevicePixels> {
-        let (width, height) = self.data.dimensions();
+        let (width, height) = self.frames[0].buffer.dimensions();
         size(width.into(), height.into())
     }
+
+    /// Get the number of frames in this image.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether this image has more than one frame.
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// Get how long the given frame should be displayed for.
+    pub fn frame_delay(&self, frame_index: usize) -> Duration {
+        self.frames
+            .get(frame_index)
+            .map_or(Duration::ZERO, |frame| frame.delay)
+    }
+
+    /// Get how many times this image's animation plays.
+    pub fn loop_count(&self) -> LoopCount {
+        self.loop_count
+    }
+
+    /// Whether the image's encoded data was truncated, so only the part that could be
+    /// decoded is shown and the rest is left blank. Fetching it again may yield the
+    /// complete image.
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+
+    /// Whether every pixel of every frame of this image is fully opaque, so nothing behind
+    /// it shows through, such as for deciding whether to draw a backdrop behind it. Images
+    /// are checked pixel by pixel once they're loaded, so opaque images in formats with an
+    /// alpha channel are reported as opaque too.
+    pub fn is_opaque(&self) -> bool {
+        self.opaque
+    }
+
+    /// The color space the image declares its pixels are in.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// The ICC profile embedded in the image, if any, for converting its colors to
+    /// another color space.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    /// Get the size of the image this one was made from, which may be larger than
+    /// [`Self::size`] if it was scaled down when it was decoded, such as with
+    /// [`crate::Img::max_decode_size`]. For SVGs, this is the size the document declares,
+    /// in logical pixels. `None` for images created without one.
+    pub fn natural_size(&self) -> Option<Size<DevicePixels>> {
+        self.natural_size
+    }
+
+    /// Get the format this image was decoded from. `None` for SVGs and for images created
+    /// without one.
+    pub fn source_format(&self) -> Option<ImageFormat> {
+        self.source_format
+    }
+
+    /// Get the sizes of every image embedded in the file this one was decoded from, such as
+    /// each resolution of an ICO, of which this is one. Empty for formats that hold a
+    /// single image.
+    pub fn embedded_sizes(&self) -> &[Size<DevicePixels>] {
+        self.embedded_sizes.as_deref().unwrap_or_default()
+    }
+
+    /// Compare the pixels of this image with another, frame by frame. Images whose sizes or
+    /// frame counts differ aren't compared pixel by pixel, and only report that their
+    /// dimensions don't match. Useful for comparing rendered images against golden images
+    /// in tests.
+    pub fn diff(&self, other: &ImageData) -> ImageDiff {
+        if self.size() != other.size() || self.frame_count() != other.frame_count() {
+            return ImageDiff {
+                max_channel_delta: 0,
+                mismatched_pixels: 0,
+                dimensions_match: false,
+            };
+        }
+
+        let mut diff = ImageDiff {
+            max_channel_delta: 0,
+            mismatched_pixels: 0,
+            dimensions_match: true,
+        };
+        for (a, b) in self.frames.iter().zip(&other.frames) {
+            for (a, b) in a.buffer.pixels().zip(b.buffer.pixels()) {
+                let delta =
+                    a.0.iter()
+                        .zip(b.0)
+                        .map(|(a, b)| a.abs_diff(b))
+                        .max()
+                        .unwrap_or(0);
+                if delta > 0 {
+                    diff.mismatched_pixels += 1;
+                    diff.max_channel_delta = diff.max_channel_delta.max(delta);
+                }
+            }
+        }
+        diff
+    }
+}
+
+/// How two images differ, as reported by [`ImageData::diff`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImageDiff {
+    /// The largest difference between the same channel of any two corresponding pixels.
+    pub max_channel_delta: u8,
+    /// The number of pixels, across all frames, that differ in any channel.
+    pub mismatched_pixels: usize,
+    /// Whether the images have the same size and number of frames. When they don't, no
+    /// pixels are compared.
+    pub dimensions_match: bool,
+}
+
+impl ImageDiff {
+    /// Whether the images have the same dimensions, and no channel of any pixel differs by
+    /// more than `tolerance`.
+    pub fn is_within(&self, tolerance: u8) -> bool {
+        self.dimensions_match && self.max_channel_delta <= tolerance
+    }
 }
 
 impl fmt::Debug for ImageData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ImageData")
             .field("id", &self.id)
-            .field("size", &self.data.dimensions())
+            .field("size", &self.frames[0].buffer.dimensions())
+            .field("frame_count", &self.frames.len())
+            .field("partial", &self.partial)
+            .field("color_space", &self.color_space)
+            .field("natural_size", &self.natural_size)
+            .field("source_format", &self.source_format)
+            .field("embedded_sizes", &self.embedded_sizes)
             .finish()
     }
 }
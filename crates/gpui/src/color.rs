@@ -1,6 +1,9 @@
 use anyhow::bail;
 use serde::de::{self, Deserialize, Deserializer, Visitor};
-use std::fmt;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
 
 /// Convert an RGB hex color code number to a color type
 pub fn rgb(hex: u32) -> Rgba {
@@ -217,6 +220,15 @@ impl Ord for Hsla {
 
 impl Eq for Hsla {}
 
+impl Hash for Hsla {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.h.to_bits().hash(state);
+        self.s.to_bits().hash(state);
+        self.l.to_bits().hash(state);
+        self.a.to_bits().hash(state);
+    }
+}
+
 /// Construct an [`Hsla`] object from plain values
 pub fn hsla(h: f32, s: f32, l: f32, a: f32) -> Hsla {
     Hsla {
@@ -1277,6 +1277,7 @@ pub struct Interactivity {
     pub(crate) hover_listener: Option<Box<dyn Fn(&bool, &mut WindowContext)>>,
     pub(crate) tooltip_builder: Option<TooltipBuilder>,
     pub(crate) occlude_mouse: bool,
+    pub(crate) accessibility_label: Option<SharedString>,
 
     #[cfg(debug_assertions)]
     pub(crate) location: Option<core::panic::Location<'static>>,
@@ -1385,7 +1386,11 @@ impl Interactivity {
                 cx.with_text_style(style.text_style().cloned(), |cx| {
                     cx.with_content_mask(style.overflow_mask(bounds, cx.rem_size()), |cx| {
                         let hitbox = if self.should_insert_hitbox(&style) {
-                            Some(cx.insert_hitbox(bounds, self.occlude_mouse))
+                            Some(cx.insert_labeled_hitbox(
+                                bounds,
+                                self.occlude_mouse,
+                                self.accessibility_label.clone(),
+                            ))
                         } else {
                             None
                         };
@@ -1415,6 +1420,7 @@ impl Interactivity {
             || self.drag_listener.is_some()
             || !self.drop_listeners.is_empty()
             || self.tooltip_builder.is_some()
+            || self.accessibility_label.is_some()
     }
 
     fn clamp_scroll_position(
@@ -1,17 +1,38 @@
-use std::fs;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
 
 use crate::{
-    point, px, size, AbsoluteLength, Asset, Bounds, DefiniteLength, DevicePixels, Element,
-    ElementId, GlobalElementId, Hitbox, ImageData, InteractiveElement, Interactivity, IntoElement,
-    LayoutId, Length, Pixels, SharedUri, Size, StyleRefinement, Styled, SvgSize, UriOrPath,
-    WindowContext,
+    apng_loop_count, check_header_size, decode_animation_frame, decode_apng, decode_gif,
+    decode_ico, decode_page, decode_raster, fontdb, hash, hsla, paint_zoom_listeners, point, px,
+    quad, raster_size, read_color_space, size, svg_renderer, transparent_black, unsupported_format,
+    white, AbsoluteLength, AnyElement, AppContext, Asset, AssetLoadGuard, AsyncAppContext, Axis,
+    BlendMode, Bounds, ColorSpace, ContentMask, Corners, DecodedRaster, DefiniteLength,
+    DevicePixels, Edges, Element, ElementId, EncodedImage, EncodedImageReader, GlobalElementId,
+    Hitbox, Hsla, ImageData, ImageId, ImagePaintParams, ImageRedirect, InteractiveElement,
+    Interactivity, IntoElement, LayoutId, Length, LoadPriority, LoadProgress, LoadingAsset,
+    LoopCount, Pixels, Point, Rotation, Sampling, SharedString, SharedUri, Size, Style,
+    StyleRefinement, Styled, SvgOptions, SvgQuality, SvgSize, Task, Transformation, UriOrPath,
+    WindowContext, ZoomConfig, ZoomState, DEFAULT_MAX_SVG_RASTERIZATION_SIZE,
+};
+use collections::FxHashMap;
+use futures::{future::Shared, AsyncRead, Future, FutureExt};
+use image::{
+    imageops::{self, FilterType},
+    Bgra, DynamicImage, GenericImageView, ImageBuffer, ImageError, ImageFormat, RgbaImage,
 };
-use futures::{AsyncReadExt, Future};
-use image::{ImageBuffer, ImageError};
 #[cfg(target_os = "macos")]
 use media::core_video::CVImageBuffer;
+use parking_lot::Mutex;
+use resvg::tiny_skia::Pixmap;
 
 use thiserror::Error;
 use util::{http, ResultExt};
@@ -20,32 +41,247 @@ use util::{http, ResultExt};
 #[derive(Clone, Debug)]
 pub enum ImageSource {
     /// Image content will be loaded from provided URI at render time.
-    Uri(SharedUri),
+    /// `data:` URIs are decoded directly rather than fetched.
+    Uri {
+        /// The URI to load the image from.
+        uri: SharedUri,
+        /// How the image is fetched, decoded and cached.
+        options: UriOptions,
+    },
+    /// A single page of a multi-page image, such as a scanned TIFF document, or a single
+    /// frame of an animated image, loaded from the provided URI at render time. See
+    /// [`ImageSource::uri_page`].
+    UriPage {
+        /// The URI to load the image from.
+        uri: SharedUri,
+        /// The index of the page to display, starting from zero.
+        page: usize,
+    },
     /// Image content will be loaded from the provided file at render time.
     File(Arc<PathBuf>),
+    /// Image content will be loaded from the app's [`AssetSource`](crate::AssetSource) at
+    /// the given path at render time, like the icons of `svg` elements, so that images
+    /// bundled into the app don't need to be on the filesystem.
+    Asset(SharedString),
+    /// Encoded image content (in any supported format, including SVG) that will be
+    /// decoded at render time. Decoded images are cached by the hash of these bytes.
+    Bytes(Arc<[u8]>),
+    /// Encoded image content read from a stream at render time. See
+    /// [`ImageSource::reader`].
+    Reader(ImageReader),
+    /// Another source whose pixels are transformed once they've been decoded. See
+    /// [`ImageSource::with_decode_filter`].
+    Filtered {
+        /// The source to load the image from.
+        source: Box<ImageSource>,
+        /// The transform applied to the decoded pixels.
+        filter: DecodeFilter,
+    },
+    /// Another source whose image is cached separately from the same image loaded in other
+    /// scopes. See [`ImageSource::scoped`].
+    Scoped {
+        /// The scope the image is cached in.
+        scope: SharedString,
+        /// The source to load the image from.
+        source: Box<ImageSource>,
+    },
     /// Cached image data
     Data(Arc<ImageData>),
-    // TODO: move surface definitions into mac platform module
-    /// A CoreVideo image buffer
-    #[cfg(target_os = "macos")]
-    Surface(CVImageBuffer),
+    /// An animation made of already decoded frames, played like a decoded GIF. See
+    /// [`AnimatedImageData`].
+    AnimatedData(Arc<AnimatedImageData>),
+    /// A frame that's replaced often, such as one produced by a video decoder. See
+    /// [`Surface`].
+    Surface(Surface),
+}
+
+/// Options for loading an [`ImageSource::Uri`]. Every option is part of the cache key,
+/// so the same URI loaded with different options is fetched and cached separately.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UriOptions {
+    /// The HTTP headers to send when fetching the image. See
+    /// [`ImageSource::uri_with_headers`].
+    pub headers: Option<Arc<[(SharedString, SharedString)]>>,
+    /// The format to decode the image as, rather than the one detected from its
+    /// contents. See [`ImageSource::uri_with_format`].
+    pub format: Option<EncodedFormat>,
+    /// How long the loaded image is shown before it's loaded again. See
+    /// [`ImageSource::uri_with_ttl`].
+    pub ttl: Option<Duration>,
+}
+
+impl ImageSource {
+    /// Create a source that fetches the given URI with additional HTTP headers,
+    /// such as an `Authorization` token. The headers are part of the cache key, so
+    /// the same URI requested with different headers is fetched and cached separately.
+    pub fn uri_with_headers(
+        uri: impl Into<SharedUri>,
+        headers: impl IntoIterator<Item = (impl Into<SharedString>, impl Into<SharedString>)>,
+    ) -> Self {
+        Self::Uri {
+            uri: uri.into(),
+            options: UriOptions {
+                headers: Some(
+                    headers
+                        .into_iter()
+                        .map(|(name, value)| (name.into(), value.into()))
+                        .collect(),
+                ),
+                ..UriOptions::default()
+            },
+        }
+    }
+
+    /// Create a source that fetches the given URI and decodes it as the given format,
+    /// for servers whose content isn't recognized correctly, such as SVGs that start
+    /// with whitespace.
+    pub fn uri_with_format(uri: impl Into<SharedUri>, format: impl Into<EncodedFormat>) -> Self {
+        Self::Uri {
+            uri: uri.into(),
+            options: UriOptions {
+                format: Some(format.into()),
+                ..UriOptions::default()
+            },
+        }
+    }
+
+    /// Create a source that reads encoded image content from the stream returned by `open`,
+    /// such as one arriving over a chunked transport. The stream is read to its end before
+    /// the image is decoded. Images are cached by `id` rather than by their content, so
+    /// `open` is only called again once the image has been evicted from the cache, and
+    /// every stream it returns should produce the same image.
+    pub fn reader<R>(
+        id: impl Into<SharedString>,
+        open: impl Fn() -> R + Send + Sync + 'static,
+    ) -> Self
+    where
+        R: AsyncRead + Send + 'static,
+    {
+        Self::Reader(ImageReader {
+            id: id.into(),
+            open: Arc::new(move || Box::pin(open())),
+        })
+    }
+
+    /// Create a source that transforms the pixels of the image loaded from `source` once
+    /// it's been decoded, such as to color correct it, so the transformed image is what's
+    /// cached and drawn. The filter is called once for every frame. Images are cached by
+    /// the identity of `filter`, so clones of the same [`Arc`] share a cached image while
+    /// separate filters don't. Images that are already in memory aren't decoded, so they
+    /// aren't filtered either.
+    pub fn with_decode_filter(
+        source: impl Into<ImageSource>,
+        filter: Arc<dyn Fn(&mut RgbaImage) + Send + Sync>,
+    ) -> Self {
+        Self::Filtered {
+            source: Box::new(source.into()),
+            filter: DecodeFilter(filter),
+        }
+    }
+
+    /// Create a source whose image is cached within `scope`, so the same image loaded in
+    /// another scope, or outside of any, is fetched, decoded and cached separately. This
+    /// keeps images that depend on who requested them, such as ones fetched with a
+    /// session's credentials, from being shown to another session. Scoped images bypass the
+    /// disk cache, and can be removed all at once with [`crate::AssetCache::clear_scope`].
+    /// Images that are already in memory aren't cached, so they have no scope.
+    pub fn scoped(scope: impl Into<SharedString>, source: impl Into<ImageSource>) -> Self {
+        Self::Scoped {
+            scope: scope.into(),
+            source: Box::new(source.into()),
+        }
+    }
+
+    /// Create a source that fetches the given URI again when an element draws it after it
+    /// has been cached for longer than `ttl`, for images that change over time such as
+    /// status badges. The previous image is shown until the new one has loaded, and is kept
+    /// if loading it fails. These images bypass the disk cache.
+    pub fn uri_with_ttl(uri: impl Into<SharedUri>, ttl: Duration) -> Self {
+        Self::Uri {
+            uri: uri.into(),
+            options: UriOptions {
+                ttl: Some(ttl),
+                ..UriOptions::default()
+            },
+        }
+    }
+
+    /// Create a source that displays a single page of the multi-page image at the given
+    /// URI, or a single frame of an animated one. Only that page is decoded, and each page
+    /// is cached separately, but the image is only fetched once for all of them. Pages
+    /// past the end of the image fail to load with [`ImageCacheError::PageOutOfRange`].
+    pub fn uri_page(uri: impl Into<SharedUri>, page: usize) -> Self {
+        Self::UriPage {
+            uri: uri.into(),
+            page,
+        }
+    }
+
+    /// The URI a remote image was last fetched from, if the server redirected the request
+    /// for its original URI. Images read from the disk cache report the URI they were
+    /// redirected to when they were downloaded in this session, as long as the image is
+    /// still cached in memory.
+    pub fn resolved_uri(&self, cx: &AppContext) -> Option<SharedUri> {
+        cx.asset_cache
+            .get::<ImageRedirect>(&self.uri_or_path()?)
+            .flatten()
+    }
+
+    /// Get how loading this image with the default decoding options is going, starting to
+    /// load it if necessary. Like an [`Img`], this redraws the window once it's loaded.
+    pub fn state(&self, cx: &mut WindowContext) -> ImageState {
+        match self.use_data(&DecodeOptions::default(), cx) {
+            None => ImageState::Loading,
+            Some(Ok(data)) => ImageState::Loaded(data),
+            Some(Err(error)) => ImageState::Error(error),
+        }
+    }
+}
+
+/// The state of loading an image, as reported by [`ImageSource::state`].
+#[derive(Clone, Debug)]
+pub enum ImageState {
+    /// The image is still being fetched or decoded.
+    Loading,
+    /// The image has been loaded.
+    Loaded(Arc<ImageData>),
+    /// The image failed to load.
+    Error(ImageCacheError),
+}
+
+/// The format of an encoded image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EncodedFormat {
+    /// A raster image, decoded with the [`image`] crate.
+    Raster(ImageFormat),
+    /// An SVG document.
+    Svg,
+}
+
+impl From<ImageFormat> for EncodedFormat {
+    fn from(format: ImageFormat) -> Self {
+        Self::Raster(format)
+    }
 }
 
 impl From<SharedUri> for ImageSource {
     fn from(value: SharedUri) -> Self {
-        Self::Uri(value)
+        Self::Uri {
+            uri: value,
+            options: UriOptions::default(),
+        }
     }
 }
 
 impl From<&'static str> for ImageSource {
     fn from(uri: &'static str) -> Self {
-        Self::Uri(uri.into())
+        SharedUri::from(uri).into()
     }
 }
 
 impl From<String> for ImageSource {
     fn from(uri: String) -> Self {
-        Self::Uri(uri.into())
+        SharedUri::from(uri).into()
     }
 }
 
@@ -61,16 +297,309 @@ impl From<PathBuf> for ImageSource {
     }
 }
 
+impl From<Arc<[u8]>> for ImageSource {
+    fn from(value: Arc<[u8]>) -> Self {
+        Self::Bytes(value)
+    }
+}
+
 impl From<Arc<ImageData>> for ImageSource {
     fn from(value: Arc<ImageData>) -> Self {
         Self::Data(value)
     }
 }
 
+impl From<Arc<AnimatedImageData>> for ImageSource {
+    fn from(value: Arc<AnimatedImageData>) -> Self {
+        Self::AnimatedData(value)
+    }
+}
+
+impl From<AnimatedImageData> for ImageSource {
+    fn from(value: AnimatedImageData) -> Self {
+        Self::AnimatedData(Arc::new(value))
+    }
+}
+
+impl From<Surface> for ImageSource {
+    fn from(value: Surface) -> Self {
+        Self::Surface(value)
+    }
+}
+
+impl From<RawFrame> for ImageSource {
+    fn from(value: RawFrame) -> Self {
+        Self::Surface(value.into())
+    }
+}
+
 #[cfg(target_os = "macos")]
 impl From<CVImageBuffer> for ImageSource {
     fn from(value: CVImageBuffer) -> Self {
-        Self::Surface(value)
+        Self::Surface(value.into())
+    }
+}
+
+/// A frame that's replaced often, such as one produced by a video decoder, displayed with
+/// [`ImageSource::Surface`]. Unlike [`ImageSource::Data`], when an `img` element with an id
+/// paints a new frame, the previous one is released from the GPU.
+#[derive(Clone, Debug)]
+pub enum Surface {
+    /// Uncompressed pixels, which are converted into an image on a background thread
+    /// before they're painted. Until then, an `img` element with an id keeps painting the
+    /// previous frame.
+    Raw(Arc<RawFrame>),
+    /// A CoreVideo image buffer, which the renderer samples directly.
+    #[cfg(target_os = "macos")]
+    CoreVideo(CVImageBuffer),
+}
+
+impl Surface {
+    /// The size of this frame, in pixels.
+    pub fn size(&self) -> Size<DevicePixels> {
+        match self {
+            Surface::Raw(frame) => frame.size(),
+            #[cfg(target_os = "macos")]
+            Surface::CoreVideo(image_buffer) => {
+                size(image_buffer.width().into(), image_buffer.height().into())
+            }
+        }
+    }
+}
+
+impl From<RawFrame> for Surface {
+    fn from(value: RawFrame) -> Self {
+        Self::Raw(Arc::new(value))
+    }
+}
+
+impl From<Arc<RawFrame>> for Surface {
+    fn from(value: Arc<RawFrame>) -> Self {
+        Self::Raw(value)
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl From<CVImageBuffer> for Surface {
+    fn from(value: CVImageBuffer) -> Self {
+        Self::CoreVideo(value)
+    }
+}
+
+/// The pixel layout of a [`RawFrame`]. Alpha channels are expected to be straight, as
+/// with [`ImageData`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FrameFormat {
+    /// Four bytes per pixel, in red, green, blue, alpha order.
+    Rgba8,
+    /// Four bytes per pixel, in blue, green, red, alpha order.
+    Bgra8,
+    /// A plane of one luma byte per pixel, followed by a plane of interleaved blue and
+    /// red chroma bytes subsampled by two in each dimension. This is the layout most
+    /// hardware video decoders produce. Colors are converted using BT.601 limited range.
+    Nv12,
+}
+
+/// An uncompressed frame of pixels, displayed as a [`Surface::Raw`].
+#[derive(Debug)]
+pub struct RawFrame {
+    data: Arc<[u8]>,
+    format: FrameFormat,
+    size: Size<DevicePixels>,
+    image: OnceLock<Arc<ImageData>>,
+    converting: AtomicBool,
+}
+
+impl RawFrame {
+    /// Wrap the pixels of a frame of the given format and size. Returns an error if the
+    /// size is negative, or if `data` is too short to hold the pixels. Rows must be
+    /// tightly packed.
+    pub fn new(
+        data: impl Into<Arc<[u8]>>,
+        format: FrameFormat,
+        size: Size<DevicePixels>,
+    ) -> anyhow::Result<Self> {
+        let data = data.into();
+        let (Ok(width), Ok(height)) = (
+            usize::try_from(size.width.0),
+            usize::try_from(size.height.0),
+        ) else {
+            anyhow::bail!(
+                "a frame can't have a negative size, but got {}x{}",
+                size.width.0,
+                size.height.0
+            );
+        };
+        let expected_len = match format {
+            FrameFormat::Rgba8 | FrameFormat::Bgra8 => width
+                .checked_mul(height)
+                .and_then(|pixels| pixels.checked_mul(4)),
+            FrameFormat::Nv12 => {
+                let chroma = width
+                    .div_ceil(2)
+                    .checked_mul(height.div_ceil(2))
+                    .and_then(|samples| samples.checked_mul(2));
+                width
+                    .checked_mul(height)
+                    .zip(chroma)
+                    .and_then(|(luma, chroma)| luma.checked_add(chroma))
+            }
+        };
+        let Some(expected_len) = expected_len else {
+            anyhow::bail!("a {width}x{height} {format:?} frame is too large");
+        };
+        anyhow::ensure!(
+            data.len() >= expected_len,
+            "a {width}x{height} {format:?} frame needs {expected_len} bytes, but got {}",
+            data.len()
+        );
+        Ok(Self {
+            data,
+            format,
+            size,
+            image: OnceLock::new(),
+            converting: AtomicBool::new(false),
+        })
+    }
+
+    /// The size of this frame, in pixels.
+    pub fn size(&self) -> Size<DevicePixels> {
+        self.size
+    }
+
+    /// The pixel layout of this frame.
+    pub fn format(&self) -> FrameFormat {
+        self.format
+    }
+
+    /// The image this frame has been converted into, if it's ready. Otherwise the
+    /// conversion is started on a background thread, and the window is redrawn once it's
+    /// done.
+    fn use_image_data(self: &Arc<Self>, cx: &mut WindowContext) -> Option<Arc<ImageData>> {
+        if let Some(image) = self.image.get() {
+            return Some(image.clone());
+        }
+        if !self.converting.swap(true, SeqCst) {
+            let frame = self.clone();
+            let conversion = cx.background_executor().spawn(async move {
+                frame.image_data();
+            });
+            let parent_id = cx.parent_view_id();
+            cx.spawn(|mut cx| async move {
+                conversion.await;
+                cx.on_next_frame(move |cx| {
+                    if let Some(parent_id) = parent_id {
+                        cx.notify(parent_id)
+                    } else {
+                        cx.refresh()
+                    }
+                });
+            })
+            .detach();
+        }
+        None
+    }
+
+    /// Convert this frame into an image that can be uploaded to the GPU. The conversion
+    /// happens once, so this should be called on a background thread.
+    fn image_data(&self) -> Arc<ImageData> {
+        self.image
+            .get_or_init(|| Arc::new(ImageData::new(self.to_bgra())))
+            .clone()
+    }
+
+    fn to_bgra(&self) -> ImageBuffer<Bgra<u8>, Vec<u8>> {
+        let width = u32::from(self.size.width);
+        let height = u32::from(self.size.height);
+        let pixel_count = width as usize * height as usize;
+        let mut bgra = Vec::with_capacity(pixel_count * 4);
+        match self.format {
+            FrameFormat::Bgra8 => bgra.extend_from_slice(&self.data[..pixel_count * 4]),
+            FrameFormat::Rgba8 => {
+                for pixel in self.data[..pixel_count * 4].chunks_exact(4) {
+                    bgra.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                }
+            }
+            FrameFormat::Nv12 => {
+                let (luma, chroma) = self.data.split_at(pixel_count);
+                let chroma_stride = (width as usize).div_ceil(2) * 2;
+                let to_u8 = |component: f32| component.round().clamp(0., 255.) as u8;
+                for y in 0..height as usize {
+                    for x in 0..width as usize {
+                        let chroma_index = (y / 2) * chroma_stride + (x / 2) * 2;
+                        let l = 1.164 * (luma[y * width as usize + x] as f32 - 16.);
+                        let u = chroma[chroma_index] as f32 - 128.;
+                        let v = chroma[chroma_index + 1] as f32 - 128.;
+                        bgra.extend_from_slice(&[
+                            to_u8(l + 2.017 * u),
+                            to_u8(l - 0.392 * u - 0.813 * v),
+                            to_u8(l + 1.596 * v),
+                            255,
+                        ]);
+                    }
+                }
+            }
+        }
+        ImageBuffer::from_raw(width, height, bgra).expect("frame size was validated in new")
+    }
+}
+
+/// The frames of an animation that were decoded or generated by the app, such as a
+/// procedurally drawn progress indicator, displayed with [`ImageSource::AnimatedData`].
+#[derive(Debug)]
+pub struct AnimatedImageData {
+    frames: Vec<Arc<ImageData>>,
+    delays: Vec<Duration>,
+    loop_count: LoopCount,
+}
+
+impl AnimatedImageData {
+    /// Combine the given frames into an animation, each shown for the delay at the same
+    /// index. Delays shorter than 20ms are treated like those of a GIF, as 100ms. Only the
+    /// first frame of each image is used. Returns an error if there are no frames, if
+    /// there isn't a delay for each frame, or if the frames differ in size.
+    pub fn new(
+        frames: Vec<Arc<ImageData>>,
+        delays: Vec<Duration>,
+        loop_count: LoopCount,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(!frames.is_empty(), "an animation needs at least one frame");
+        anyhow::ensure!(
+            frames.len() == delays.len(),
+            "{} frames were given {} delays",
+            frames.len(),
+            delays.len()
+        );
+        let frame_size = frames[0].size();
+        if let Some(frame) = frames.iter().find(|frame| frame.size() != frame_size) {
+            anyhow::bail!(
+                "frames must all be {}x{}, but one is {}x{}",
+                frame_size.width.0,
+                frame_size.height.0,
+                frame.size().width.0,
+                frame.size().height.0
+            );
+        }
+        Ok(Self {
+            frames,
+            delays,
+            loop_count,
+        })
+    }
+
+    /// The frames of this animation.
+    pub fn frames(&self) -> &[Arc<ImageData>] {
+        &self.frames
+    }
+
+    /// How long each frame of this animation is displayed for.
+    pub fn delays(&self) -> &[Duration] {
+        &self.delays
+    }
+
+    /// How many times this animation plays.
+    pub fn loop_count(&self) -> LoopCount {
+        self.loop_count
     }
 }
 
@@ -78,8 +607,41 @@ impl From<CVImageBuffer> for ImageSource {
 pub struct Img {
     interactivity: Interactivity,
     source: ImageSource,
-    grayscale: bool,
+    sources: Vec<(Size<DevicePixels>, ImageSource)>,
+    placeholder: Option<ImageSource>,
+    fallback: Option<ImageSource>,
+    desaturation: f32,
+    tint: Hsla,
+    opacity: f32,
+    blend_mode: BlendMode,
+    sampling: Sampling,
+    rotation: Rotation,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+    transformation: Option<Transformation>,
+    transparency_grid: Option<TransparencyGrid>,
+    clip_shape: Option<ImageClip>,
     object_fit: ObjectFit,
+    object_position: Point<f32>,
+    focal_point: Option<Point<f32>>,
+    pixel_snap: Option<bool>,
+    source_rect: Option<Bounds<DevicePixels>>,
+    aspect_ratio: Option<f32>,
+    fit_axis: Option<Axis>,
+    load_priority: Option<LoadPriority>,
+    autoplay: bool,
+    min_update_interval: Option<Duration>,
+    respect_reduced_motion: bool,
+    alt: Option<ImageAlt>,
+    decode_options: DecodeOptions,
+    on_load: Option<Box<dyn Fn(&Arc<ImageData>, &mut WindowContext) + 'static>>,
+    on_error: Option<Box<dyn Fn(&ImageCacheError, &mut WindowContext) + 'static>>,
+    on_error_render: Option<Box<dyn Fn(&ImageCacheError, &mut WindowContext) -> AnyElement>>,
+    loading: Option<Box<dyn Fn(&mut WindowContext) -> AnyElement>>,
+    on_progress: Option<Box<dyn Fn(usize, Option<usize>, &mut WindowContext) + 'static>>,
+    debug_label: Option<SharedString>,
+    zoom: Option<ZoomConfig>,
+    on_zoom: Option<Box<dyn Fn(f32, &mut WindowContext) + 'static>>,
 }
 
 /// Create a new image element.
@@ -87,12 +649,155 @@ pub fn img(source: impl Into<ImageSource>) -> Img {
     Img {
         interactivity: Interactivity::default(),
         source: source.into(),
-        grayscale: false,
+        sources: Vec::new(),
+        placeholder: None,
+        fallback: None,
+        desaturation: 0.,
+        tint: white(),
+        opacity: 1.0,
+        blend_mode: BlendMode::Normal,
+        sampling: Sampling::Linear,
+        rotation: Rotation::None,
+        flip_horizontal: false,
+        flip_vertical: false,
+        transformation: None,
+        transparency_grid: None,
+        clip_shape: None,
         object_fit: ObjectFit::Contain,
+        object_position: point(0.5, 0.5),
+        focal_point: None,
+        pixel_snap: None,
+        source_rect: None,
+        aspect_ratio: None,
+        fit_axis: None,
+        load_priority: None,
+        autoplay: true,
+        min_update_interval: None,
+        respect_reduced_motion: true,
+        alt: None,
+        decode_options: DecodeOptions::default(),
+        on_load: None,
+        on_error: None,
+        on_error_render: None,
+        loading: None,
+        on_progress: None,
+        debug_label: None,
+        zoom: None,
+        on_zoom: None,
+    }
+}
+
+/// The checkerboard painted behind an image to show where it's transparent.
+/// See [`Img::transparency_grid`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransparencyGrid {
+    /// The width and height of each square of the grid.
+    pub cell_size: Pixels,
+    /// The color of the square in the top left corner, and every other square from it.
+    pub light: Hsla,
+    /// The color of the remaining squares.
+    pub dark: Hsla,
+}
+
+impl Default for TransparencyGrid {
+    fn default() -> Self {
+        Self {
+            cell_size: px(8.),
+            light: white(),
+            dark: hsla(0., 0., 0.8, 1.),
+        }
+    }
+}
+
+impl TransparencyGrid {
+    /// Paint the grid into the given bounds, rounding its corner squares by the given radii.
+    fn paint(
+        &self,
+        bounds: Bounds<Pixels>,
+        corner_radii: Corners<Pixels>,
+        opacity: f32,
+        cx: &mut WindowContext,
+    ) {
+        let fade = |mut color: Hsla| {
+            color.a *= opacity;
+            color
+        };
+        cx.paint_quad(quad(
+            bounds,
+            corner_radii,
+            fade(self.light),
+            0.,
+            transparent_black(),
+        ));
+
+        let cell_size = self.cell_size.max(px(1.));
+        let columns = (bounds.size.width / cell_size).ceil().max(0.) as usize;
+        let rows = (bounds.size.height / cell_size).ceil().max(0.) as usize;
+        let radius = |is_corner: bool, radius| if is_corner { radius } else { Pixels::ZERO };
+        for row in 0..rows {
+            // The light squares are already painted, so only paint the dark ones.
+            for column in ((row + 1) % 2..columns).step_by(2) {
+                let (first_row, last_row) = (row == 0, row + 1 == rows);
+                let (first_column, last_column) = (column == 0, column + 1 == columns);
+                let cell = Bounds {
+                    origin: bounds.origin
+                        + point(cell_size * column as f32, cell_size * row as f32),
+                    size: size(cell_size, cell_size),
+                }
+                .intersect(&bounds);
+                cx.paint_quad(quad(
+                    cell,
+                    Corners {
+                        top_left: radius(first_row && first_column, corner_radii.top_left),
+                        top_right: radius(first_row && last_column, corner_radii.top_right),
+                        bottom_right: radius(last_row && last_column, corner_radii.bottom_right),
+                        bottom_left: radius(last_row && first_column, corner_radii.bottom_left),
+                    },
+                    fade(self.dark),
+                    0.,
+                    transparent_black(),
+                ));
+            }
+        }
+    }
+}
+
+/// A shape to clip an image to. See [`Img::clip_shape`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageClip {
+    /// The largest circle that fits in the bounds, centered within them.
+    Circle,
+    /// The ellipse that fills the bounds.
+    Ellipse,
+    /// The bounds, with their corners rounded by the given radii.
+    RoundedRect(Corners<Pixels>),
+}
+
+impl ImageClip {
+    /// The rectangle this shape occupies within `bounds`, and the radii of its corners. An
+    /// ellipse is approximated by fully rounding the shorter sides of its bounds.
+    pub(crate) fn rounded_rect(&self, bounds: Bounds<Pixels>) -> (Bounds<Pixels>, Corners<Pixels>) {
+        match self {
+            ImageClip::Circle => {
+                let diameter = bounds.size.width.min(bounds.size.height);
+                let radius = diameter / 2.;
+                let circle = Bounds {
+                    origin: bounds.center() - point(radius, radius),
+                    size: size(diameter, diameter),
+                };
+                (circle, Corners::all(radius))
+            }
+            ImageClip::Ellipse => {
+                let radius = bounds.size.width.min(bounds.size.height) / 2.;
+                (bounds, Corners::all(radius))
+            }
+            ImageClip::RoundedRect(corner_radii) => (bounds, *corner_radii),
+        }
     }
 }
 
 /// How to fit the image into the bounds of the element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ObjectFit {
     /// The image will be stretched to fill the bounds of the element.
     Fill,
@@ -100,10 +805,25 @@ pub enum ObjectFit {
     Contain,
     /// The image will be scaled to cover the bounds of the element.
     Cover,
-    /// The image will be scaled down to fit within the bounds of the element.
+    /// The image will be scaled down to fit within the bounds of the element,
+    /// but never scaled up past its natural size.
     ScaleDown,
-    /// The image will maintain its original size.
+    /// The image will maintain its original size, aligned within the bounds of the
+    /// element like the other fits. Images larger than the element are clipped to it.
     None,
+    /// The image will be split into a 3x3 grid by the given insets, measured in the
+    /// image's pixels. The corners keep their natural size, the edges stretch along
+    /// their length and the center stretches to fill the rest of the bounds.
+    NineSlice {
+        /// The height of the top row.
+        top: Pixels,
+        /// The width of the right column.
+        right: Pixels,
+        /// The height of the bottom row.
+        bottom: Pixels,
+        /// The width of the left column.
+        left: Pixels,
+    },
 }
 
 impl ObjectFit {
@@ -112,68 +832,52 @@ impl ObjectFit {
         &self,
         bounds: Bounds<Pixels>,
         image_size: Size<DevicePixels>,
+    ) -> Bounds<Pixels> {
+        self.get_bounds_with_position(bounds, image_size, point(0.5, 0.5))
+    }
+
+    /// Get the bounds of the image within the given bounds, aligning any leftover
+    /// or overflowing space according to `position`, where `(0.0, 0.0)` aligns the
+    /// image to the top left of the bounds and `(1.0, 1.0)` to the bottom right.
+    pub fn get_bounds_with_position(
+        &self,
+        bounds: Bounds<Pixels>,
+        image_size: Size<DevicePixels>,
+        position: Point<f32>,
     ) -> Bounds<Pixels> {
         let image_size = image_size.map(|dimension| Pixels::from(u32::from(dimension)));
         let image_ratio = image_size.width / image_size.height;
         let bounds_ratio = bounds.size.width / bounds.size.height;
 
-        let result_bounds = match self {
-            ObjectFit::Fill => bounds,
-            ObjectFit::Contain => {
-                let new_size = if bounds_ratio > image_ratio {
-                    size(
-                        image_size.width * (bounds.size.height / image_size.height),
-                        bounds.size.height,
-                    )
-                } else {
-                    size(
-                        bounds.size.width,
-                        image_size.height * (bounds.size.width / image_size.width),
-                    )
-                };
+        let contained_size = if bounds_ratio > image_ratio {
+            size(
+                image_size.width * (bounds.size.height / image_size.height),
+                bounds.size.height,
+            )
+        } else {
+            size(
+                bounds.size.width,
+                image_size.height * (bounds.size.width / image_size.width),
+            )
+        };
 
-                Bounds {
-                    origin: point(
-                        bounds.origin.x + (bounds.size.width - new_size.width) / 2.0,
-                        bounds.origin.y + (bounds.size.height - new_size.height) / 2.0,
-                    ),
-                    size: new_size,
-                }
-            }
-            ObjectFit::ScaleDown => {
-                // Check if the image is larger than the bounds in either dimension.
-                if image_size.width > bounds.size.width || image_size.height > bounds.size.height {
-                    // If the image is larger, use the same logic as Contain to scale it down.
-                    let new_size = if bounds_ratio > image_ratio {
-                        size(
-                            image_size.width * (bounds.size.height / image_size.height),
-                            bounds.size.height,
-                        )
-                    } else {
-                        size(
-                            bounds.size.width,
-                            image_size.height * (bounds.size.width / image_size.width),
-                        )
-                    };
+        let positioned = |new_size: Size<Pixels>| Bounds {
+            origin: point(
+                bounds.origin.x + (bounds.size.width - new_size.width) * position.x,
+                bounds.origin.y + (bounds.size.height - new_size.height) * position.y,
+            ),
+            size: new_size,
+        };
 
-                    Bounds {
-                        origin: point(
-                            bounds.origin.x + (bounds.size.width - new_size.width) / 2.0,
-                            bounds.origin.y + (bounds.size.height - new_size.height) / 2.0,
-                        ),
-                        size: new_size,
-                    }
+        match self {
+            ObjectFit::Fill | ObjectFit::NineSlice { .. } => bounds,
+            ObjectFit::Contain => positioned(contained_size),
+            ObjectFit::ScaleDown => {
+                // Behave like `Contain`, but never scale the image up past its natural size.
+                if contained_size.width < image_size.width {
+                    positioned(contained_size)
                 } else {
-                    // If the image is smaller than or equal to the container, display it at its original size,
-                    // centered within the container.
-                    let original_size = size(image_size.width, image_size.height);
-                    Bounds {
-                        origin: point(
-                            bounds.origin.x + (bounds.size.width - original_size.width) / 2.0,
-                            bounds.origin.y + (bounds.size.height - original_size.height) / 2.0,
-                        ),
-                        size: original_size,
-                    }
+                    positioned(image_size)
                 }
             }
             ObjectFit::Cover => {
@@ -189,21 +893,77 @@ impl ObjectFit {
                     )
                 };
 
-                Bounds {
-                    origin: point(
-                        bounds.origin.x + (bounds.size.width - new_size.width) / 2.0,
-                        bounds.origin.y + (bounds.size.height - new_size.height) / 2.0,
-                    ),
-                    size: new_size,
-                }
+                positioned(new_size)
             }
-            ObjectFit::None => Bounds {
-                origin: bounds.origin,
-                size: image_size,
-            },
+            ObjectFit::None => positioned(image_size),
+        }
+    }
+
+    /// Get the bounds of the image within the given bounds, keeping `focal_point` visible
+    /// when `Cover` crops the image. The focal point is in normalized coordinates of the
+    /// image and is moved as close to the center of the bounds as the image allows while
+    /// still covering them, so `(0.5, 0.5)` gives the same centered crop as
+    /// [`ObjectFit::get_bounds`]. Other fits ignore the focal point.
+    pub fn get_bounds_with_focal_point(
+        &self,
+        bounds: Bounds<Pixels>,
+        image_size: Size<DevicePixels>,
+        focal_point: Point<f32>,
+    ) -> Bounds<Pixels> {
+        let fitted = self.get_bounds(bounds, image_size);
+        if *self != ObjectFit::Cover {
+            return fitted;
+        }
+
+        let focal_point = point(
+            fitted.size.width * focal_point.x.clamp(0., 1.),
+            fitted.size.height * focal_point.y.clamp(0., 1.),
+        );
+        let overflow = point(
+            bounds.size.width - fitted.size.width,
+            bounds.size.height - fitted.size.height,
+        );
+        let origin =
+            (bounds.center() - focal_point).clamp(&(bounds.origin + overflow), &bounds.origin);
+        Bounds {
+            origin,
+            size: fitted.size,
+        }
+    }
+
+    /// Get the region of an image of the given size that's visible within the given bounds,
+    /// in the image's pixels, when it's fitted with [`ObjectFit::get_bounds`]. Only `Cover`
+    /// and `None` crop the image, so the other fits return the whole image.
+    pub fn source_rect(
+        &self,
+        bounds: Bounds<Pixels>,
+        image_size: Size<DevicePixels>,
+    ) -> Bounds<DevicePixels> {
+        let image = Bounds {
+            origin: Point::default(),
+            size: image_size,
         };
+        let fitted = self.get_bounds(bounds, image_size);
+        if matches!(self, ObjectFit::NineSlice { .. })
+            || fitted.size.width <= Pixels::ZERO
+            || fitted.size.height <= Pixels::ZERO
+        {
+            return image;
+        }
 
-        result_bounds
+        let visible = fitted.intersect(&bounds);
+        let scale_x = image_size.width.0 as f32 / fitted.size.width.0;
+        let scale_y = image_size.height.0 as f32 / fitted.size.height.0;
+        // Include every pixel that's even partly visible.
+        let upper_left = point(
+            DevicePixels(((visible.origin.x - fitted.origin.x).0 * scale_x).floor() as i32),
+            DevicePixels(((visible.origin.y - fitted.origin.y).0 * scale_y).floor() as i32),
+        );
+        let lower_right = point(
+            DevicePixels(((visible.right() - fitted.origin.x).0 * scale_x).ceil() as i32),
+            DevicePixels(((visible.bottom() - fitted.origin.y).0 * scale_y).ceil() as i32),
+        );
+        Bounds::from_corners(upper_left, lower_right).intersect(&image)
     }
 }
 
@@ -217,187 +977,2870 @@ impl Img {
         ]
     }
 
-    /// Set the image to be displayed in grayscale.
-    pub fn grayscale(mut self, grayscale: bool) -> Self {
-        self.grayscale = grayscale;
+    /// Set the image to be displayed in grayscale. Equivalent to `desaturate(1.0)`, or to
+    /// `desaturate(0.0)` when `false`.
+    pub fn grayscale(self, grayscale: bool) -> Self {
+        self.desaturate(if grayscale { 1. } else { 0. })
+    }
+
+    /// Mix the image's colors towards gray by `amount`, from `0.0` for full color to `1.0`
+    /// for fully gray. Values outside that range are clamped. Useful for animating between
+    /// a colored and a grayscale image.
+    pub fn desaturate(mut self, amount: f32) -> Self {
+        self.desaturation = amount.clamp(0., 1.);
         self
     }
-    /// Set the object fit for the image.
-    pub fn object_fit(mut self, object_fit: ObjectFit) -> Self {
-        self.object_fit = object_fit;
+
+    /// Multiply every pixel of the image by the given color, channel by channel, including
+    /// alpha. The tint is applied after any desaturation and before opacity, so
+    /// a grayscale image tinted with a color is shaded in that color.
+    pub fn tint(mut self, color: impl Into<Hsla>) -> Self {
+        self.tint = color.into();
         self
     }
-}
 
-impl Element for Img {
-    type RequestLayoutState = ();
-    type PrepaintState = Option<Hitbox>;
+    /// Set the opacity the image is drawn with, from `0.0` (transparent) to `1.0` (opaque).
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
 
-    fn id(&self) -> Option<ElementId> {
-        self.interactivity.element_id.clone()
+    /// Set how the image is combined with what's painted beneath it, for example to draw
+    /// highlights with [`BlendMode::Add`]. Defaults to [`BlendMode::Normal`].
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
     }
 
-    fn request_layout(
-        &mut self,
-        global_id: Option<&GlobalElementId>,
-        cx: &mut WindowContext,
-    ) -> (LayoutId, Self::RequestLayoutState) {
-        let layout_id = self
-            .interactivity
-            .request_layout(global_id, cx, |mut style, cx| {
-                if let Some(data) = self.source.data(cx) {
-                    let image_size = data.size();
-                    match (style.size.width, style.size.height) {
-                        (Length::Auto, Length::Auto) => {
-                            style.size = Size {
-                                width: Length::Definite(DefiniteLength::Absolute(
-                                    AbsoluteLength::Pixels(px(image_size.width.0 as f32)),
-                                )),
-                                height: Length::Definite(DefiniteLength::Absolute(
-                                    AbsoluteLength::Pixels(px(image_size.height.0 as f32)),
-                                )),
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+    /// Set how the image's pixels are sampled when it's drawn larger or smaller than its
+    /// own size. Defaults to [`Sampling::Linear`], which suits photos. Use
+    /// [`Sampling::Nearest`] with [`ObjectFit::None`] to draw pixel art crisply at its
+    /// natural size, or with another fit at a whole multiple of its size to scale it up
+    /// without blurring its pixels.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
 
-                cx.request_layout(&style, [])
-            });
-        (layout_id, ())
+    /// Rotate the image clockwise by the given number of quarter turns when it's painted.
+    ///
+    /// The rotation doesn't change the element's layout, which is still sized by the
+    /// upright image. Instead, the rotated image is fitted into the element's bounds, so a
+    /// quarter turn fits the image as if its width and height were swapped. Flips are
+    /// applied after the rotation, across the axes of the element. Rotation and flipping
+    /// are ignored by [`ObjectFit::NineSlice`].
+    pub fn rotate(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
     }
 
-    fn prepaint(
-        &mut self,
-        global_id: Option<&GlobalElementId>,
-        bounds: Bounds<Pixels>,
-        _request_layout: &mut Self::RequestLayoutState,
-        cx: &mut WindowContext,
-    ) -> Option<Hitbox> {
-        self.interactivity
-            .prepaint(global_id, bounds, bounds.size, cx, |_, _, hitbox, _| hitbox)
+    /// Mirror the image left to right when it's painted, for example to match a right to
+    /// left layout. See [`Img::rotate`] for how this combines with rotation.
+    pub fn flip_horizontal(mut self, flip_horizontal: bool) -> Self {
+        self.flip_horizontal = flip_horizontal;
+        self
     }
 
-    fn paint(
-        &mut self,
+    /// Mirror the image top to bottom when it's painted. See [`Img::rotate`] for how this
+    /// combines with rotation.
+    pub fn flip_vertical(mut self, flip_vertical: bool) -> Self {
+        self.flip_vertical = flip_vertical;
+        self
+    }
+
+    /// Paint a checkerboard behind the image, as image editors do, so that its transparent
+    /// parts are distinguishable from what's beneath the element. The grid covers the same
+    /// bounds and corner radii as the image. Use [`Img::transparency_grid_style`] to change
+    /// the size and colors of its squares.
+    ///
+    /// Corner radii larger than a square can leave parts of the squares next to the corner
+    /// square outside the rounded corner. The grid isn't drawn behind images with a
+    /// [`Img::with_transformation`].
+    pub fn transparency_grid(mut self, transparency_grid: bool) -> Self {
+        self.transparency_grid = transparency_grid.then(TransparencyGrid::default);
+        self
+    }
+
+    /// Paint the given checkerboard behind the image. See [`Img::transparency_grid`].
+    pub fn transparency_grid_style(mut self, transparency_grid: TransparencyGrid) -> Self {
+        self.transparency_grid = Some(transparency_grid);
+        self
+    }
+
+    /// Clip the image to the given shape within the element's bounds, rather than to its
+    /// corner radii. [`ImageClip::Circle`] always clips to the largest circle that fits in the
+    /// element, making it suited to avatars of any size. Clip edges are anti-aliased.
+    pub fn clip_shape(mut self, clip_shape: ImageClip) -> Self {
+        self.clip_shape = Some(clip_shape);
+        self
+    }
+
+    /// Transform the painted image with the given transformation, around the center of the
+    /// element. The element's hitbox is transformed too, so it responds to the mouse where
+    /// the image is drawn, but its layout is unchanged. The transformation is applied after
+    /// any [`Img::rotate`] and flips, and doesn't apply to [`Img::loading`] or
+    /// [`Img::on_error_render`] content.
+    pub fn with_transformation(mut self, transformation: Transformation) -> Self {
+        self.transformation = Some(transformation);
+        self
+    }
+
+    /// Set the object fit for the image.
+    pub fn object_fit(mut self, object_fit: ObjectFit) -> Self {
+        self.object_fit = object_fit;
+        self
+    }
+
+    /// Reserve space for the image with the given width-to-height ratio, so the layout
+    /// doesn't jump once an image that loads asynchronously arrives. The ratio is applied
+    /// against whichever dimension is constrained, and keeps being honored once the image
+    /// has loaded, even if its natural aspect ratio differs.
+    pub fn aspect_ratio(mut self, ratio: f32) -> Self {
+        self.aspect_ratio = (ratio.is_finite() && ratio > 0.0).then_some(ratio);
+        self
+    }
+
+    /// Fill the available width, with the height following the image's natural aspect ratio
+    /// once it's loaded. Until then, the ratio given to [`Img::aspect_ratio`] is used, if
+    /// any. Overrides the element's own width and height.
+    pub fn fit_to_width(mut self) -> Self {
+        self.fit_axis = Some(Axis::Horizontal);
+        self
+    }
+
+    /// Fill the available height, with the width following the image's natural aspect
+    /// ratio once it's loaded. Until then, the ratio given to [`Img::aspect_ratio`] is used,
+    /// if any. Overrides the element's own width and height.
+    pub fn fit_to_height(mut self) -> Self {
+        self.fit_axis = Some(Axis::Vertical);
+        self
+    }
+
+    /// Set how urgently the image is fetched and decoded relative to other images waiting
+    /// their turn, as limited by [`crate::AppContext::set_image_fetch_concurrency`] and
+    /// [`crate::AppContext::set_image_decode_concurrency`]. By default, images
+    /// that are in view have a [`LoadPriority::High`] priority and those that are scrolled
+    /// out of view a [`LoadPriority::Low`] one, so what's on screen is shown first.
+    pub fn load_priority(mut self, priority: LoadPriority) -> Self {
+        self.load_priority = Some(priority);
+        self
+    }
+
+    /// Set whether animated images, such as GIFs, play automatically. Defaults to `true`.
+    /// When disabled, or when the element has no id, only the first frame is shown.
+    pub fn autoplay(mut self, autoplay: bool) -> Self {
+        self.autoplay = autoplay;
+        self
+    }
+
+    /// Set whether the image stops animating when the user prefers reduced motion, as set
+    /// with [`crate::AppContext::set_reduce_motion`]. Defaults to `true`. Opt out for
+    /// animations that convey essential information, such as a loading indicator.
+    pub fn respect_reduced_motion(mut self, respect_reduced_motion: bool) -> Self {
+        self.respect_reduced_motion = respect_reduced_motion;
+        self
+    }
+
+    /// Show a new image at most once per the given interval, for sources that change more
+    /// often than they need to be displayed, such as an [`ImageSource::Data`] updated from a
+    /// live feed. An image that arrives too soon is held back, and the newest image is shown
+    /// once the interval has passed. Images are compared by pointer, so redrawing the same
+    /// `Arc<ImageData>` doesn't count as a change.
+    ///
+    /// The element must have an id to remember when its image last changed, otherwise
+    /// every image is shown straight away.
+    pub fn min_update_interval(mut self, interval: Duration) -> Self {
+        self.min_update_interval = Some(interval);
+        self
+    }
+
+    /// Describe the image's content for assistive technology, such as screen readers. The
+    /// description labels the element's [`Hitbox`], where it's found by
+    /// [`WindowContext::accessibility_label_at`].
+    pub fn alt(mut self, text: impl Into<SharedString>) -> Self {
+        self.alt = Some(ImageAlt::Text(text.into()));
+        self
+    }
+
+    /// Mark the image as purely decorative, so assistive technology skips it: it has no
+    /// label, and the element doesn't insert a hitbox just to carry one.
+    pub fn alt_decorative(mut self) -> Self {
+        self.alt = Some(ImageAlt::Decorative);
+        self
+    }
+
+    /// The label assistive technology should announce for this image, or `None` if it
+    /// has no description or is decorative.
+    pub fn accessibility_label(&self) -> Option<&SharedString> {
+        match &self.alt {
+            Some(ImageAlt::Text(text)) => Some(text),
+            Some(ImageAlt::Decorative) | None => None,
+        }
+    }
+
+    /// Whether assistive technology should skip this image.
+    pub fn is_decorative(&self) -> bool {
+        self.alt == Some(ImageAlt::Decorative)
+    }
+
+    /// Set whether JPEG and TIFF images are rotated and flipped according to their EXIF
+    /// orientation tag. Defaults to `true`.
+    pub fn respect_exif_orientation(mut self, respect_exif_orientation: bool) -> Self {
+        self.decode_options.respect_exif_orientation = respect_exif_orientation;
+        self
+    }
+
+    /// Paint every fill and stroke of an SVG image with the given color, for example to
+    /// match monochrome icons to the current theme. Has no effect on raster images.
+    pub fn svg_color(mut self, color: impl Into<Hsla>) -> Self {
+        self.decode_options.svg_color = Some(color.into());
+        self
+    }
+
+    /// Parse an SVG image with the given options, rather than those set with
+    /// [`crate::AppContext::set_svg_options`]. Has no effect on raster images.
+    pub fn svg_options(mut self, options: SvgOptions) -> Self {
+        self.decode_options.svg_options = Some(options);
+        self
+    }
+
+    /// Render an SVG image smoothly or crisply, rather than with the quality of its
+    /// [`SvgOptions`]. Each quality is cached separately. Has no effect on raster images.
+    pub fn svg_quality(mut self, quality: SvgQuality) -> Self {
+        self.decode_options.svg_quality = Some(quality);
+        self
+    }
+
+    /// Blur the image with a Gaussian blur, where `radius` is the standard deviation of
+    /// the blur, like CSS's `blur()` filter. The blurred image is computed once when the
+    /// image is decoded and cached separately from the unblurred one.
+    pub fn blur(mut self, radius: impl Into<Pixels>) -> Self {
+        let radius = radius.into();
+        self.decode_options.blur_radius = (radius > px(0.)).then_some(radius);
+        self
+    }
+
+    /// Sharpen the image with an unsharp mask, to make photos that are decoded at a reduced
+    /// size with [`Img::max_decode_size`] look less soft. `amount` is how much of the
+    /// detail the mask finds is added back, from `0.0` for none up to `2.0`, past which
+    /// edges start to ring. Like [`Img::blur`], the sharpened image is computed once when
+    /// the image is decoded and cached separately for each amount.
+    pub fn sharpen(mut self, amount: f32) -> Self {
+        let amount = amount.clamp(0., MAX_SHARPEN_AMOUNT);
+        self.decode_options.sharpen_amount = (amount > 0.).then_some(amount.to_bits());
+        self
+    }
+
+    /// Decode still raster images at no more than the given size, preserving their aspect
+    /// ratio. Pass the size the image is displayed at, in device pixels, to avoid holding
+    /// the full resolution of very large images in memory. Each size is cached separately.
+    pub fn max_decode_size(mut self, max_size: Size<DevicePixels>) -> Self {
+        self.decode_options.max_decode_size = Some(max_size);
+        self
+    }
+
+    /// Choose between several versions of the image at different resolutions, each paired
+    /// with its size in pixels, like an HTML `srcset`. Once the element has been laid out,
+    /// only the smallest version that covers the element's size in device pixels is loaded,
+    /// or the largest one if none do, in place of the element's source. Versions that fail
+    /// to load are skipped in favor of the next best one. The element must have an id to
+    /// take the natural size of the chosen version, otherwise it's only known once painted.
+    pub fn sources(mut self, sources: Vec<(Size<DevicePixels>, ImageSource)>) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Name the image in the errors logged when it fails to load, to tell which image failed
+    /// when its source, such as a `data:` URI, doesn't make that obvious. The label has no
+    /// effect on how the image is loaded, cached or drawn.
+    pub fn debug_label(mut self, label: impl Into<SharedString>) -> Self {
+        self.debug_label = Some(label.into());
+        self
+    }
+
+    /// Let the user zoom into the image by scrolling over it, towards the cursor, and pan
+    /// around the zoomed image by dragging it, within the limits of `config`. The image is
+    /// kept covering the element while it's zoomed in, so it can't be panned past its
+    /// edges, and is clipped to the element. The zoom is kept across frames, so the
+    /// element needs an id. Zooming is ignored by [`ObjectFit::NineSlice`].
+    pub fn zoomable(mut self, config: ZoomConfig) -> Self {
+        self.zoom = Some(config);
+        self.interactivity.occlude_mouse();
+        self
+    }
+
+    /// Register a callback for when the user zooms a [`Img::zoomable`] image, which is
+    /// given the new zoom, such as to show it as a percentage.
+    pub fn on_zoom(mut self, listener: impl Fn(f32, &mut WindowContext) + 'static) -> Self {
+        self.on_zoom = Some(Box::new(listener));
+        self
+    }
+
+    /// Set an image to display while the source is still loading.
+    /// It is drawn with the same object fit and corner radii as the source.
+    pub fn placeholder(mut self, placeholder: impl Into<ImageSource>) -> Self {
+        self.placeholder = Some(placeholder.into());
+        self
+    }
+
+    /// Set an image to display if the source fails to load.
+    /// It is drawn with the same object fit and corner radii as the source.
+    pub fn fallback(mut self, fallback: impl Into<ImageSource>) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+
+    /// Register a callback to be invoked once the image has finished loading. Use
+    /// [`ImageSource::resolved_uri`] to find where an image was redirected to.
+    ///
+    /// The callback fires once per loaded image. The element must have an id to remember
+    /// what it reported, otherwise the callback isn't invoked.
+    pub fn on_load(
+        mut self,
+        listener: impl Fn(&Arc<ImageData>, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.on_load = Some(Box::new(listener));
+        self
+    }
+
+    /// Register a callback to be invoked if the image fails to load.
+    ///
+    /// The callback fires once per failed source, like [`Img::on_load`].
+    pub fn on_error(
+        mut self,
+        listener: impl Fn(&ImageCacheError, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.on_error = Some(Box::new(listener));
+        self
+    }
+
+    /// Render the given element in place of the image if the source fails to load.
+    /// It's laid out as the element's only child, and takes precedence over [`Img::fallback`].
+    pub fn on_error_render(
+        mut self,
+        render: impl Fn(&ImageCacheError, &mut WindowContext) -> AnyElement + 'static,
+    ) -> Self {
+        self.on_error_render = Some(Box::new(render));
+        self
+    }
+
+    /// Render the given element in place of the image while the source is loading, such as
+    /// a spinner. It's laid out as the element's only child, and takes precedence over
+    /// [`Img::placeholder`]. Sources that have already loaded are drawn straight away, so
+    /// the element only appears while a load is actually in progress.
+    pub fn loading(mut self, render: impl Fn(&mut WindowContext) -> AnyElement + 'static) -> Self {
+        self.loading = Some(Box::new(render));
+        self
+    }
+
+    /// Build the element to render in place of the image, if the source is still loading
+    /// or failed to load and there's something to render for that.
+    fn replacement_element(&self, cx: &mut WindowContext) -> Option<AnyElement> {
+        if self.loading.is_none() && self.on_error_render.is_none() {
+            return None;
+        }
+        // Any pending load was already requested, and guarded if possible, by `Self::data`.
+        let (result, _) =
+            cx.with_asset_load_guards(|cx| self.source.use_data(&self.decode_options, cx));
+        match result {
+            None => Some((self.loading.as_ref()?)(cx)),
+            Some(Err(error)) => Some((self.on_error_render.as_ref()?)(&error, cx)),
+            Some(Ok(_)) => None,
+        }
+    }
+
+    /// Register a callback to be invoked as a remote image downloads, with the number of
+    /// bytes received so far and, if the server reported it, the total size in bytes.
+    ///
+    /// The element must have an id for the callback to fire only when the progress changes,
+    /// otherwise it will be invoked every time the element is drawn during the download.
+    pub fn on_progress(
+        mut self,
+        listener: impl Fn(usize, Option<usize>, &mut WindowContext) + 'static,
+    ) -> Self {
+        self.on_progress = Some(Box::new(listener));
+        self
+    }
+
+    /// Choose the best of [`Img::sources`] for displaying the image at `device_size`,
+    /// skipping any that have already failed to load.
+    fn select_source(
+        &self,
+        device_size: Size<DevicePixels>,
+        cx: &mut WindowContext,
+    ) -> Option<ImageSource> {
+        let candidates = by_size_preference(&self.sources, device_size);
+        let failed = |source: &ImageSource, cx: &mut WindowContext| {
+            source
+                .asset_source(&self.decode_options)
+                .and_then(|source| cx.asset_cache.get::<Image>(&source))
+                .map_or(false, |result| result.is_err())
+        };
+        candidates
+            .iter()
+            .find(|source| !failed(*source, cx))
+            .or(candidates.first())
+            .map(|source| (*source).clone())
+    }
+
+    /// The one of [`Img::sources`] chosen when the element was last prepainted, to load
+    /// before it's laid out again.
+    fn selected_source(
+        &self,
+        global_id: Option<&GlobalElementId>,
+        cx: &mut WindowContext,
+    ) -> Option<ImageSource> {
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, _| match state {
+            Some(state) => {
+                let state = state.unwrap_or_default();
+                (state.selected_source.clone(), Some(state))
+            }
+            None => (None, None),
+        })
+    }
+
+    /// Decode the image of a multi-size ICO that best suits the size the element was last
+    /// laid out at, once the ICO's largest image has loaded and listed the sizes it holds.
+    /// The largest image is shown until the better one has loaded. Elements without an id
+    /// don't remember their size, so they always show the largest image.
+    fn select_ico_size(&mut self, global_id: Option<&GlobalElementId>, cx: &mut WindowContext) {
+        let Some(device_size) = self.device_size(global_id, cx) else {
+            return;
+        };
+        let Some(Ok(data)) = self
+            .source
+            .asset_source(&self.decode_options)
+            .and_then(|source| cx.asset_cache.get::<Image>(&source))
+        else {
+            return;
+        };
+        let sizes = data
+            .embedded_sizes()
+            .iter()
+            .map(|size| (*size, *size))
+            .collect::<Vec<_>>();
+        let Some(&&best) = by_size_preference(&sizes, device_size).first() else {
+            return;
+        };
+        if data.natural_size() == Some(best) {
+            return;
+        }
+        let options = DecodeOptions {
+            ico_size: Some(best),
+            ..self.decode_options.clone()
+        };
+        if let Some(Ok(_)) = self.source.use_data(&options, cx) {
+            self.decode_options = options;
+        }
+    }
+
+    /// The size in device pixels the element was last laid out at, to choose between its
+    /// [`Img::sources`], or the images in an ICO, before it's laid out again.
+    fn device_size(
+        &self,
+        global_id: Option<&GlobalElementId>,
+        cx: &mut WindowContext,
+    ) -> Option<Size<DevicePixels>> {
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, _| match state {
+            Some(state) => {
+                let state = state.unwrap_or_default();
+                (state.device_size, Some(state))
+            }
+            None => (None, None),
+        })
+    }
+
+    /// Load the image to display, along with the size of the source if it's a vector image.
+    /// The element's state keeps any fetches this starts alive, so they're cancelled if the
+    /// element stops being drawn before they complete. Elements without an id have nowhere to
+    /// keep them, so their fetches always run to completion.
+    fn data(
+        &self,
+        global_id: Option<&GlobalElementId>,
+        cx: &mut WindowContext,
+    ) -> (Option<Arc<ImageData>>, Option<Size<Pixels>>) {
+        let load = |cx: &mut WindowContext| {
+            let data = self.load_data(cx);
+            let vector_size = self.vector_size(data.is_none(), cx);
+            (data, vector_size)
+        };
+        let Some(global_id) = global_id else {
+            return load(cx);
+        };
+        let ((data, vector_size), load_guards) = cx.with_asset_load_guards(load);
+        cx.with_element_state::<ImgState, _>(global_id, |state, cx| {
+            let mut state = state.unwrap_or_default();
+            state.load_guards = load_guards;
+            let data = if self.source.is_raw_surface() {
+                state.show_raw_frame(data, cx)
+            } else {
+                data
+            };
+            let data = match (self.min_update_interval, data) {
+                (Some(interval), Some(data)) => Some(state.throttle(data, interval, cx)),
+                (_, data) => data,
+            };
+            ((data, vector_size), state)
+        })
+    }
+
+    /// The size an SVG source declares for itself, in logical pixels. This only needs the
+    /// document to be parsed rather than rasterized, so while the image is `loading` it's
+    /// requested alongside it, letting the element take its natural size before the image
+    /// is first painted. Once the image has loaded, the size is only used if it's already
+    /// known, to avoid reading the source a second time.
+    fn vector_size(&self, loading: bool, cx: &mut WindowContext) -> Option<Size<Pixels>> {
+        if self.source_rect.is_some() {
+            return None;
+        }
+        let source = self.source.uri_or_path()?;
+        let intrinsic_size = if loading {
+            cx.use_cached_asset::<ImageSize>(&source)?
+        } else {
+            cx.asset_cache.get::<ImageSize>(&source)?
+        };
+        match intrinsic_size {
+            Ok(IntrinsicSize {
+                size: intrinsic,
+                vector: true,
+            }) => Some(size(
+                px(intrinsic.width.0 as f32),
+                px(intrinsic.height.0 as f32),
+            )),
+            Ok(_) => None,
+            Err(error) => {
+                if error.is_transient() {
+                    cx.on_next_frame(move |cx| {
+                        cx.remove_cached_asset::<ImageSize>(&source);
+                    });
+                }
+                None
+            }
+        }
+    }
+
+    /// While the image is still loading, let the fetch and decode queues know how urgently
+    /// it's needed, which unless set with [`Img::load_priority`] depends on whether the
+    /// element is in view.
+    fn update_load_priority(&self, bounds: Bounds<Pixels>, cx: &mut WindowContext) {
+        let Some(source) = self.source.asset_source(&self.decode_options) else {
+            return;
+        };
+        if cx.asset_cache.contains::<Image>(&source) {
+            return;
+        }
+        let priority = self.load_priority.unwrap_or_else(|| {
+            if cx.content_mask().bounds.intersects(&bounds) {
+                LoadPriority::High
+            } else {
+                LoadPriority::Low
+            }
+        });
+        cx.image_fetch_queue()
+            .set_priority(hash(&source.source), priority);
+        cx.image_decode_queue()
+            .set_priority(hash(&source), priority);
+    }
+
+    fn load_data(&self, cx: &mut WindowContext) -> Option<Arc<ImageData>> {
+        let options = &self.decode_options;
+        match self.source.use_data(options, cx) {
+            Some(Ok(data)) => Some(data),
+            Some(Err(error)) => {
+                match &self.debug_label {
+                    Some(label) => log::error!("failed to load image {label:?}: {error:?}"),
+                    None => log::error!("failed to load image: {error:?}"),
+                }
+                self.fallback.as_ref()?.data(options, cx)
+            }
+            // Raw frames are converted in a moment, and until then the previous frame is
+            // kept on screen rather than flashing the placeholder.
+            None if self.source.is_raw_surface() => None,
+            None => self.placeholder.as_ref()?.data(options, cx),
+        }
+    }
+
+    /// Get the zoom of a [`Img::zoomable`] image, updated for where the element and image
+    /// are painted this frame.
+    fn zoom_state(
+        &self,
         global_id: Option<&GlobalElementId>,
         bounds: Bounds<Pixels>,
-        _: &mut Self::RequestLayoutState,
-        hitbox: &mut Self::PrepaintState,
+        content_bounds: Bounds<Pixels>,
+        cx: &mut WindowContext,
+    ) -> Option<Rc<RefCell<ZoomState>>> {
+        let config = self.zoom?;
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, _| {
+            let Some(state) = state else {
+                return (None, None);
+            };
+            let mut state = state.unwrap_or_default();
+            let zoom = state.zoom.get_or_insert_with(Default::default).clone();
+            zoom.borrow_mut().update(bounds, content_bounds, config);
+            (Some(zoom), Some(state))
+        })
+    }
+
+    fn current_frame(
+        &self,
+        global_id: Option<&GlobalElementId>,
+        frames: Frames,
+        cx: &mut WindowContext,
+    ) -> usize {
+        if frames.count() <= 1 || (self.respect_reduced_motion && cx.reduce_motion()) {
+            return 0;
+        }
+
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, cx| {
+            let Some(state) = state else {
+                return (0, None);
+            };
+            let mut state = state.unwrap_or_default();
+
+            let now = Instant::now();
+            if state
+                .animation
+                .as_ref()
+                .map_or(true, |animation| animation.image_id != frames.id())
+            {
+                state.animation = Some(AnimationState {
+                    image_id: frames.id(),
+                    frame_index: 0,
+                    frame_started: now,
+                    loops_completed: 0,
+                    next_frame: None,
+                });
+            }
+            let animation = state.animation.as_mut().unwrap();
+
+            if self.autoplay && !animation.is_finished(frames) {
+                // Catch up on at most one loop of frames, in case we haven't been drawn for a while.
+                for _ in 0..frames.count() {
+                    let delay = frames.delay(animation.frame_index);
+                    if now.duration_since(animation.frame_started) < delay {
+                        break;
+                    }
+                    animation.frame_started += delay;
+                    if animation.frame_index + 1 < frames.count() {
+                        animation.frame_index += 1;
+                    } else {
+                        animation.loops_completed += 1;
+                        if animation.is_finished(frames) {
+                            break;
+                        }
+                        animation.frame_index = 0;
+                    }
+                }
+                if now.duration_since(animation.frame_started)
+                    >= frames.delay(animation.frame_index)
+                {
+                    animation.frame_started = now;
+                }
+
+                if !animation.is_finished(frames) {
+                    animation.schedule_next_frame(frames, now, cx);
+                }
+            }
+
+            (animation.frame_index, Some(state))
+        })
+    }
+
+    fn notify_load_listeners(
+        &mut self,
+        global_id: Option<&GlobalElementId>,
         cx: &mut WindowContext,
     ) {
-        let source = self.source.clone();
-        self.interactivity
-            .paint(global_id, bounds, hitbox.as_ref(), cx, |style, cx| {
-                let corner_radii = style.corner_radii.to_pixels(bounds.size, cx.rem_size());
+        // Any pending load was already requested, and guarded if possible, during layout.
+        let (result, _) =
+            cx.with_asset_load_guards(|cx| self.source.use_data(&self.decode_options, cx));
+        let Some(result) = result else {
+            return;
+        };
+        let outcome = match &result {
+            Ok(data) => LoadOutcome::Loaded(data.id),
+            Err(_) => LoadOutcome::Failed(hash(&self.source.uri_or_path())),
+        };
+
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, cx| {
+            // Without an id, there's nowhere to remember what was already reported.
+            let Some(state) = state else {
+                return ((), None);
+            };
+            let mut state = state.unwrap_or_default();
+
+            if state.last_outcome != Some(outcome) {
+                match &result {
+                    Ok(data) => {
+                        if let Some(on_load) = self.on_load.as_ref() {
+                            on_load(data, cx);
+                        }
+                    }
+                    Err(error) => {
+                        if let Some(on_error) = self.on_error.as_ref() {
+                            on_error(error, cx);
+                        }
+                    }
+                }
+                state.last_outcome = Some(outcome);
+            }
+
+            ((), Some(state))
+        });
+    }
+
+    fn notify_progress_listener(
+        &mut self,
+        global_id: Option<&GlobalElementId>,
+        cx: &mut WindowContext,
+    ) {
+        let Some(on_progress) = self.on_progress.as_ref() else {
+            return;
+        };
+        let Some(source) = self.source.uri_or_path() else {
+            return;
+        };
+        let key = hash(&source);
+        let Some(progress) = cx.image_load_progress.get(key) else {
+            return;
+        };
+
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, cx| {
+            let mut state = state.map(|state| state.unwrap_or_default());
+            let already_notified = state
+                .as_ref()
+                .map_or(false, |state| state.last_progress == Some(progress));
+            if !already_notified {
+                on_progress(progress.loaded, progress.total, cx);
+                if let Some(state) = state.as_mut() {
+                    state.last_progress = Some(progress);
+                }
+            }
+
+            // Downloads don't notify the window as they progress, so redraw when this one
+            // progresses again.
+            let changed = cx.image_load_progress.changed(key);
+            let parent_id = cx.parent_view_id();
+            let task = cx.spawn(|mut cx| async move {
+                if changed.await.is_ok() {
+                    cx.update(|cx| {
+                        if let Some(parent_id) = parent_id {
+                            cx.notify(parent_id)
+                        } else {
+                            cx.refresh()
+                        }
+                    })
+                    .ok();
+                }
+            });
+            match state.as_mut() {
+                Some(state) => state.progress_update = Some(task),
+                None => task.detach(),
+            }
+
+            ((), state)
+        });
+    }
+
+    /// Set where the image is placed within the element when it doesn't fill it exactly,
+    /// as normalized coordinates. `(0.5, 0.5)` centers the image, which is the default.
+    pub fn object_position(mut self, x: f32, y: f32) -> Self {
+        self.object_position = point(x, y);
+        self
+    }
+
+    /// Keep the given point of the image visible when [`ObjectFit::Cover`] crops it, such
+    /// as a detected face, as normalized coordinates of the drawn image. The crop is moved
+    /// towards the point as far as it can while the image still covers the element; see
+    /// [`ObjectFit::get_bounds_with_focal_point`]. Takes precedence over
+    /// [`Img::object_position`] for `Cover`.
+    pub fn focal_point(mut self, focal_point: Point<f32>) -> Self {
+        self.focal_point = Some(focal_point);
+        self
+    }
+
+    /// Round the bounds the image is painted at to whole device pixels, so that images
+    /// landing between pixels, such as in flex layouts or at fractional scale factors,
+    /// aren't softened by filtering. Snapping suits icons and other crisp images, but
+    /// makes images that move smoothly, such as during an animation, jitter from pixel to
+    /// pixel instead. Defaults to on for images with a [`SvgQuality::Crisp`] quality and
+    /// off otherwise.
+    pub fn pixel_snap(mut self, pixel_snap: bool) -> Self {
+        self.pixel_snap = Some(pixel_snap);
+        self
+    }
+
+    /// Draw only the given region of the image, in its pixels, such as one cell of a
+    /// sprite sheet. The region is sized and fitted as if it were the whole image.
+    pub fn source_rect(mut self, rect: Bounds<DevicePixels>) -> Self {
+        self.source_rect = Some(rect);
+        self
+    }
+
+    /// The natural size of what the element paints, before it's fitted into the element's
+    /// bounds. Images are measured by the region of them that's drawn, after any rotation,
+    /// and surfaces by their whole buffer.
+    fn content_size(&self, data: Option<&ImageData>) -> Option<Size<DevicePixels>> {
+        if let Some(data) = data {
+            return Some(self.rotation.rotate_size(self.source_region(data).size));
+        }
+        match &self.source {
+            ImageSource::Surface(surface) => Some(surface.size()),
+            _ => None,
+        }
+    }
+
+    /// Where content of the given size is painted within the element's bounds, according
+    /// to its object fit and position. Images and surfaces are framed the same way, so
+    /// switching between them doesn't move the content.
+    fn content_bounds(
+        &self,
+        bounds: Bounds<Pixels>,
+        content_size: Size<DevicePixels>,
+    ) -> Bounds<Pixels> {
+        match (self.object_fit, self.focal_point) {
+            (ObjectFit::Cover, Some(focal_point)) => {
+                self.object_fit
+                    .get_bounds_with_focal_point(bounds, content_size, focal_point)
+            }
+            _ => {
+                self.object_fit
+                    .get_bounds_with_position(bounds, content_size, self.object_position)
+            }
+        }
+    }
+
+    /// Whether the image is painted at whole device pixels. See [`Img::pixel_snap`].
+    fn snaps_to_pixels(&self) -> bool {
+        self.pixel_snap
+            .unwrap_or(self.decode_options.svg_quality == Some(SvgQuality::Crisp))
+    }
+
+    /// The color adjustments this image is painted with.
+    fn paint_params(&self) -> ImagePaintParams {
+        ImagePaintParams {
+            desaturation: self.desaturation,
+            tint: self.tint,
+            opacity: self.opacity,
+            blend_mode: self.blend_mode,
+            sampling: self.sampling,
+            ..ImagePaintParams::default()
+        }
+    }
+
+    /// The region of the image to draw, clamped to the image's bounds.
+    fn source_region(&self, data: &ImageData) -> Bounds<DevicePixels> {
+        self.source_region_for(data.size())
+    }
+
+    fn source_region_for(&self, image_size: Size<DevicePixels>) -> Bounds<DevicePixels> {
+        let image = Bounds {
+            origin: Point::default(),
+            size: image_size,
+        };
+        self.source_rect
+            .map_or(image, |source_rect| source_rect.intersect(&image))
+    }
+
+    /// Map a point in the element, relative to its top left corner, to the point of the
+    /// image painted there, in the image's pixels. Takes the size of the element, such as
+    /// from its bounds in a mouse listener, and of the image, such as from
+    /// [`WindowContext::image_size`]. Accounts for the object fit and position, the source
+    /// rect, rotation and flips, and any transformation, but not for zooming. Returns `None`
+    /// for points where the image isn't painted, such as in the margins of a `Contain` fit.
+    pub fn image_position(
+        &self,
+        element_size: Size<Pixels>,
+        image_size: Size<DevicePixels>,
+        position: Point<Pixels>,
+    ) -> Option<Point<f32>> {
+        let bounds = Bounds {
+            origin: Point::default(),
+            size: element_size,
+        };
+        let position = match self.transformation {
+            Some(transformation) => transformation
+                .into_matrix(bounds.center(), 1.0)
+                .inverse()?
+                .apply(position),
+            None => position,
+        };
+        if !bounds.contains(&position) {
+            return None;
+        }
+
+        let region = self.source_region_for(image_size);
+        let unit_position = |bounds: Bounds<Pixels>| {
+            point(
+                (position.x - bounds.origin.x) / bounds.size.width,
+                (position.y - bounds.origin.y) / bounds.size.height,
+            )
+        };
+        let (source, unit) = if let ObjectFit::NineSlice {
+            top,
+            right,
+            bottom,
+            left,
+        } = self.object_fit
+        {
+            // Nine slice images are painted without rotation or flips.
+            let insets = Edges {
+                top,
+                right,
+                bottom,
+                left,
+            };
+            let slice = nine_slices(bounds, region.size, &insets)
+                .into_iter()
+                .find(|slice| slice.bounds.contains(&position))?;
+            let source = Bounds {
+                origin: region.origin + slice.source.origin,
+                size: slice.source.size,
+            };
+            (source, unit_position(slice.bounds))
+        } else {
+            let content_bounds =
+                self.content_bounds(bounds, self.rotation.rotate_size(region.size));
+            if !content_bounds.contains(&position) {
+                return None;
+            }
+            let unit = orient_unit_point(
+                unit_position(content_bounds),
+                self.rotation,
+                self.flip_horizontal,
+                self.flip_vertical,
+            );
+            (region, unit)
+        };
+        Some(point(
+            source.origin.x.0 as f32 + unit.x * source.size.width.0 as f32,
+            source.origin.y.0 as f32 + unit.y * source.size.height.0 as f32,
+        ))
+    }
+}
+
+/// Map a point in a painted image, in units of its size, to the point of the image drawn
+/// there, undoing its flips and then its rotation as the sprite shaders do.
+fn orient_unit_point(
+    unit: Point<f32>,
+    rotation: Rotation,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+) -> Point<f32> {
+    let x = if flip_horizontal { 1. - unit.x } else { unit.x };
+    let y = if flip_vertical { 1. - unit.y } else { unit.y };
+    match rotation {
+        Rotation::None => point(x, y),
+        Rotation::Clockwise90 => point(y, 1. - x),
+        Rotation::Clockwise180 => point(1. - x, 1. - y),
+        Rotation::Clockwise270 => point(1. - y, x),
+    }
+}
+
+impl Element for Img {
+    type RequestLayoutState = Option<AnyElement>;
+    type PrepaintState = Option<Hitbox>;
+
+    fn id(&self) -> Option<ElementId> {
+        self.interactivity.element_id.clone()
+    }
+
+    fn request_layout(
+        &mut self,
+        global_id: Option<&GlobalElementId>,
+        cx: &mut WindowContext,
+    ) -> (LayoutId, Self::RequestLayoutState) {
+        // Sources are chosen once the element's size is known, when it's prepainted, so until
+        // then there's no telling which of them to load.
+        let unselected = !self.sources.is_empty()
+            && match self.selected_source(global_id, cx) {
+                Some(source) => {
+                    self.source = source;
+                    false
+                }
+                None => true,
+            };
+        if !unselected {
+            self.select_ico_size(global_id, cx);
+        }
+        let (data, vector_size) = if unselected {
+            (None, None)
+        } else {
+            self.data(global_id, cx)
+        };
+        let mut replacement = if unselected {
+            None
+        } else {
+            self.replacement_element(cx)
+        };
+        let image_size = vector_size
+            .or_else(|| {
+                let data = data?;
+                let size = self.source_region(&data).size;
+                Some(crate::size(
+                    px(size.width.0 as f32),
+                    px(size.height.0 as f32),
+                ))
+            })
+            .filter(|_| replacement.is_none());
+        let aspect_ratio = self.aspect_ratio;
+        let fit_axis = self.fit_axis;
+        let layout_id = self
+            .interactivity
+            .request_layout(global_id, cx, |mut style, cx| {
+                if let Some(aspect_ratio) = aspect_ratio {
+                    style.aspect_ratio.get_or_insert(aspect_ratio);
+                }
+
+                if let Some(axis) = fit_axis {
+                    let natural_ratio = image_size
+                        .map(|size| size.width.0 / size.height.0)
+                        .filter(|ratio| ratio.is_finite() && *ratio > 0.0);
+                    fit_to_axis(&mut style, axis, natural_ratio);
+                } else if let Some(image_size) = image_size {
+                    match (style.size.width, style.size.height) {
+                        // Let the declared ratio derive the height from the natural width.
+                        (Length::Auto, Length::Auto) if aspect_ratio.is_some() => {
+                            style.size.width = Length::Definite(DefiniteLength::Absolute(
+                                AbsoluteLength::Pixels(image_size.width),
+                            ));
+                        }
+                        (Length::Auto, Length::Auto) => {
+                            style.size = Size {
+                                width: Length::Definite(DefiniteLength::Absolute(
+                                    AbsoluteLength::Pixels(image_size.width),
+                                )),
+                                height: Length::Definite(DefiniteLength::Absolute(
+                                    AbsoluteLength::Pixels(image_size.height),
+                                )),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                let child_layout_id = replacement
+                    .as_mut()
+                    .map(|element| element.request_layout(cx));
+                cx.request_layout(&style, child_layout_id)
+            });
+        (layout_id, replacement)
+    }
+
+    fn prepaint(
+        &mut self,
+        global_id: Option<&GlobalElementId>,
+        bounds: Bounds<Pixels>,
+        replacement: &mut Self::RequestLayoutState,
+        cx: &mut WindowContext,
+    ) -> Option<Hitbox> {
+        let scale_factor = cx.scale_factor();
+        let device_size = bounds
+            .size
+            .map(|pixels| DevicePixels((pixels.0 * scale_factor).ceil() as i32));
+        let selected_source = self.select_source(device_size, cx);
+        if let Some(source) = &selected_source {
+            self.source = source.clone();
+        }
+        cx.with_optional_element_state::<ImgState, _>(global_id, |state, _| {
+            let mut state = state.map(Option::unwrap_or_default);
+            if let Some(state) = state.as_mut() {
+                state.device_size = Some(device_size);
+                state.selected_source = selected_source;
+            }
+            ((), state)
+        });
+        self.update_load_priority(bounds, cx);
+        if self.on_load.is_some() || self.on_error.is_some() {
+            self.notify_load_listeners(global_id, cx);
+        }
+        if self.on_progress.is_some() {
+            self.notify_progress_listener(global_id, cx);
+        }
+
+        let transformation = self
+            .transformation
+            .filter(|_| replacement.is_none())
+            .map(|transformation| transformation.into_matrix(bounds.center(), 1.0))
+            .unwrap_or_default();
+        self.interactivity.accessibility_label = self.accessibility_label().cloned();
+        cx.with_hitbox_transformation(transformation, |cx| {
+            self.interactivity
+                .prepaint(global_id, bounds, bounds.size, cx, |_, _, hitbox, cx| {
+                    if let Some(element) = replacement {
+                        element.prepaint(cx);
+                    }
+                    hitbox
+                })
+        })
+    }
+
+    fn paint(
+        &mut self,
+        global_id: Option<&GlobalElementId>,
+        bounds: Bounds<Pixels>,
+        replacement: &mut Self::RequestLayoutState,
+        hitbox: &mut Self::PrepaintState,
+        cx: &mut WindowContext,
+    ) {
+        if let Some(element) = replacement {
+            self.interactivity
+                .paint(global_id, bounds, hitbox.as_ref(), cx, |_, cx| {
+                    element.paint(cx)
+                });
+            return;
+        }
+
+        let source = self.source.clone();
+        let (data, _) = self.data(global_id, cx);
+        // Frames given separately are each painted as an image of their own, rather than
+        // being copied into a single animated image.
+        let animation = source.animated_data().filter(
+            |animation| matches!(&data, Some(data) if Arc::ptr_eq(data, &animation.frames[0])),
+        );
+        let (data, frame_index) = match animation {
+            Some(animation) => {
+                let frame_index = self.current_frame(global_id, Frames::Separate(animation), cx);
+                (Some(animation.frames[frame_index].clone()), 0)
+            }
+            None => {
+                let frame_index = data.as_ref().map_or(0, |data| {
+                    self.current_frame(global_id, Frames::Decoded(data), cx)
+                });
+                (data, frame_index)
+            }
+        };
+        let content_bounds = self
+            .content_size(data.as_deref())
+            .map(|content_size| self.content_bounds(bounds, content_size))
+            .map(|content_bounds| {
+                if self.snaps_to_pixels() {
+                    snap_to_device_pixels(content_bounds, cx.scale_factor())
+                } else {
+                    content_bounds
+                }
+            });
+        let zoom = content_bounds
+            .filter(|_| !matches!(self.object_fit, ObjectFit::NineSlice { .. }))
+            .and_then(|content_bounds| self.zoom_state(global_id, bounds, content_bounds, cx));
+        let content_bounds = match &zoom {
+            Some(zoom) => content_bounds.map(|content_bounds| zoom.borrow().apply(content_bounds)),
+            None => content_bounds,
+        };
+        if let (Some(zoom), Some(config), Some(hitbox)) = (&zoom, self.zoom, hitbox.as_ref()) {
+            paint_zoom_listeners(zoom, config, self.on_zoom.take(), hitbox, cx);
+        }
+        let data = data.map(|data| {
+            let region = self.source_region(&data);
+            (data, region)
+        });
+        let transformation = self
+            .transformation
+            .map(|transformation| transformation.into_matrix(bounds.center(), cx.scale_factor()))
+            .unwrap_or_default();
+        let transparency_grid = self
+            .transparency_grid
+            .filter(|_| self.transformation.is_none());
+        self.interactivity
+            .paint(global_id, bounds, hitbox.as_ref(), cx, |style, cx| {
+                let corner_radii = style.corner_radii.to_pixels(bounds.size, cx.rem_size());
+                // Without a clip shape, the image's own bounds are rounded by the corner radii,
+                // except for images at their natural size, which are clipped to the element.
+                let clip = |image_bounds: Bounds<Pixels>, corner_radii: Corners<Pixels>| match self
+                    .clip_shape
+                {
+                    Some(clip_shape) => (bounds, clip_shape),
+                    None if self.object_fit == ObjectFit::None || zoom.is_some() => {
+                        (bounds, ImageClip::RoundedRect(corner_radii))
+                    }
+                    None => (image_bounds, ImageClip::RoundedRect(corner_radii)),
+                };
+                let paint_grid = |image_bounds: Bounds<Pixels>, cx: &mut WindowContext| {
+                    let Some(grid) = transparency_grid else {
+                        return;
+                    };
+                    let (clip_bounds, clip) = clip(image_bounds, corner_radii);
+                    let (grid_bounds, grid_radii) = clip.rounded_rect(clip_bounds);
+                    cx.with_content_mask(
+                        Some(ContentMask {
+                            bounds: image_bounds,
+                        }),
+                        |cx| grid.paint(grid_bounds, grid_radii, self.opacity, cx),
+                    );
+                };
+
+                if let Some((data, region)) = data {
+                    if let ObjectFit::NineSlice {
+                        top,
+                        right,
+                        bottom,
+                        left,
+                    } = self.object_fit
+                    {
+                        let insets = Edges {
+                            top,
+                            right,
+                            bottom,
+                            left,
+                        };
+                        paint_grid(bounds, cx);
+                        for slice in nine_slices(bounds, region.size, &insets) {
+                            let source = Bounds {
+                                origin: region.origin + slice.source.origin,
+                                size: slice.source.size,
+                            };
+                            let (clip_bounds, clip) =
+                                clip(slice.bounds, slice.corner_radii(&corner_radii));
+                            cx.paint_image(
+                                slice.bounds,
+                                data.clone(),
+                                ImagePaintParams {
+                                    frame_index,
+                                    source: Some(source),
+                                    clip,
+                                    clip_bounds: Some(clip_bounds),
+                                    transformation,
+                                    ..self.paint_params()
+                                },
+                            )
+                            .log_err();
+                        }
+                    } else if let Some(new_bounds) = content_bounds {
+                        paint_grid(new_bounds, cx);
+                        let (clip_bounds, clip) = clip(new_bounds, corner_radii);
+                        cx.paint_image(
+                            new_bounds,
+                            data,
+                            ImagePaintParams {
+                                frame_index,
+                                source: self.source_rect.map(|_| region),
+                                clip,
+                                clip_bounds: Some(clip_bounds),
+                                rotation: self.rotation,
+                                flip_horizontal: self.flip_horizontal,
+                                flip_vertical: self.flip_vertical,
+                                transformation,
+                                ..self.paint_params()
+                            },
+                        )
+                        .log_err();
+                    }
+                }
+
+                match source {
+                    #[cfg(target_os = "macos")]
+                    ImageSource::Surface(Surface::CoreVideo(image_buffer)) => {
+                        if let Some(new_bounds) = content_bounds {
+                            // TODO: Add support for desaturation.
+                            let (clip_bounds, clip) = clip(new_bounds, corner_radii);
+                            cx.paint_surface(new_bounds, clip_bounds, clip, image_buffer);
+                        }
+                    }
+                    _ => {}
+                }
+            })
+    }
+}
+
+impl IntoElement for Img {
+    type Element = Self;
+
+    fn into_element(self) -> Self::Element {
+        self
+    }
+}
+
+impl Styled for Img {
+    fn style(&mut self) -> &mut StyleRefinement {
+        &mut self.interactivity.base_style
+    }
+}
+
+impl InteractiveElement for Img {
+    fn interactivity(&mut self) -> &mut Interactivity {
+        &mut self.interactivity
+    }
+}
+
+impl ImageSource {
+    fn uri_or_path(&self) -> Option<UriOrPath> {
+        match self {
+            ImageSource::Uri { uri, options } => Some(UriOrPath::Uri {
+                uri: uri.clone(),
+                options: options.clone(),
+            }),
+            ImageSource::UriPage { uri, .. } => Some(uri.clone().into()),
+            ImageSource::File(path) => Some(path.clone().into()),
+            ImageSource::Asset(path) => Some(UriOrPath::Asset(path.clone())),
+            ImageSource::Bytes(bytes) => Some(UriOrPath::Bytes(bytes.clone())),
+            ImageSource::Reader(reader) => Some(UriOrPath::Reader(reader.clone())),
+            ImageSource::Filtered { source, .. } => source.uri_or_path(),
+            ImageSource::Scoped { scope, source } => {
+                Some(source.uri_or_path()?.scoped(scope.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn asset_source(&self, options: &DecodeOptions) -> Option<ImageAssetSource> {
+        let mut options = options.clone();
+        match self {
+            ImageSource::UriPage { page, .. } => options.page = Some(*page),
+            ImageSource::Filtered { source, filter } => {
+                options.decode_filter = Some(filter.clone());
+                return source.asset_source(&options);
+            }
+            ImageSource::Scoped { scope, source } => {
+                let source = source.asset_source(&options)?;
+                return Some(ImageAssetSource {
+                    source: source.source.scoped(scope.clone()),
+                    options: source.options,
+                });
+            }
+            _ => {}
+        }
+        Some(ImageAssetSource {
+            source: self.uri_or_path()?,
+            options,
+        })
+    }
+
+    fn use_cached_data(
+        &self,
+        options: &DecodeOptions,
+        cx: &mut WindowContext,
+    ) -> Option<Result<Arc<ImageData>, ImageCacheError>> {
+        if let Some(ttl) = self.ttl() {
+            return self.use_expiring_data(ttl, options, cx);
+        }
+
+        match self {
+            ImageSource::Uri { .. }
+            | ImageSource::UriPage { .. }
+            | ImageSource::File(_)
+            | ImageSource::Asset(_)
+            | ImageSource::Bytes(_)
+            | ImageSource::Reader(_) => cx.use_cached_asset::<Image>(&self.asset_source(options)?),
+
+            ImageSource::Filtered { source, .. } | ImageSource::Scoped { source, .. } => {
+                match self.asset_source(options) {
+                    Some(asset_source) => cx.use_cached_asset::<Image>(&asset_source),
+                    None => source.use_cached_data(options, cx),
+                }
+            }
+
+            ImageSource::Data(data) => Some(Ok(data.to_owned())),
+            ImageSource::AnimatedData(animation) => Some(Ok(animation.frames[0].clone())),
+            ImageSource::Surface(Surface::Raw(frame)) => frame.use_image_data(cx).map(Ok),
+            #[cfg(target_os = "macos")]
+            ImageSource::Surface(Surface::CoreVideo(_)) => None,
+        }
+    }
+
+    /// The separately given frames this source plays, if it's an
+    /// [`ImageSource::AnimatedData`], which scopes and filters don't apply to.
+    fn animated_data(&self) -> Option<&AnimatedImageData> {
+        match self {
+            ImageSource::AnimatedData(animation) => Some(animation.as_ref()),
+            ImageSource::Filtered { source, .. } | ImageSource::Scoped { source, .. } => {
+                source.animated_data()
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this source paints a [`Surface::Raw`], which scopes and filters don't apply
+    /// to.
+    fn is_raw_surface(&self) -> bool {
+        match self {
+            ImageSource::Surface(Surface::Raw(_)) => true,
+            ImageSource::Filtered { source, .. } | ImageSource::Scoped { source, .. } => {
+                source.is_raw_surface()
+            }
+            _ => false,
+        }
+    }
+
+    /// How long this image is cached for before it's fetched again, if it expires. See
+    /// [`ImageSource::uri_with_ttl`].
+    fn ttl(&self) -> Option<Duration> {
+        match self {
+            ImageSource::Uri { options, .. } => options.ttl,
+            ImageSource::Filtered { source, .. } | ImageSource::Scoped { source, .. } => {
+                source.ttl()
+            }
+            _ => None,
+        }
+    }
+
+    fn use_expiring_data(
+        &self,
+        ttl: Duration,
+        options: &DecodeOptions,
+        cx: &mut WindowContext,
+    ) -> Option<Result<Arc<ImageData>, ImageCacheError>> {
+        let source = self.asset_source(options)?;
+        let Some(cached) = cx.asset_cache.get::<Image>(&source) else {
+            return cx.use_cached_asset::<Image>(&source);
+        };
+        // When the image was cached is forgotten along with it once it's evicted.
+        let loaded_at = cx.asset_cache.inserted_at::<Image>(&source)?;
+        if loaded_at.elapsed() < ttl {
+            return Some(cached);
+        }
+
+        // Keep showing the cached image until it's been loaded again.
+        let Some(result) = cx.use_asset::<Image>(&source) else {
+            return Some(cached);
+        };
+        // Caching the image again restarts its time to live, even if it couldn't be refreshed.
+        if result.is_err() && cached.is_ok() {
+            cx.asset_cache.insert::<Image>(source, cached.clone());
+            return Some(cached);
+        }
+        cx.asset_cache.insert::<Image>(source, result.clone());
+        Some(result)
+    }
+
+    /// Load the intrinsic size of this image. See [`WindowContext::image_size`].
+    pub(crate) fn size(
+        &self,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Size<DevicePixels>, ImageCacheError>> {
+        let source = match self {
+            ImageSource::Filtered { source, .. } => return source.size(cx),
+            // Images that are already in memory aren't cached in a scope.
+            ImageSource::Scoped { source, .. } if self.uri_or_path().is_none() => {
+                return source.size(cx)
+            }
+            ImageSource::Data(data) => return Task::ready(Ok(data.size())),
+            ImageSource::AnimatedData(animation) => {
+                return Task::ready(Ok(animation.frames[0].size()))
+            }
+            ImageSource::Surface(surface) => return Task::ready(Ok(surface.size())),
+            _ => match self.uri_or_path() {
+                Some(source) => source,
+                None => unreachable!("every other image source has a uri or path"),
+            },
+        };
+
+        let load = load_image_asset::<ImageSize, _>(source, cx);
+        cx.background_executor()
+            .spawn(async move { load.await.map(|size| size.size) })
+    }
+
+    /// Make a thumbnail of this image. See [`WindowContext::image_thumbnail`].
+    pub(crate) fn thumbnail(
+        &self,
+        size: Size<DevicePixels>,
+        object_fit: ObjectFit,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Arc<ImageData>, ImageCacheError>> {
+        if size.width.0 <= 0 || size.height.0 <= 0 {
+            return Task::ready(Err(ImageCacheError::RenderFailed(
+                format!("can't make a {}x{} thumbnail", size.width.0, size.height.0).into(),
+            )));
+        }
+
+        let source = match self {
+            // Thumbnails are made from the unfiltered image.
+            ImageSource::Filtered { source, .. } => return source.thumbnail(size, object_fit, cx),
+            ImageSource::Scoped { source, .. } if self.uri_or_path().is_none() => {
+                return source.thumbnail(size, object_fit, cx)
+            }
+            // Images that are already in memory are scaled without being cached again.
+            ImageSource::Data(data) => {
+                let data = data.clone();
+                return cx
+                    .background_executor()
+                    .spawn(async move { thumbnail_from_data(&data, size, object_fit) });
+            }
+            ImageSource::AnimatedData(animation) => {
+                let data = animation.frames[0].clone();
+                return cx
+                    .background_executor()
+                    .spawn(async move { thumbnail_from_data(&data, size, object_fit) });
+            }
+            ImageSource::Surface(Surface::Raw(frame)) => {
+                let frame = frame.clone();
+                return cx.background_executor().spawn(async move {
+                    thumbnail_from_data(&frame.image_data(), size, object_fit)
+                });
+            }
+            #[cfg(target_os = "macos")]
+            ImageSource::Surface(Surface::CoreVideo(_)) => {
+                return Task::ready(Err(ImageCacheError::RenderFailed(
+                    "can't make a thumbnail of a surface".into(),
+                )))
+            }
+            _ => match self.uri_or_path() {
+                Some(source) => ThumbnailSource {
+                    source,
+                    size,
+                    object_fit,
+                },
+                None => unreachable!("every other image source has a uri or path"),
+            },
+        };
+
+        load_image_asset::<ImageThumbnail, _>(source, cx)
+    }
+
+    /// Extract the frame of this image shown at `time` into the animation. See
+    /// [`WindowContext::image_frame_at`].
+    pub(crate) fn frame_at(
+        &self,
+        time: Duration,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Arc<ImageData>, ImageCacheError>> {
+        let Some(source) = self.asset_source(&DecodeOptions::default()) else {
+            let data = match self {
+                ImageSource::Data(data) => data.clone(),
+                ImageSource::AnimatedData(animation) => {
+                    let frame_index = frame_index_at(Frames::Separate(animation), time);
+                    return Task::ready(Ok(animation.frames[frame_index].clone()));
+                }
+                ImageSource::Surface(Surface::Raw(frame)) => {
+                    let frame = frame.clone();
+                    return cx
+                        .background_executor()
+                        .spawn(async move { Ok(frame.image_data()) });
+                }
+                // Filters only apply to images that are decoded from a uri or path.
+                ImageSource::Filtered { source, .. } | ImageSource::Scoped { source, .. } => {
+                    return source.frame_at(time, cx)
+                }
+                #[cfg(target_os = "macos")]
+                ImageSource::Surface(Surface::CoreVideo(_)) => {
+                    return Task::ready(Err(ImageCacheError::RenderFailed(
+                        "can't extract a frame of a surface".into(),
+                    )))
+                }
+                _ => unreachable!("every other image source has a uri or path"),
+            };
+            return cx.background_executor().spawn(async move {
+                let frame_index = frame_index_at(Frames::Decoded(&data), time);
+                Ok(Arc::new(data.single_frame(frame_index)))
+            });
+        };
+
+        // Once the whole animation has been decoded, the frame is taken from it.
+        if let Some(animation) = cx.asset_cache.get::<Image>(&source) {
+            let animation = match animation {
+                Ok(animation) => animation,
+                Err(error) => return Task::ready(Err(error)),
+            };
+            let source = FrameSource {
+                frame_index: frame_index_at(Frames::Decoded(&animation), time),
+                source,
+            };
+            if let Some(frame) = cx.asset_cache.get::<AnimationFrame>(&source) {
+                return Task::ready(frame);
+            }
+            let asset_cache = cx.asset_cache.clone();
+            return cx.background_executor().spawn(async move {
+                let frame = Ok(Arc::new(animation.single_frame(source.frame_index)));
+                asset_cache.insert::<AnimationFrame>(source, frame.clone());
+                frame
+            });
+        }
+
+        // Otherwise, the frame may have been extracted before.
+        let timing = cx.asset_cache.get::<AnimationTiming>(&source);
+        if let Some(frame_index) = timing.and_then(|timing| timing.frame_index_at(time)) {
+            let source = FrameSource {
+                source: source.clone(),
+                frame_index,
+            };
+            if let Some(frame) = cx.asset_cache.get::<AnimationFrame>(&source) {
+                return Task::ready(frame);
+            }
+        }
+
+        // If not, the animation is decoded a frame at a time, only up to the frame shown at
+        // `time`, and without keeping the frames before it.
+        let reader = EncodedImageReader::new(cx);
+        let decode_queue = cx.image_decode_queue();
+        let max_pixels = cx.image_max_pixels();
+        let asset_cache = cx.asset_cache.clone();
+        let decode: Task<Result<Option<Arc<ImageData>>, ImageCacheError>> =
+            cx.background_executor().spawn({
+                let source = source.clone();
+                async move {
+                    let EncodedImage { bytes, format, .. } =
+                        reader.read(source.source.clone()).await?;
+                    let Some(format) = format else {
+                        return Ok(None);
+                    };
+                    let _permit = decode_queue.acquire(hash(&source)).await;
+                    let Some((frame_index, frame, timing)) =
+                        decode_animation_frame(&bytes, format, max_pixels, |timing| {
+                            timing.frame_index_at(time)
+                        })?
+                    else {
+                        return Ok(None);
+                    };
+
+                    let (color_space, icc_profile) = read_color_space(&bytes, format);
+                    let frame = Arc::new(
+                        frame
+                            .with_color_space(color_space, icc_profile)
+                            .with_source_format(format),
+                    );
+                    asset_cache.insert::<AnimationFrame>(
+                        FrameSource {
+                            source: source.clone(),
+                            frame_index,
+                        },
+                        Ok(frame.clone()),
+                    );
+                    // Keep whichever timing knows the most frames, in case the same animation
+                    // was decoded further in the meantime.
+                    let known = asset_cache.get::<AnimationTiming>(&source);
+                    let progress =
+                        |timing: &FrameTiming| (timing.loop_count.is_some(), timing.delays.len());
+                    if known.map_or(true, |known| progress(&known) < progress(&timing)) {
+                        asset_cache.insert::<AnimationTiming>(source, Arc::new(timing));
+                    }
+                    Ok(Some(frame))
+                }
+            });
+        cx.spawn(|mut cx| async move {
+            if let Some(frame) = decode.await? {
+                return Ok(frame);
+            }
+            // Still images and SVGs are loaded as usual, since their only frame is the
+            // whole image.
+            let image = cx
+                .update(|cx| load_image(source, cx))
+                .map_err(|_| ImageCacheError::RenderFailed("the window was closed".into()))?
+                .await?;
+            Ok(Arc::new(image.single_frame(0)))
+        })
+    }
+
+    /// Load and cache this image without drawing it. See [`WindowContext::prefetch_image`].
+    pub(crate) fn prefetch(&self, cx: &mut WindowContext) -> Task<Result<(), ImageCacheError>> {
+        // Images that are already in memory have nothing to load.
+        let Some(source) = self.asset_source(&DecodeOptions::default()) else {
+            return Task::ready(Ok(()));
+        };
+        let load = load_image(source, cx);
+        cx.background_executor()
+            .spawn(async move { load.await.map(|_| ()) })
+    }
+
+    fn data(&self, options: &DecodeOptions, cx: &mut WindowContext) -> Option<Arc<ImageData>> {
+        self.use_data(options, cx)?.log_err()
+    }
+
+    fn use_data(
+        &self,
+        options: &DecodeOptions,
+        cx: &mut WindowContext,
+    ) -> Option<Result<Arc<ImageData>, ImageCacheError>> {
+        let result = self.use_cached_data(options, cx)?;
+        if let Err(error) = &result {
+            if error.is_transient() {
+                if let Some(source) = self.asset_source(options) {
+                    retry_after_cooldown(source, cx);
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+/// How long an image that failed with a transient error keeps showing the error before it's
+/// fetched again.
+const TRANSIENT_ERROR_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Don't let a transient failure poison the cache. Once the failure is older than
+/// [`TRANSIENT_ERROR_COOLDOWN`], the entry is evicted after this frame so the next time the
+/// image is drawn, it's fetched again. Until then, the failure is kept, so a server that keeps
+/// failing isn't asked again on every frame.
+fn retry_after_cooldown(source: ImageAssetSource, cx: &mut WindowContext) {
+    // The failure is cached along with when it happened, so it's forgotten when it's evicted.
+    let Some(failed_at) = cx.asset_cache.inserted_at::<Image>(&source) else {
+        return;
+    };
+    let elapsed = failed_at.elapsed();
+    if elapsed >= TRANSIENT_ERROR_COOLDOWN {
+        cx.on_next_frame(move |cx| {
+            cx.remove_cached_asset::<Image>(&source);
+        });
+        return;
+    }
+
+    // Draw again once the cooldown has passed, so the image is fetched again then.
+    let key = hash(&source);
+    if !cx.image_retries.insert(key) {
+        return;
+    }
+    let parent_id = cx.parent_view_id();
+    cx.spawn(|mut cx| async move {
+        cx.background_executor()
+            .timer(TRANSIENT_ERROR_COOLDOWN - elapsed)
+            .await;
+        AsyncAppContext::update(&cx, |cx| cx.image_retries.remove(&key)).ok();
+        cx.update(|cx| {
+            if let Some(parent_id) = parent_id {
+                cx.notify(parent_id)
+            } else {
+                cx.refresh()
+            }
+        })
+        .ok();
+    })
+    .detach();
+}
+
+/// Load an image into the asset cache, sharing the load with any element that's already
+/// waiting for the same image.
+fn load_image(
+    source: ImageAssetSource,
+    cx: &mut WindowContext,
+) -> Task<Result<Arc<ImageData>, ImageCacheError>> {
+    load_image_asset::<Image, _>(source, cx)
+}
+
+/// Load an image, or something derived from one like its size, into the asset cache,
+/// sharing the load with anything else that's already waiting for it. Transient failures
+/// aren't cached, so the next request tries again.
+fn load_image_asset<A, T>(
+    source: A::Source,
+    cx: &mut WindowContext,
+) -> Task<Result<T, ImageCacheError>>
+where
+    A: Asset<Output = Result<T, ImageCacheError>> + 'static,
+    A::Source: 'static,
+    T: Clone + Send + 'static,
+{
+    if let Some(result) = cx.asset_cache.get::<A>(&source) {
+        return Task::ready(result);
+    }
+
+    let asset_id = (TypeId::of::<A>(), hash(&source));
+    let mut loading_asset = cx.loading_assets.remove(&asset_id).unwrap_or_else(|| {
+        let load = A::load(source.clone(), cx);
+        LoadingAsset::new(Box::new(cx.background_executor().spawn(load).shared()))
+    });
+    loading_asset.pin();
+    let task = loading_asset
+        .task
+        .downcast_ref::<Shared<Task<A::Output>>>()
+        .unwrap()
+        .clone();
+    cx.loading_assets.insert(asset_id, loading_asset);
+
+    cx.spawn(|mut cx| async move {
+        let result = task.await;
+        cx.update(|cx| {
+            cx.loading_assets.remove(&asset_id);
+            if !matches!(&result, Err(error) if error.is_transient()) {
+                cx.asset_cache.insert::<A>(source, result.clone());
+            }
+        })
+        .ok();
+        result
+    })
+}
+
+/// A stream of encoded image content, identified by an id. See [`ImageSource::reader`].
+#[derive(Clone)]
+pub struct ImageReader {
+    pub(crate) id: SharedString,
+    open: Arc<dyn Fn() -> Pin<Box<dyn AsyncRead + Send>> + Send + Sync>,
+}
+
+impl PartialEq for ImageReader {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for ImageReader {}
+
+impl Hash for ImageReader {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl fmt::Debug for ImageReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageReader").field("id", &self.id).finish()
+    }
+}
+
+/// A transform applied to the pixels of an image once it's been decoded, identified by
+/// its [`Arc`]. See [`ImageSource::with_decode_filter`].
+#[derive(Clone)]
+pub struct DecodeFilter(Arc<dyn Fn(&mut RgbaImage) + Send + Sync>);
+
+impl DecodeFilter {
+    fn id(&self) -> *const () {
+        Arc::as_ptr(&self.0) as *const ()
+    }
+
+    /// Apply the filter to a decoded frame, converting it to and from RGBA around it.
+    fn apply(&self, buffer: ImageBuffer<Bgra<u8>, Vec<u8>>) -> ImageBuffer<Bgra<u8>, Vec<u8>> {
+        let (width, height) = buffer.dimensions();
+        let mut pixels = buffer.into_raw();
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        let mut image =
+            RgbaImage::from_raw(width, height, pixels).expect("buffer size matches its dimensions");
+        (self.0)(&mut image);
+
+        let (width, height) = image.dimensions();
+        let mut pixels = image.into_raw();
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        ImageBuffer::from_raw(width, height, pixels).expect("image size matches its dimensions")
+    }
+}
+
+impl PartialEq for DecodeFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for DecodeFilter {}
+
+impl Hash for DecodeFilter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl fmt::Debug for DecodeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DecodeFilter").field(&self.id()).finish()
+    }
+}
+
+/// Options that affect how an image is decoded, and therefore how it's cached.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct DecodeOptions {
+    pub(crate) respect_exif_orientation: bool,
+    svg_color: Option<Hsla>,
+    svg_options: Option<SvgOptions>,
+    svg_quality: Option<SvgQuality>,
+    blur_radius: Option<Pixels>,
+    /// The bits of the amount passed to [`Img::sharpen`], so the options can be hashed.
+    sharpen_amount: Option<u32>,
+    pub(crate) max_decode_size: Option<Size<DevicePixels>>,
+    /// The single page or frame of the image to decode, rather than all of them.
+    page: Option<usize>,
+    /// The size of the image to decode from an ICO that holds several, chosen by the
+    /// element for the size it's displayed at. The largest is decoded if this is `None`.
+    ico_size: Option<Size<DevicePixels>>,
+    /// The transform applied to the decoded pixels of a filtered source.
+    decode_filter: Option<DecodeFilter>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            respect_exif_orientation: true,
+            svg_color: None,
+            svg_options: None,
+            svg_quality: None,
+            blur_radius: None,
+            sharpen_amount: None,
+            max_decode_size: None,
+            page: None,
+            ico_size: None,
+            decode_filter: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ImageAssetSource {
+    source: UriOrPath,
+    options: DecodeOptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ImageAlt {
+    Text(SharedString),
+    Decorative,
+}
+
+#[derive(Default)]
+struct ImgState {
+    last_outcome: Option<LoadOutcome>,
+    last_progress: Option<LoadProgress>,
+    /// Redraws the element when the download it's showing the progress of progresses.
+    progress_update: Option<Task<()>>,
+    animation: Option<AnimationState>,
+    raw_frame: Option<Arc<ImageData>>,
+    load_guards: Vec<AssetLoadGuard>,
+    /// The image last shown by an element with a minimum update interval, and when it was
+    /// first shown.
+    shown: Option<(Arc<ImageData>, Instant)>,
+    pending_update: Option<Task<()>>,
+    /// The size the element was last laid out at, in device pixels.
+    device_size: Option<Size<DevicePixels>>,
+    /// The one of the element's sources chosen for that size.
+    selected_source: Option<ImageSource>,
+    zoom: Option<Rc<RefCell<ZoomState>>>,
+}
+
+impl ImgState {
+    /// Show the given frame of a raw surface, releasing the previously shown one from the
+    /// sprite atlas once it's been replaced. While the frame is still being converted, the
+    /// previous one is shown in its place.
+    fn show_raw_frame(
+        &mut self,
+        data: Option<Arc<ImageData>>,
+        cx: &mut WindowContext,
+    ) -> Option<Arc<ImageData>> {
+        let Some(data) = data else {
+            return self.raw_frame.clone();
+        };
+        if let Some(previous) = self.raw_frame.replace(data.clone()) {
+            if previous.id != data.id {
+                cx.drop_image(&previous);
+            }
+        }
+        Some(data)
+    }
+
+    /// Keep showing the previous image until `interval` has passed since it was first
+    /// shown, redrawing once it has so that the newest image is shown.
+    fn throttle(
+        &mut self,
+        data: Arc<ImageData>,
+        interval: Duration,
+        cx: &mut WindowContext,
+    ) -> Arc<ImageData> {
+        let now = Instant::now();
+        if let Some((shown, shown_at)) = &self.shown {
+            if Arc::ptr_eq(shown, &data) {
+                return data;
+            }
+            let elapsed = now.duration_since(*shown_at);
+            if elapsed < interval {
+                if self.pending_update.is_none() {
+                    let parent_id = cx.parent_view_id();
+                    self.pending_update = Some(cx.spawn(|mut cx| async move {
+                        cx.background_executor().timer(interval - elapsed).await;
+                        cx.update(|cx| {
+                            if let Some(parent_id) = parent_id {
+                                cx.notify(parent_id)
+                            } else {
+                                cx.refresh()
+                            }
+                        })
+                        .ok();
+                    }));
+                }
+                return shown.clone();
+            }
+        }
+
+        self.shown = Some((data.clone(), now));
+        self.pending_update = None;
+        data
+    }
+}
+
+struct AnimationState {
+    image_id: ImageId,
+    frame_index: usize,
+    frame_started: Instant,
+    loops_completed: u32,
+    /// When the current frame ends, along with the timer that redraws the image then.
+    next_frame: Option<(Instant, Task<()>)>,
+}
+
+impl AnimationState {
+    fn is_finished(&self, frames: Frames) -> bool {
+        match frames.loop_count() {
+            LoopCount::Infinite => false,
+            LoopCount::Finite(count) => self.loops_completed >= count,
+        }
+    }
+
+    /// Redraw once the current frame's delay has passed, so that the next frame is shown
+    /// then. However often the image is drawn in the meantime, only one redraw is pending.
+    fn schedule_next_frame(&mut self, frames: Frames, now: Instant, cx: &mut WindowContext) {
+        let frame_ends = self.frame_started + frames.delay(self.frame_index);
+        if let Some((scheduled, _)) = &self.next_frame {
+            if *scheduled == frame_ends && now < frame_ends {
+                return;
+            }
+        }
+
+        let delay = frame_ends.saturating_duration_since(now);
+        let parent_id = cx.parent_view_id();
+        let task = cx.spawn(|mut cx| async move {
+            cx.background_executor().timer(delay).await;
+            cx.update(|cx| {
+                if let Some(parent_id) = parent_id {
+                    cx.notify(parent_id)
+                } else {
+                    cx.refresh()
+                }
+            })
+            .ok();
+        });
+        self.next_frame = Some((frame_ends, task));
+    }
+}
+
+/// Size `style` to fill its container along `axis`, with the other dimension derived from
+/// `natural_ratio`, or from the style's own aspect ratio if the image hasn't loaded yet.
+fn fit_to_axis(style: &mut Style, axis: Axis, natural_ratio: Option<f32>) {
+    let full = Length::Definite(DefiniteLength::Fraction(1.0));
+    style.size = match axis {
+        Axis::Horizontal => Size {
+            width: full,
+            height: Length::Auto,
+        },
+        Axis::Vertical => Size {
+            width: Length::Auto,
+            height: full,
+        },
+    };
+    if natural_ratio.is_some() {
+        style.aspect_ratio = natural_ratio;
+    }
+}
+
+/// Find the frame of an animation that's shown `time` after it starts playing, with the
+/// same frame delays as [`Img`] uses. Animations that stop after a number of loops stay on
+/// their last frame.
+fn frame_index_at(frames: Frames, time: Duration) -> usize {
+    frame_index_with_delays(
+        frames.count(),
+        |frame_index| frames.delay(frame_index),
+        frames.loop_count(),
+        time,
+    )
+}
+
+/// See [`frame_index_at`].
+fn frame_index_with_delays(
+    frame_count: usize,
+    delay: impl Fn(usize) -> Duration,
+    loop_count: LoopCount,
+    time: Duration,
+) -> usize {
+    let duration = (0..frame_count).map(&delay).sum::<Duration>();
+    if frame_count <= 1 || duration.is_zero() {
+        return 0;
+    }
+
+    let loops_completed = time.as_nanos() / duration.as_nanos();
+    if let LoopCount::Finite(count) = loop_count {
+        if loops_completed >= u128::from(count.max(1)) {
+            return frame_count - 1;
+        }
+    }
+    let mut remaining = time.as_nanos() % duration.as_nanos();
+    for frame_index in 0..frame_count {
+        let delay = delay(frame_index).as_nanos();
+        if remaining < delay {
+            return frame_index;
+        }
+        remaining -= delay;
+    }
+    frame_count - 1
+}
+
+/// The frames of an animation and how long each is shown for, whether they were decoded
+/// into a single image or given separately as [`AnimatedImageData`].
+#[derive(Clone, Copy)]
+enum Frames<'a> {
+    Decoded(&'a ImageData),
+    Separate(&'a AnimatedImageData),
+}
+
+impl Frames<'_> {
+    /// Identifies the animation, so that it starts playing again once it's replaced.
+    fn id(self) -> ImageId {
+        match self {
+            Frames::Decoded(data) => data.id,
+            Frames::Separate(animation) => animation.frames[0].id,
+        }
+    }
+
+    fn count(self) -> usize {
+        match self {
+            Frames::Decoded(data) => data.frame_count(),
+            Frames::Separate(animation) => animation.frames.len(),
+        }
+    }
+
+    /// How long the given frame is shown for. See [`shown_delay`].
+    fn delay(self, frame_index: usize) -> Duration {
+        shown_delay(match self {
+            Frames::Decoded(data) => data.frame_delay(frame_index),
+            Frames::Separate(animation) => animation
+                .delays
+                .get(frame_index)
+                .copied()
+                .unwrap_or_default(),
+        })
+    }
+
+    fn loop_count(self) -> LoopCount {
+        match self {
+            Frames::Decoded(data) => data.loop_count(),
+            Frames::Separate(animation) => animation.loop_count,
+        }
+    }
+}
+
+/// How long a frame with the given delay is shown for. Like browsers, treat the very short
+/// delays some GIFs specify as a sensible default.
+fn shown_delay(delay: Duration) -> Duration {
+    if delay < Duration::from_millis(20) {
+        Duration::from_millis(100)
+    } else {
+        delay
+    }
+}
+
+/// How long each frame of an animation is shown for, as far as it's been decoded by
+/// [`ImageSource::frame_at`], so that later calls can tell which frame to show without
+/// decoding the animation again.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FrameTiming {
+    /// The delay of each frame decoded so far.
+    pub(crate) delays: Vec<Duration>,
+    /// How many times the animation plays, which is only known once every frame has been
+    /// decoded.
+    pub(crate) loop_count: Option<LoopCount>,
+}
+
+impl FrameTiming {
+    /// Find the frame shown `time` after the animation starts playing, like
+    /// [`frame_index_at`], or `None` if that's past the frames decoded so far.
+    pub(crate) fn frame_index_at(&self, time: Duration) -> Option<usize> {
+        let delay = |frame_index: usize| shown_delay(self.delays[frame_index]);
+        if let Some(loop_count) = self.loop_count {
+            return Some(frame_index_with_delays(
+                self.delays.len(),
+                delay,
+                loop_count,
+                time,
+            ));
+        }
+
+        // Within the first loop, the frame only depends on the frames before it.
+        let mut remaining = time;
+        for frame_index in 0..self.delays.len() {
+            let delay = delay(frame_index);
+            if remaining < delay {
+                return Some(frame_index);
+            }
+            remaining -= delay;
+        }
+        None
+    }
+}
+
+/// What an `img` element last reported to its load listeners.
+#[derive(Clone, Copy, PartialEq)]
+enum LoadOutcome {
+    Loaded(ImageId),
+    Failed(u64),
+}
+
+#[derive(Clone)]
+enum Image {}
+
+impl Asset for Image {
+    type Source = ImageAssetSource;
+    type Output = Result<Arc<ImageData>, ImageCacheError>;
+
+    fn scope(source: &Self::Source) -> Option<SharedString> {
+        source.source.scope()
+    }
+
+    fn uri(source: &Self::Source) -> Option<SharedUri> {
+        source.source.uri()
+    }
+
+    fn load(
+        asset_source: Self::Source,
+        cx: &mut WindowContext,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        let key = hash(&asset_source);
+        let ImageAssetSource { source, options } = asset_source;
+        let reader = EncodedImageReader::new(cx);
+        let instrumentation = cx.image_instrumentation.clone();
+        let interner = cx.image_interner();
+        let decode_queue = cx.image_decode_queue();
+        let max_pixels = cx.image_max_pixels();
+        let scale_factor = cx.scale_factor();
+        let mut svg_renderer = cx.svg_renderer();
+        if let Some(svg_options) = &options.svg_options {
+            svg_renderer.set_options(svg_options.clone());
+        }
+        if let Some(svg_quality) = options.svg_quality {
+            svg_renderer.set_quality(svg_quality);
+        }
+        async move {
+            let EncodedImage {
+                bytes,
+                format,
+                content_type,
+            } = reader.read(source.clone()).await?;
+            let _permit = decode_queue.acquire(key).await;
+            let decode_started = Instant::now();
+            instrumentation.report(|| ImageEvent::DecodeStarted {
+                source: source.description(),
+            });
+
+            let data = if let Some(format) = format {
+                // Pages and the images in an ICO have their own sizes.
+                let natural_size = if options.page.is_some() || format == ImageFormat::Ico {
+                    None
+                } else {
+                    raster_size(&bytes, format, options.respect_exif_orientation).ok()
+                };
+                check_header_size(&bytes, format, max_pixels)?;
+                let unsupported =
+                    |error| unsupported_format(error, format, content_type.as_deref());
+                let data = if let Some(page) = options.page {
+                    decode_page(&bytes, format, page, &options, max_pixels).map_err(unsupported)?
+                } else if format == ImageFormat::Gif {
+                    decode_gif(&bytes, max_pixels)?
+                } else if format == ImageFormat::Ico {
+                    decode_ico(&bytes, options.ico_size)?
+                } else if let Some(loop_count) = apng_loop_count(&bytes, format) {
+                    decode_apng(&bytes, loop_count, max_pixels)?
+                } else {
+                    let DecodedRaster { image, partial } =
+                        decode_raster(&bytes, format, &options).map_err(unsupported)?;
+                    ImageData::new(image.into_bgra8()).with_partial(partial)
+                };
+                let natural_size = natural_size
+                    .or(data.natural_size())
+                    .unwrap_or_else(|| data.size());
+                let (color_space, icc_profile) = read_color_space(&bytes, format);
+                data.with_color_space(color_space, icc_profile)
+                    .with_natural_size(natural_size)
+                    .with_source_format(format)
+            } else {
+                let pixmap = svg_renderer.render_pixmap(
+                    &bytes,
+                    SvgSize::ScaleFactor(scale_factor),
+                    options.svg_color,
+                )?;
+
+                let data =
+                    ImageData::new(pixmap_to_bgra(pixmap)).with_color_space(ColorSpace::Srgb, None);
+                match svg_renderer.intrinsic_size(&bytes) {
+                    Ok(natural_size) => data.with_natural_size(natural_size),
+                    Err(_) => data,
+                }
+            };
+            instrumentation.report(|| ImageEvent::DecodeFinished {
+                source: source.description(),
+                size: data.size(),
+                frame_count: data.frame_count(),
+                duration: decode_started.elapsed(),
+            });
+
+            let data = match &options.decode_filter {
+                Some(filter) => data.map_frames(|buffer| filter.apply(buffer)),
+                None => data,
+            };
+
+            let data = match options.sharpen_amount {
+                Some(amount) => data.map_frames(|buffer| sharpen(&buffer, f32::from_bits(amount))),
+                None => data,
+            };
+
+            let data = if let Some(radius) = options.blur_radius {
+                let sigma = radius.0 * scale_factor;
+                data.map_frames(|buffer| blur(&buffer, sigma))
+            } else {
+                data
+            };
+
+            Ok(match interner {
+                Some(interner) => interner.intern(data),
+                None => Arc::new(data),
+            })
+        }
+    }
+
+    fn size_in_bytes(output: &Self::Output) -> usize {
+        output.as_ref().map_or(0, |data| data.size_in_bytes())
+    }
+}
+
+/// The intrinsic size of an image, as reported by [`WindowContext::image_size`].
+enum ImageSize {}
+
+#[derive(Clone, Copy)]
+struct IntrinsicSize {
+    size: Size<DevicePixels>,
+    /// Whether the image is an SVG, whose size is in logical rather than device pixels.
+    vector: bool,
+}
+
+impl Asset for ImageSize {
+    type Source = UriOrPath;
+    type Output = Result<IntrinsicSize, ImageCacheError>;
+
+    fn scope(source: &Self::Source) -> Option<SharedString> {
+        source.scope()
+    }
+
+    fn uri(source: &Self::Source) -> Option<SharedUri> {
+        source.uri()
+    }
+
+    fn load(
+        source: Self::Source,
+        cx: &mut WindowContext,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        let reader = EncodedImageReader::new(cx);
+        let svg_renderer = cx.svg_renderer();
+        async move {
+            let EncodedImage {
+                bytes,
+                format,
+                content_type,
+            } = reader.read(source).await?;
+
+            if let Some(format) = format {
+                let size = raster_size(&bytes, format, true).map_err(|error| {
+                    unsupported_format(error.into(), format, content_type.as_deref())
+                })?;
+                Ok(IntrinsicSize {
+                    size,
+                    vector: false,
+                })
+            } else {
+                Ok(IntrinsicSize {
+                    size: svg_renderer.intrinsic_size(&bytes)?,
+                    vector: true,
+                })
+            }
+        }
+    }
+}
+
+/// A thumbnail made by [`WindowContext::image_thumbnail`].
+enum ImageThumbnail {}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ThumbnailSource {
+    source: UriOrPath,
+    size: Size<DevicePixels>,
+    object_fit: ObjectFit,
+}
+
+impl Asset for ImageThumbnail {
+    type Source = ThumbnailSource;
+    type Output = Result<Arc<ImageData>, ImageCacheError>;
+
+    fn scope(source: &Self::Source) -> Option<SharedString> {
+        source.source.scope()
+    }
+
+    fn uri(source: &Self::Source) -> Option<SharedUri> {
+        source.source.uri()
+    }
+
+    fn load(
+        thumbnail_source: Self::Source,
+        cx: &mut WindowContext,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        let key = hash(&thumbnail_source);
+        let ThumbnailSource {
+            source,
+            size,
+            object_fit,
+        } = thumbnail_source;
+        let reader = EncodedImageReader::new(cx);
+        let decode_queue = cx.image_decode_queue();
+        let max_pixels = cx.image_max_pixels();
+        let svg_renderer = cx.svg_renderer();
+        async move {
+            let EncodedImage {
+                bytes,
+                format,
+                content_type,
+            } = reader.read(source).await?;
+            let _permit = decode_queue.acquire(key).await;
+
+            let (image, color_space, icc_profile) = if let Some(format) = format {
+                check_header_size(&bytes, format, max_pixels)?;
+                let DecodedRaster { image, .. } =
+                    decode_raster(&bytes, format, &DecodeOptions::default()).map_err(|error| {
+                        unsupported_format(error, format, content_type.as_deref())
+                    })?;
+                let (color_space, icc_profile) = read_color_space(&bytes, format);
+                (image, color_space, icc_profile)
+            } else {
+                // Rasterize the SVG at the size it's shown at in the thumbnail, so it's
+                // only cropped rather than scaled.
+                let intrinsic_size = svg_renderer.intrinsic_size(&bytes)?;
+                let fitted = object_fit.get_bounds(thumbnail_canvas(size), intrinsic_size);
+                let fitted_size = crate::size(
+                    DevicePixels(fitted.size.width.0.round().max(1.) as i32),
+                    DevicePixels(fitted.size.height.0.round().max(1.) as i32),
+                );
+                let pixmap =
+                    svg_renderer.render_pixmap(&bytes, SvgSize::Size(fitted_size), None)?;
+                let image = DynamicImage::ImageBgra8(pixmap_to_bgra(pixmap));
+                (image, ColorSpace::Srgb, None)
+            };
+
+            let thumbnail = crop_thumbnail(&image, size, object_fit);
+            Ok(Arc::new(
+                ImageData::new(thumbnail.into_bgra8()).with_color_space(color_space, icc_profile),
+            ))
+        }
+    }
+
+    fn size_in_bytes(output: &Self::Output) -> usize {
+        output.as_ref().map_or(0, |data| data.size_in_bytes())
+    }
+}
+
+/// How long the frames of an animation decoded by [`WindowContext::image_frame_at`] are
+/// shown for. It's only cached by decoding the animation, rather than loaded.
+enum AnimationTiming {}
+
+impl Asset for AnimationTiming {
+    type Source = ImageAssetSource;
+    type Output = Arc<FrameTiming>;
+
+    fn scope(source: &Self::Source) -> Option<SharedString> {
+        source.source.scope()
+    }
+
+    fn uri(source: &Self::Source) -> Option<SharedUri> {
+        source.source.uri()
+    }
+
+    fn load(
+        _source: Self::Source,
+        _cx: &mut WindowContext,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        async { Arc::default() }
+    }
+
+    fn size_in_bytes(output: &Self::Output) -> usize {
+        mem::size_of::<FrameTiming>() + mem::size_of_val(output.delays.as_slice())
+    }
+}
+
+/// A single frame of an animation, extracted by [`WindowContext::image_frame_at`].
+enum AnimationFrame {}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct FrameSource {
+    source: ImageAssetSource,
+    frame_index: usize,
+}
+
+impl Asset for AnimationFrame {
+    type Source = FrameSource;
+    type Output = Result<Arc<ImageData>, ImageCacheError>;
+
+    fn scope(source: &Self::Source) -> Option<SharedString> {
+        source.source.source.scope()
+    }
 
-                if let Some(data) = source.data(cx) {
-                    let new_bounds = self.object_fit.get_bounds(bounds, data.size());
-                    cx.paint_image(new_bounds, corner_radii, data.clone(), self.grayscale)
-                        .log_err();
-                }
+    fn uri(source: &Self::Source) -> Option<SharedUri> {
+        source.source.source.uri()
+    }
 
-                match source {
-                    #[cfg(target_os = "macos")]
-                    ImageSource::Surface(surface) => {
-                        let size = size(surface.width().into(), surface.height().into());
-                        let new_bounds = self.object_fit.get_bounds(bounds, size);
-                        // TODO: Add support for corner_radii and grayscale.
-                        cx.paint_surface(new_bounds, surface);
-                    }
-                    _ => {}
-                }
-            })
+    fn load(
+        FrameSource {
+            source,
+            frame_index,
+        }: Self::Source,
+        cx: &mut WindowContext,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        let animation = Image::load(source, cx);
+        async move { Ok(Arc::new(animation.await?.single_frame(frame_index))) }
+    }
+
+    fn size_in_bytes(output: &Self::Output) -> usize {
+        output.as_ref().map_or(0, |data| data.size_in_bytes())
     }
 }
 
-impl IntoElement for Img {
-    type Element = Self;
+/// Decoded images, keyed by a hash of their pixels, so that identical images loaded from
+/// different sources share a single buffer.
+/// See [`crate::AppContext::set_image_deduplication`].
+#[derive(Clone, Default)]
+pub(crate) struct ImageInterner(Arc<Mutex<InternedImages>>);
 
-    fn into_element(self) -> Self::Element {
-        self
+#[derive(Default)]
+struct InternedImages {
+    images: FxHashMap<u64, Weak<ImageData>>,
+    /// How many images there can be before those no longer in use are removed, which is
+    /// twice as many as were left the last time, so pruning is amortized over insertions.
+    prune_at: usize,
+}
+
+/// The fewest images the interner holds before it starts pruning ones that aren't in use.
+const MIN_INTERNED_IMAGES_TO_PRUNE: usize = 64;
+
+impl ImageInterner {
+    /// Get a previously decoded image identical to this one if there's one still in use,
+    /// or start sharing this one.
+    fn intern(&self, data: ImageData) -> Arc<ImageData> {
+        let key = data.content_hash();
+        let mut interned = self.0.lock();
+        if let Some(existing) = interned.images.get(&key).and_then(Weak::upgrade) {
+            if existing.has_same_content(&data) {
+                return existing;
+            }
+            // The hashes collided, so leave the existing image interned.
+            return Arc::new(data);
+        }
+
+        if interned.images.len() >= interned.prune_at {
+            interned.images.retain(|_, image| image.strong_count() > 0);
+            interned.prune_at = (interned.images.len() * 2).max(MIN_INTERNED_IMAGES_TO_PRUNE);
+        }
+        let data = Arc::new(data);
+        interned.images.insert(key, Arc::downgrade(&data));
+        data
     }
 }
 
-impl Styled for Img {
-    fn style(&mut self) -> &mut StyleRefinement {
-        &mut self.interactivity.base_style
+/// A step in loading an image, reported to the listener registered with
+/// [`AppContext::on_image_event`]. Steps that fail aren't reported as finished.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageEvent {
+    /// A remote image started being fetched.
+    FetchStarted {
+        /// The URI the image is fetched from.
+        uri: SharedString,
+    },
+    /// A remote image was fetched.
+    FetchFinished {
+        /// The URI the image was fetched from.
+        uri: SharedString,
+        /// The size of the encoded image, in bytes.
+        bytes: usize,
+        /// Whether the image was read from the disk cache rather than downloaded.
+        cache_hit: bool,
+        /// How long fetching the image took.
+        duration: Duration,
+    },
+    /// An image started being decoded, or rasterized if it's an SVG.
+    DecodeStarted {
+        /// Where the image comes from, such as its URI or path.
+        source: SharedString,
+    },
+    /// An image was decoded.
+    DecodeFinished {
+        /// Where the image comes from, such as its URI or path.
+        source: SharedString,
+        /// The size of the decoded image, in pixels.
+        size: Size<DevicePixels>,
+        /// The number of frames decoded.
+        frame_count: usize,
+        /// How long decoding the image took.
+        duration: Duration,
+    },
+}
+
+/// The listener for [`ImageEvent`]s, if one has been registered.
+#[derive(Clone, Default)]
+pub(crate) struct ImageInstrumentation(pub(crate) Option<Arc<dyn Fn(&ImageEvent) + Send + Sync>>);
+
+impl ImageInstrumentation {
+    /// Report the event built by `event`, which is only built if there's a listener.
+    pub(crate) fn report(&self, event: impl FnOnce() -> ImageEvent) {
+        if let Some(listener) = &self.0 {
+            listener(&event());
+        }
     }
 }
 
-impl InteractiveElement for Img {
-    fn interactivity(&mut self) -> &mut Interactivity {
-        &mut self.interactivity
+/// One of the nine regions an [`ObjectFit::NineSlice`] image is painted in.
+#[derive(Debug, PartialEq)]
+struct NineSlice {
+    /// The region of the element to paint.
+    bounds: Bounds<Pixels>,
+    /// The region of the image painted into `bounds`.
+    source: Bounds<DevicePixels>,
+    row: usize,
+    column: usize,
+}
+
+impl NineSlice {
+    /// Only the corner slices are rounded, by the element's radius for that corner.
+    fn corner_radii(&self, radii: &Corners<Pixels>) -> Corners<Pixels> {
+        let radius = |row, column, radius| {
+            if (self.row, self.column) == (row, column) {
+                radius
+            } else {
+                Pixels::ZERO
+            }
+        };
+        Corners {
+            top_left: radius(0, 0, radii.top_left),
+            top_right: radius(0, 2, radii.top_right),
+            bottom_right: radius(2, 2, radii.bottom_right),
+            bottom_left: radius(2, 0, radii.bottom_left),
+        }
     }
 }
 
-impl ImageSource {
-    fn data(&self, cx: &mut WindowContext) -> Option<Arc<ImageData>> {
-        match self {
-            ImageSource::Uri(_) | ImageSource::File(_) => {
-                let uri_or_path: UriOrPath = match self {
-                    ImageSource::Uri(uri) => uri.clone().into(),
-                    ImageSource::File(path) => path.clone().into(),
-                    _ => unreachable!(),
-                };
+/// Split `bounds`, and an image of the given size, into a 3x3 grid by the given insets.
+/// The corners are painted at their natural size unless the bounds are too small to fit
+/// them, in which case they're shrunk proportionally. Empty slices are skipped.
+fn nine_slices(
+    bounds: Bounds<Pixels>,
+    image_size: Size<DevicePixels>,
+    insets: &Edges<Pixels>,
+) -> Vec<NineSlice> {
+    // Returns the offsets of the grid lines along one axis, in the image and in the bounds.
+    let grid_lines = |image_length: i32, bounds_length: f32, start: Pixels, end: Pixels| {
+        let start = (start.0.round() as i32).clamp(0, image_length);
+        let end = (end.0.round() as i32).clamp(0, image_length - start);
+        let image_lines = [0, start, image_length - end, image_length];
+
+        let scale = (bounds_length / (start + end).max(1) as f32).min(1.);
+        let bounds_lines = [
+            0.,
+            start as f32 * scale,
+            bounds_length - end as f32 * scale,
+            bounds_length,
+        ];
+        (image_lines, bounds_lines)
+    };
 
-                cx.use_cached_asset::<Image>(&uri_or_path)?.log_err()
+    let (image_columns, bounds_columns) = grid_lines(
+        image_size.width.0,
+        bounds.size.width.0,
+        insets.left,
+        insets.right,
+    );
+    let (image_rows, bounds_rows) = grid_lines(
+        image_size.height.0,
+        bounds.size.height.0,
+        insets.top,
+        insets.bottom,
+    );
+
+    let mut slices = Vec::with_capacity(9);
+    for row in 0..3 {
+        for column in 0..3 {
+            let source = Bounds {
+                origin: point(
+                    DevicePixels(image_columns[column]),
+                    DevicePixels(image_rows[row]),
+                ),
+                size: size(
+                    DevicePixels(image_columns[column + 1] - image_columns[column]),
+                    DevicePixels(image_rows[row + 1] - image_rows[row]),
+                ),
+            };
+            let slice_bounds = Bounds {
+                origin: bounds.origin + point(px(bounds_columns[column]), px(bounds_rows[row])),
+                size: size(
+                    px(bounds_columns[column + 1] - bounds_columns[column]),
+                    px(bounds_rows[row + 1] - bounds_rows[row]),
+                ),
+            };
+            if source.size.width.0 > 0
+                && source.size.height.0 > 0
+                && slice_bounds.size.width > Pixels::ZERO
+                && slice_bounds.size.height > Pixels::ZERO
+            {
+                slices.push(NineSlice {
+                    bounds: slice_bounds,
+                    source,
+                    row,
+                    column,
+                });
             }
+        }
+    }
+    slices
+}
 
-            ImageSource::Data(data) => Some(data.to_owned()),
-            #[cfg(target_os = "macos")]
-            ImageSource::Surface(_) => None,
+/// The largest amount an image can be sharpened by. See [`Img::sharpen`].
+const MAX_SHARPEN_AMOUNT: f32 = 2.;
+
+/// Sharpen an image with an unsharp mask, adding `amount` times the difference between
+/// each pixel and a blurred copy of the image back onto it. Alpha is left as it is.
+fn sharpen(buffer: &ImageBuffer<Bgra<u8>, Vec<u8>>, amount: f32) -> ImageBuffer<Bgra<u8>, Vec<u8>> {
+    const SIGMA: f32 = 1.;
+
+    let blurred = imageops::blur(buffer, SIGMA);
+    let mut sharpened = buffer.clone();
+    for (pixel, blurred) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        for (channel, blurred) in pixel.0[..3].iter_mut().zip(&blurred.0[..3]) {
+            let detail = *channel as f32 - *blurred as f32;
+            *channel = (*channel as f32 + detail * amount).round().clamp(0., 255.) as u8;
         }
     }
+    sharpened
 }
 
-#[derive(Clone)]
-enum Image {}
+/// Apply a Gaussian blur with the given standard deviation, in device pixels. Wide blurs
+/// are computed on a downsampled copy of the image, which looks nearly identical once
+/// blurred but is much cheaper, then scaled back up to the original size.
+fn blur(buffer: &ImageBuffer<Bgra<u8>, Vec<u8>>, sigma: f32) -> ImageBuffer<Bgra<u8>, Vec<u8>> {
+    const MAX_SIGMA: f32 = 4.;
 
-impl Asset for Image {
-    type Source = UriOrPath;
-    type Output = Result<Arc<ImageData>, ImageCacheError>;
+    let downscale = sigma / MAX_SIGMA;
+    if downscale <= 1. {
+        return imageops::blur(buffer, sigma);
+    }
 
-    fn load(
-        source: Self::Source,
-        cx: &mut WindowContext,
-    ) -> impl Future<Output = Self::Output> + Send + 'static {
-        let client = cx.http_client();
-        let scale_factor = cx.scale_factor();
-        let svg_renderer = cx.svg_renderer();
-        async move {
-            let bytes = match source.clone() {
-                UriOrPath::Path(uri) => fs::read(uri.as_ref())?,
-                UriOrPath::Uri(uri) => {
-                    let mut response = client.get(uri.as_ref(), ().into(), true).await?;
-                    let mut body = Vec::new();
-                    response.body_mut().read_to_end(&mut body).await?;
-                    if !response.status().is_success() {
-                        return Err(ImageCacheError::BadStatus {
-                            status: response.status(),
-                            body: String::from_utf8_lossy(&body).into_owned(),
-                        });
-                    }
-                    body
-                }
-            };
+    let (width, height) = buffer.dimensions();
+    let downsampled = imageops::resize(
+        buffer,
+        ((width as f32 / downscale).ceil() as u32).max(1),
+        ((height as f32 / downscale).ceil() as u32).max(1),
+        FilterType::Triangle,
+    );
+    let blurred = imageops::blur(&downsampled, MAX_SIGMA);
+    imageops::resize(&blurred, width, height, FilterType::Triangle)
+}
 
-            let data = if let Ok(format) = image::guess_format(&bytes) {
-                let data = image::load_from_memory_with_format(&bytes, format)?.into_bgra8();
-                ImageData::new(data)
-            } else {
-                let pixmap =
-                    svg_renderer.render_pixmap(&bytes, SvgSize::ScaleFactor(scale_factor))?;
+impl ImageData {
+    /// Render an SVG into an image of the given size, preserving its aspect ratio, for use
+    /// outside of drawing a window. Text is rendered with the fonts in `fontdb`.
+    pub fn from_svg_bytes(
+        bytes: &[u8],
+        size: Size<DevicePixels>,
+        fontdb: &fontdb::Database,
+    ) -> Result<Self, ImageCacheError> {
+        let pixmap = svg_renderer::render_pixmap(
+            bytes,
+            SvgSize::Size(size),
+            None,
+            &SvgOptions::default(),
+            fontdb,
+            DEFAULT_MAX_SVG_RASTERIZATION_SIZE,
+        )?;
+        Ok(Self::new(pixmap_to_bgra(pixmap)))
+    }
+}
 
-                let buffer =
-                    ImageBuffer::from_raw(pixmap.width(), pixmap.height(), pixmap.take()).unwrap();
+/// Order candidates of different sizes, such as [`Img::sources`] or the images in an ICO,
+/// from best to worst for displaying an image at `device_size`: the smallest that cover
+/// it, followed by the rest from largest to smallest.
+pub(crate) fn by_size_preference<T>(
+    candidates: &[(Size<DevicePixels>, T)],
+    device_size: Size<DevicePixels>,
+) -> Vec<&T> {
+    let mut candidates = candidates.iter().collect::<Vec<_>>();
+    candidates.sort_by_key(|(size, _)| {
+        let area = size.width.0 as i64 * size.height.0 as i64;
+        if size.width >= device_size.width && size.height >= device_size.height {
+            (false, area)
+        } else {
+            (true, -area)
+        }
+    });
+    candidates
+        .into_iter()
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
 
-                ImageData::new(buffer)
-            };
+/// Round the edges of the given bounds to the nearest whole device pixels.
+fn snap_to_device_pixels(bounds: Bounds<Pixels>, scale_factor: f32) -> Bounds<Pixels> {
+    let snap = |pixels: Pixels| px((pixels.0 * scale_factor).round() / scale_factor);
+    let upper_left = bounds.origin;
+    let lower_right = bounds.lower_right();
+    Bounds::from_corners(
+        point(snap(upper_left.x), snap(upper_left.y)),
+        point(snap(lower_right.x), snap(lower_right.y)),
+    )
+}
 
-            Ok(Arc::new(data))
+/// Convert a rendered pixmap's premultiplied RGBA pixels into the straight-alpha BGRA
+/// layout of [`ImageData`], in place.
+pub(crate) fn pixmap_to_bgra(pixmap: Pixmap) -> ImageBuffer<Bgra<u8>, Vec<u8>> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let mut pixels = pixmap.take();
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+        let alpha = pixel[3] as u16;
+        if alpha != 0 && alpha != 255 {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u16 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
         }
     }
+    ImageBuffer::from_raw(width, height, pixels).expect("pixmap size matches its dimensions")
+}
+
+/// The bounds of a thumbnail of the given size, in pixels of the thumbnail.
+fn thumbnail_canvas(size: Size<DevicePixels>) -> Bounds<Pixels> {
+    Bounds {
+        origin: Point::default(),
+        size: size.map(|dimension| px(dimension.0 as f32)),
+    }
+}
+
+/// Make a thumbnail of the first frame of an image that's already decoded.
+fn thumbnail_from_data(
+    data: &ImageData,
+    size: Size<DevicePixels>,
+    object_fit: ObjectFit,
+) -> Result<Arc<ImageData>, ImageCacheError> {
+    let frame = data.frame_as_bytes(0).unwrap_or_default().to_vec();
+    let buffer = ImageBuffer::from_raw(data.size().width.into(), data.size().height.into(), frame)
+        .ok_or_else(|| ImageCacheError::RenderFailed("image data has the wrong size".into()))?;
+    let thumbnail = crop_thumbnail(&DynamicImage::ImageBgra8(buffer), size, object_fit);
+    Ok(Arc::new(
+        ImageData::new(thumbnail.into_bgra8())
+            .with_color_space(data.color_space(), data.icc_profile().map(Arc::from)),
+    ))
+}
+
+/// Scale and crop an image into a thumbnail of the given size, fitted according to
+/// `object_fit`. Only the part of the image that's visible in the thumbnail is scaled, and
+/// the thumbnail is shrunk to that part, so no space is left around the image.
+fn crop_thumbnail(
+    image: &DynamicImage,
+    size: Size<DevicePixels>,
+    object_fit: ObjectFit,
+) -> DynamicImage {
+    let canvas = thumbnail_canvas(size);
+    let (width, height) = image.dimensions();
+    let fitted = object_fit.get_bounds(canvas, crate::size(width.into(), height.into()));
+    let visible = fitted.intersect(&canvas);
+
+    // Map the visible part of the thumbnail back to the image's pixels.
+    let scale_x = width as f32 / fitted.size.width.0;
+    let scale_y = height as f32 / fitted.size.height.0;
+    let source_x = (((visible.origin.x - fitted.origin.x).0 * scale_x).round() as u32)
+        .min(width.saturating_sub(1));
+    let source_y = (((visible.origin.y - fitted.origin.y).0 * scale_y).round() as u32)
+        .min(height.saturating_sub(1));
+    let source_width = ((visible.size.width.0 * scale_x).round() as u32).clamp(1, width - source_x);
+    let source_height =
+        ((visible.size.height.0 * scale_y).round() as u32).clamp(1, height - source_y);
+
+    let target_width = (visible.size.width.0.round() as u32).max(1);
+    let target_height = (visible.size.height.0.round() as u32).max(1);
+    let cropped = image.crop_imm(source_x, source_y, source_width, source_height);
+    if (source_width, source_height) == (target_width, target_height) {
+        cropped
+    } else {
+        cropped.resize_exact(target_width, target_height, FilterType::Triangle)
+    }
 }
 
 /// An error that can occur when interacting with the image cache.
@@ -406,9 +3849,19 @@ pub enum ImageCacheError {
     /// An error that occurred while fetching an image from a remote source.
     #[error("http error: {0}")]
     Client(#[from] http::Error),
+    /// The remote image took longer than the [`RetryPolicy`](crate::RetryPolicy)'s timeout
+    /// to load.
+    #[error("timed out fetching image")]
+    Timeout,
+    /// A `data:` URI could not be parsed.
+    #[error("invalid data URI: {0}")]
+    InvalidDataUri(SharedString),
     /// An error that occurred while reading the image from disk.
     #[error("IO error: {0}")]
     Io(Arc<std::io::Error>),
+    /// The app's [`AssetSource`] failed to load an [`ImageSource::Asset`].
+    #[error("asset error: {0}")]
+    Asset(SharedString),
     /// An error that occurred while processing an image.
     #[error("unexpected http status: {status}, body: {body}")]
     BadStatus {
@@ -417,12 +3870,80 @@ pub enum ImageCacheError {
         /// The HTTP response body.
         body: String,
     },
+    /// A remote image redirected more than 10 times, which is likely a redirect loop.
+    #[error("too many redirects")]
+    TooManyRedirects,
+    /// A remote image redirected to a location that isn't a valid URI.
+    #[error("invalid redirect location: {0}")]
+    InvalidRedirect(SharedString),
+    /// A remote image was compressed with a `Content-Encoding`, such as `br`, that neither
+    /// the HTTP client nor GPUI could decompress.
+    #[error("unsupported content encoding: {0}")]
+    UnsupportedContentEncoding(SharedString),
+    /// A remote image's compressed body decompressed to more than 256 MiB, so it was
+    /// discarded.
+    #[error("decompressed image body is too large")]
+    DecompressedTooLarge,
     /// An error that occurred while processing an image.
     #[error("image error: {0}")]
     Image(Arc<ImageError>),
+    /// An image couldn't be decoded because its data ends early, for example because its
+    /// download was interrupted. Formats that can be decoded row by row, such as PNG, are
+    /// instead partially decoded; see [`ImageData::is_partial`].
+    #[error("truncated image: {0}")]
+    Truncated(Arc<ImageError>),
+    /// A page was requested with [`ImageSource::uri_page`] that the image doesn't have.
+    #[error("page {page} is out of range for an image with {page_count} pages")]
+    PageOutOfRange {
+        /// The index of the requested page.
+        page: usize,
+        /// The number of pages in the image.
+        page_count: usize,
+    },
+    /// An ICO has no images that could be decoded.
+    #[error("ico has no decodable images")]
+    NoDecodableFrames,
+    /// An image's data is neither in a raster format that can be decoded nor an SVG, for
+    /// example because a server returned an HTML error page instead of the image.
+    #[error("unknown image format (sniffed: {sniffed:?}, content type: {content_type:?})")]
+    UnknownFormat {
+        /// The raster format the image appears to be in, if it's one that can't be decoded.
+        sniffed: Option<ImageFormat>,
+        /// The `Content-Type` the image was served with, or the MIME type a `data:` URI
+        /// declared, if any.
+        content_type: Option<String>,
+    },
+    /// A raster image has more pixels than [`AppContext::set_image_max_pixels`] allows, so
+    /// it wasn't decoded.
+    #[error("image is too large to decode: {width}x{height}")]
+    TooLarge {
+        /// The width of the image, in pixels.
+        width: u32,
+        /// The height of the image, in pixels.
+        height: u32,
+    },
     /// An error that occurred while processing an SVG.
     #[error("svg error: {0}")]
     Usvg(Arc<usvg::Error>),
+    /// A parsed image could not be rasterized, for example because it would be empty
+    /// or too large to allocate.
+    #[error("failed to render image: {0}")]
+    RenderFailed(SharedString),
+    /// An SVG was parsed, but declares a width or height of zero and has no `viewBox`
+    /// to take its size from.
+    #[error("svg has no width or height")]
+    ZeroSize,
+}
+
+impl ImageCacheError {
+    /// Whether this error may go away if the image is fetched again.
+    fn is_transient(&self) -> bool {
+        match self {
+            ImageCacheError::Client(_) | ImageCacheError::Timeout => true,
+            ImageCacheError::BadStatus { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
 }
 
 impl From<std::io::Error> for ImageCacheError {
@@ -439,6 +3960,824 @@ impl From<ImageError> for ImageCacheError {
 
 impl From<usvg::Error> for ImageCacheError {
     fn from(error: usvg::Error) -> Self {
-        Self::Usvg(Arc::new(error))
+        match error {
+            // usvg only reports this when the SVG's own size resolves to zero.
+            usvg::Error::InvalidSize => Self::ZeroSize,
+            error => Self::Usvg(Arc::new(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{self as gpui, ImageDiff, TestAppContext};
+    use resvg::tiny_skia::ColorU8;
+    use std::cell::Cell;
+
+    fn test_bounds() -> Bounds<Pixels> {
+        Bounds {
+            origin: point(px(0.), px(0.)),
+            size: size(px(200.), px(100.)),
+        }
+    }
+
+    #[test]
+    fn test_scale_down_shrinks_large_images() {
+        let image_size = size(DevicePixels(400), DevicePixels(400));
+        assert_eq!(
+            ObjectFit::ScaleDown.get_bounds(test_bounds(), image_size),
+            ObjectFit::Contain.get_bounds(test_bounds(), image_size)
+        );
+    }
+
+    #[test]
+    fn test_scale_down_does_not_upscale_small_images() {
+        let image_size = size(DevicePixels(20), DevicePixels(10));
+        assert_eq!(
+            ObjectFit::ScaleDown.get_bounds(test_bounds(), image_size),
+            Bounds {
+                origin: point(px(90.), px(45.)),
+                size: size(px(20.), px(10.)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_object_position_aligns_cover() {
+        let image_size = size(DevicePixels(100), DevicePixels(100));
+        let top =
+            ObjectFit::Cover.get_bounds_with_position(test_bounds(), image_size, point(0., 0.));
+        assert_eq!(top.origin, point(px(0.), px(0.)));
+        let bottom =
+            ObjectFit::Cover.get_bounds_with_position(test_bounds(), image_size, point(0., 1.));
+        assert_eq!(bottom.origin, point(px(0.), px(-100.)));
+    }
+
+    #[test]
+    fn test_focal_point_biases_cover_crop() {
+        let image_size = size(DevicePixels(100), DevicePixels(100));
+        let centered = ObjectFit::Cover.get_bounds_with_focal_point(
+            test_bounds(),
+            image_size,
+            point(0.5, 0.5),
+        );
+        assert_eq!(
+            centered,
+            ObjectFit::Cover.get_bounds(test_bounds(), image_size)
+        );
+
+        // The image is 200x200 and the bounds 200x100, so the crop moves up to 100px down.
+        let biased = ObjectFit::Cover.get_bounds_with_focal_point(
+            test_bounds(),
+            image_size,
+            point(0.5, 0.6),
+        );
+        assert_eq!(biased.origin, point(px(0.), px(-70.)));
+        let clamped =
+            ObjectFit::Cover.get_bounds_with_focal_point(test_bounds(), image_size, point(0.5, 1.));
+        assert_eq!(clamped.origin, point(px(0.), px(-100.)));
+    }
+
+    #[test]
+    fn test_sources_prefer_the_smallest_that_covers_the_element() {
+        let square = |side| size(DevicePixels(side), DevicePixels(side));
+        let sources = [100, 400, 200, 50]
+            .map(|side| (square(side), ImageSource::from(format!("{side}.png"))));
+        let preference = |device_size| {
+            by_size_preference(&sources, device_size)
+                .into_iter()
+                .map(|source| match source {
+                    ImageSource::Uri { uri, .. } => uri.to_string(),
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            preference(square(150)),
+            ["200.png", "400.png", "100.png", "50.png"]
+        );
+        assert_eq!(
+            preference(square(1000)),
+            ["400.png", "200.png", "100.png", "50.png"]
+        );
+    }
+
+    #[test]
+    fn test_pixel_snapping() {
+        let bounds = Bounds {
+            origin: point(px(10.3), px(4.6)),
+            size: size(px(20.), px(20.)),
+        };
+        assert_eq!(
+            snap_to_device_pixels(bounds, 1.),
+            Bounds {
+                origin: point(px(10.), px(5.)),
+                size: size(px(20.), px(20.)),
+            }
+        );
+        assert_eq!(
+            snap_to_device_pixels(bounds, 2.),
+            Bounds {
+                origin: point(px(10.5), px(4.5)),
+                size: size(px(20.), px(20.)),
+            }
+        );
+
+        let data = Arc::new(ImageData::new(ImageBuffer::new(1, 1)));
+        assert!(!img(data.clone()).snaps_to_pixels());
+        assert!(img(data.clone())
+            .svg_quality(SvgQuality::Crisp)
+            .snaps_to_pixels());
+        assert!(!img(data)
+            .svg_quality(SvgQuality::Crisp)
+            .pixel_snap(false)
+            .snaps_to_pixels());
+    }
+
+    #[test]
+    fn test_object_fit_none_keeps_natural_size_and_position() {
+        let image_size = size(DevicePixels(300), DevicePixels(50));
+        let centered = ObjectFit::None.get_bounds(test_bounds(), image_size);
+        assert_eq!(
+            centered,
+            Bounds {
+                origin: point(px(-50.), px(25.)),
+                size: size(px(300.), px(50.)),
+            }
+        );
+        let top_left =
+            ObjectFit::None.get_bounds_with_position(test_bounds(), image_size, point(0., 0.));
+        assert_eq!(top_left.origin, test_bounds().origin);
+    }
+
+    #[test]
+    fn test_rotated_images_fit_with_swapped_dimensions() {
+        let image_size = size(DevicePixels(50), DevicePixels(100));
+        let upright = ObjectFit::Contain.get_bounds(test_bounds(), image_size);
+        assert_eq!(upright.size, size(px(50.), px(100.)));
+
+        let sideways = ObjectFit::Contain
+            .get_bounds(test_bounds(), Rotation::Clockwise90.rotate_size(image_size));
+        assert_eq!(sideways.size, size(px(200.), px(100.)));
+        assert_eq!(Rotation::Clockwise180.rotate_size(image_size), image_size);
+    }
+
+    #[test]
+    fn test_images_are_framed_by_object_fit() {
+        let data = Arc::new(ImageData::new(ImageBuffer::new(50, 100)));
+        let fits = [
+            ObjectFit::Fill,
+            ObjectFit::Contain,
+            ObjectFit::Cover,
+            ObjectFit::ScaleDown,
+            ObjectFit::None,
+        ];
+        for fit in fits {
+            let image = img(data.clone()).object_fit(fit);
+            let content_size = image.content_size(Some(&data)).unwrap();
+            assert_eq!(content_size, data.size());
+            // Surfaces are framed with the size of their buffer, through the same path.
+            assert_eq!(
+                image.content_bounds(test_bounds(), content_size),
+                fit.get_bounds(test_bounds(), data.size()),
+                "{fit:?}"
+            );
+        }
+
+        let rotated = img(data.clone()).rotate(Rotation::Clockwise90);
+        assert_eq!(
+            rotated.content_size(Some(&data)),
+            Some(size(DevicePixels(100), DevicePixels(50)))
+        );
+    }
+
+    #[test]
+    fn test_object_fit_source_rect() {
+        let image_size = size(DevicePixels(100), DevicePixels(100));
+        assert_eq!(
+            ObjectFit::Cover.source_rect(test_bounds(), image_size),
+            Bounds {
+                origin: point(DevicePixels(0), DevicePixels(25)),
+                size: size(DevicePixels(100), DevicePixels(50)),
+            }
+        );
+        assert_eq!(
+            ObjectFit::Contain.source_rect(test_bounds(), image_size),
+            Bounds {
+                origin: point(DevicePixels(0), DevicePixels(0)),
+                size: image_size,
+            }
+        );
+
+        let large = size(DevicePixels(400), DevicePixels(50));
+        assert_eq!(
+            ObjectFit::None.source_rect(test_bounds(), large),
+            Bounds {
+                origin: point(DevicePixels(100), DevicePixels(0)),
+                size: size(DevicePixels(200), DevicePixels(50)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_image_position_maps_element_points_to_image_pixels() {
+        let data = Arc::new(ImageData::new(ImageBuffer::new(100, 100)));
+        let image_size = data.size();
+        let element_size = test_bounds().size;
+
+        let cover = img(data.clone()).object_fit(ObjectFit::Cover);
+        assert_eq!(
+            cover.image_position(element_size, image_size, point(px(0.), px(0.))),
+            Some(point(0., 25.))
+        );
+        assert_eq!(
+            cover.image_position(element_size, image_size, point(px(100.), px(50.))),
+            Some(point(50., 50.))
+        );
+        assert_eq!(
+            cover.image_position(element_size, image_size, point(px(250.), px(50.))),
+            None
+        );
+
+        // The margins beside a contained image map to nothing.
+        let contain = img(data.clone()).object_fit(ObjectFit::Contain);
+        assert_eq!(
+            contain.image_position(element_size, image_size, point(px(10.), px(50.))),
+            None
+        );
+        assert_eq!(
+            contain.image_position(element_size, image_size, point(px(50.), px(0.))),
+            Some(point(0., 0.))
+        );
+
+        // A quarter turn clockwise paints the image's bottom left corner at the top left.
+        let rotated = img(data.clone())
+            .object_fit(ObjectFit::Contain)
+            .rotate(Rotation::Clockwise90);
+        assert_eq!(
+            rotated.image_position(element_size, image_size, point(px(50.), px(0.))),
+            Some(point(0., 100.))
+        );
+        let flipped = img(data.clone())
+            .object_fit(ObjectFit::Contain)
+            .flip_horizontal(true);
+        assert_eq!(
+            flipped.image_position(element_size, image_size, point(px(50.), px(0.))),
+            Some(point(100., 0.))
+        );
+
+        let cropped = img(data).object_fit(ObjectFit::Fill).source_rect(Bounds {
+            origin: point(DevicePixels(50), DevicePixels(50)),
+            size: size(DevicePixels(50), DevicePixels(50)),
+        });
+        assert_eq!(
+            cropped.image_position(element_size, image_size, point(px(100.), px(50.))),
+            Some(point(75., 75.))
+        );
+    }
+
+    #[test]
+    fn test_transformation_inverse_maps_back_to_bounds() {
+        let bounds = test_bounds();
+        let transformation = Transformation::rotate(crate::radians(std::f32::consts::FRAC_PI_4))
+            .with_scaling(size(2., 2.))
+            .into_matrix(bounds.center(), 1.0);
+        let inverse = transformation.inverse().unwrap();
+        for point in [bounds.origin, bounds.center(), bounds.lower_right()] {
+            let original = inverse.apply(transformation.apply(point));
+            assert!((original.x - point.x).abs() < px(0.001));
+            assert!((original.y - point.y).abs() < px(0.001));
+        }
+
+        let collapsed = Transformation::scale(size(0., 1.)).into_matrix(bounds.center(), 1.0);
+        assert!(collapsed.inverse().is_none());
+    }
+
+    #[test]
+    fn test_image_interner_shares_identical_images() {
+        let interner = ImageInterner::default();
+        let pixels = || ImageBuffer::from_pixel(2, 2, Bgra([1, 2, 3, 255]));
+
+        let first = interner.intern(ImageData::new(pixels()));
+        let second = interner.intern(ImageData::new(pixels()));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let different = interner.intern(ImageData::new(ImageBuffer::from_pixel(
+            2,
+            2,
+            Bgra([4, 5, 6, 255]),
+        )));
+        assert!(!Arc::ptr_eq(&first, &different));
+
+        // Images are only shared while they're in use.
+        let first_id = first.id;
+        drop((first, second));
+        assert_ne!(interner.intern(ImageData::new(pixels())).id, first_id);
+    }
+
+    #[test]
+    fn test_image_interner_prunes_unused_images() {
+        let interner = ImageInterner::default();
+        for value in 0..1000 {
+            interner.intern(ImageData::new(ImageBuffer::from_pixel(
+                1,
+                1,
+                Bgra([0, 0, (value % 256) as u8, (value / 256) as u8]),
+            )));
+        }
+        assert!(interner.0.lock().images.len() <= MIN_INTERNED_IMAGES_TO_PRUNE);
+    }
+
+    #[test]
+    fn test_circle_clip_is_inscribed_in_bounds() {
+        let (circle, corner_radii) = ImageClip::Circle.rounded_rect(test_bounds());
+        assert_eq!(
+            circle,
+            Bounds {
+                origin: point(px(50.), px(0.)),
+                size: size(px(100.), px(100.)),
+            }
+        );
+        assert_eq!(corner_radii, Corners::all(px(50.)));
+    }
+
+    #[test]
+    fn test_crop_thumbnail() {
+        let image = DynamicImage::new_rgba8(200, 100);
+        let thumbnail_size = size(DevicePixels(50), DevicePixels(50));
+        let dimensions =
+            |object_fit| crop_thumbnail(&image, thumbnail_size, object_fit).dimensions();
+
+        assert_eq!(dimensions(ObjectFit::Cover), (50, 50));
+        assert_eq!(dimensions(ObjectFit::Contain), (50, 25));
+        assert_eq!(dimensions(ObjectFit::Fill), (50, 50));
+        assert_eq!(dimensions(ObjectFit::None), (50, 50));
+
+        let small = DynamicImage::new_rgba8(10, 20);
+        assert_eq!(
+            crop_thumbnail(&small, thumbnail_size, ObjectFit::ScaleDown).dimensions(),
+            (10, 20)
+        );
+    }
+
+    #[test]
+    fn test_source_region_is_clamped_to_image() {
+        let data = Arc::new(ImageData::new(ImageBuffer::from_pixel(
+            64,
+            32,
+            Bgra([0, 0, 0, 255]),
+        )));
+        let image = img(ImageSource::Data(data.clone()));
+        assert_eq!(image.source_region(&data).size, data.size());
+
+        let image = image.source_rect(Bounds {
+            origin: point(DevicePixels(48), DevicePixels(16)),
+            size: size(DevicePixels(32), DevicePixels(32)),
+        });
+        assert_eq!(
+            image.source_region(&data),
+            Bounds {
+                origin: point(DevicePixels(48), DevicePixels(16)),
+                size: size(DevicePixels(16), DevicePixels(16)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nine_slices() {
+        let insets = Edges {
+            top: px(10.),
+            right: px(10.),
+            bottom: px(10.),
+            left: px(10.),
+        };
+        let slices = nine_slices(
+            test_bounds(),
+            size(DevicePixels(30), DevicePixels(30)),
+            &insets,
+        );
+        assert_eq!(slices.len(), 9);
+
+        // Corners keep their size.
+        assert_eq!(slices[0].bounds.size, size(px(10.), px(10.)));
+        assert_eq!(slices[8].bounds.origin, point(px(190.), px(90.)));
+        assert_eq!(
+            slices[8].source.origin,
+            point(DevicePixels(20), DevicePixels(20))
+        );
+        // The center stretches in both directions.
+        assert_eq!(slices[4].bounds.size, size(px(180.), px(80.)));
+        assert_eq!(
+            slices[4].source.size,
+            size(DevicePixels(10), DevicePixels(10))
+        );
+
+        // Corners shrink to fit bounds smaller than the insets.
+        let small_bounds = Bounds {
+            origin: point(px(0.), px(0.)),
+            size: size(px(10.), px(40.)),
+        };
+        let slices = nine_slices(
+            small_bounds,
+            size(DevicePixels(30), DevicePixels(30)),
+            &insets,
+        );
+        assert_eq!(slices.len(), 6);
+        assert_eq!(slices[0].bounds.size, size(px(5.), px(10.)));
+    }
+
+    #[test]
+    fn test_pixmap_to_bgra() {
+        let mut pixmap = Pixmap::new(2, 1).unwrap();
+        pixmap.pixels_mut()[0] = ColorU8::from_rgba(255, 0, 0, 255).premultiply();
+        pixmap.pixels_mut()[1] = ColorU8::from_rgba(0, 255, 0, 128).premultiply();
+
+        let buffer = pixmap_to_bgra(pixmap);
+        assert_eq!(buffer.get_pixel(0, 0), &Bgra([0, 0, 255, 255]));
+        assert_eq!(buffer.get_pixel(1, 0), &Bgra([0, 255, 0, 128]));
+    }
+
+    #[test]
+    fn test_anti_aliased_svg_edges_have_no_fringe() {
+        // A white circle, whose edge pixels are only partially covered.
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="16">
+            <circle cx="8" cy="8" r="6.5" fill="white"/>
+        </svg>"#;
+        let data = ImageData::from_svg_bytes(
+            svg,
+            size(DevicePixels(16), DevicePixels(16)),
+            &fontdb::Database::new(),
+        )
+        .unwrap();
+
+        let pixels = data.as_bytes().chunks_exact(4).collect::<Vec<_>>();
+        assert!(pixels.iter().any(|pixel| pixel[3] > 0 && pixel[3] < 255));
+        for pixel in pixels {
+            if pixel[3] > 0 {
+                // Premultiplied edges would be darker than the shape's color.
+                assert!(
+                    pixel[..3].iter().all(|channel| *channel >= 254),
+                    "{pixel:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_image_data_from_svg_bytes() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2"/>"#;
+        let data = ImageData::from_svg_bytes(
+            svg,
+            size(DevicePixels(8), DevicePixels(4)),
+            &fontdb::Database::new(),
+        )
+        .unwrap();
+        assert_eq!(data.size(), size(DevicePixels(8), DevicePixels(4)));
+    }
+
+    #[test]
+    fn test_natural_size_survives_downscaled_decodes() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::new(64, 32));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let natural_size = raster_size(&png, ImageFormat::Png, true).unwrap();
+        assert_eq!(natural_size, size(DevicePixels(64), DevicePixels(32)));
+
+        let options = DecodeOptions {
+            max_decode_size: Some(size(DevicePixels(16), DevicePixels(16))),
+            ..DecodeOptions::default()
+        };
+        let decoded = decode_raster(&png, ImageFormat::Png, &options).unwrap();
+        let data = ImageData::new(decoded.image.into_bgra8())
+            .with_natural_size(natural_size)
+            .with_source_format(ImageFormat::Png);
+        assert_eq!(data.size(), size(DevicePixels(16), DevicePixels(8)));
+        assert_eq!(data.natural_size(), Some(natural_size));
+
+        let filtered = data.map_frames(|buffer| buffer);
+        assert_eq!(filtered.natural_size(), Some(natural_size));
+        assert_eq!(filtered.source_format(), Some(ImageFormat::Png));
+        assert!(!filtered.has_same_content(&ImageData::new(ImageBuffer::new(16, 8))));
+    }
+
+    #[test]
+    fn test_readers_are_keyed_by_id() {
+        let reader = |id: &'static str, bytes: &'static [u8]| {
+            ImageSource::reader(id, move || futures::io::Cursor::new(bytes))
+                .uri_or_path()
+                .unwrap()
+        };
+        assert_eq!(reader("a", b"1"), reader("a", b"2"));
+        assert_ne!(reader("a", b"1"), reader("b", b"1"));
+    }
+
+    #[test]
+    fn test_decode_filters_see_rgba_and_are_keyed_by_identity() {
+        let filter: Arc<dyn Fn(&mut RgbaImage) + Send + Sync> =
+            Arc::new(|image: &mut RgbaImage| {
+                assert_eq!(image.get_pixel(0, 0).0, [3, 2, 1, 4]);
+                image.get_pixel_mut(0, 0).0[0] = 9;
+            });
+        let buffer = ImageBuffer::from_pixel(1, 1, Bgra([1, 2, 3, 4]));
+        let filtered = DecodeFilter(filter.clone()).apply(buffer);
+        assert_eq!(filtered.get_pixel(0, 0).0, [1, 2, 9, 4]);
+
+        let source = |filter| ImageSource::with_decode_filter("image.png", filter);
+        let options = DecodeOptions::default();
+        let other: Arc<dyn Fn(&mut RgbaImage) + Send + Sync> = Arc::new(|_: &mut RgbaImage| {});
+        assert_eq!(
+            source(filter.clone()).asset_source(&options),
+            source(filter.clone()).asset_source(&options)
+        );
+        assert_ne!(
+            source(filter).asset_source(&options),
+            source(other.clone()).asset_source(&options)
+        );
+        assert_ne!(
+            ImageSource::from("image.png").asset_source(&options),
+            source(other).asset_source(&options)
+        );
+    }
+
+    #[test]
+    fn test_opacity_is_checked_across_frames() {
+        let opaque = ImageBuffer::from_pixel(2, 2, Bgra([1, 2, 3, 255]));
+        let mut translucent = opaque.clone();
+        translucent.put_pixel(1, 1, Bgra([1, 2, 3, 254]));
+
+        assert!(ImageData::new(opaque.clone()).is_opaque());
+        assert!(!ImageData::new(translucent.clone()).is_opaque());
+        let frames = [(opaque, Duration::ZERO), (translucent, Duration::ZERO)];
+        assert!(!ImageData::from_frames(frames, LoopCount::Infinite).is_opaque());
+    }
+
+    #[test]
+    fn test_scoped_images_are_cached_separately() {
+        let uri = || ImageSource::from("https://example.com/avatar.png");
+        let asset_source =
+            |source: ImageSource| source.asset_source(&DecodeOptions::default()).unwrap();
+        let unscoped = asset_source(uri());
+        let a = asset_source(ImageSource::scoped("a", uri()));
+        let b = asset_source(ImageSource::scoped("b", ImageSource::scoped("c", uri())));
+        assert_ne!(a, unscoped);
+        assert_ne!(a, b);
+        assert_eq!(b.source.scope(), Some("b".into()));
+        assert_eq!(b.source.unscoped(), &unscoped.source);
+
+        let cache = crate::AssetCache::new();
+        let data = Arc::new(ImageData::new(ImageBuffer::from_pixel(
+            1,
+            1,
+            Bgra([0, 0, 0, 255]),
+        )));
+        for source in [&unscoped, &a, &b] {
+            cache.insert::<Image>(source.clone(), Ok(data.clone()));
+        }
+        cache.clear_scope("a");
+        assert!(cache.get::<Image>(&a).is_none());
+        assert!(cache.get::<Image>(&b).is_some());
+        assert!(cache.get::<Image>(&unscoped).is_some());
+    }
+
+    #[test]
+    fn test_image_diff() {
+        let gray = ImageData::new(ImageBuffer::from_pixel(2, 2, Bgra([128, 128, 128, 255])));
+        let mut buffer = ImageBuffer::from_pixel(2, 2, Bgra([128, 128, 128, 255]));
+        buffer.put_pixel(0, 1, Bgra([128, 131, 126, 255]));
+        let nudged = ImageData::new(buffer);
+
+        assert_eq!(
+            gray.diff(&nudged),
+            ImageDiff {
+                max_channel_delta: 3,
+                mismatched_pixels: 1,
+                dimensions_match: true,
+            }
+        );
+        assert!(gray.diff(&gray).is_within(0));
+        assert!(gray.diff(&nudged).is_within(3));
+        assert!(!gray.diff(&nudged).is_within(2));
+        crate::assert_images_similar(&gray, &nudged, 3);
+
+        let wide = ImageData::new(ImageBuffer::from_pixel(4, 1, Bgra([128, 128, 128, 255])));
+        assert!(!gray.diff(&wide).dimensions_match);
+        assert!(!gray.diff(&wide).is_within(u8::MAX));
+    }
+
+    #[test]
+    fn test_fit_to_axis() {
+        let mut style = Style {
+            size: size(px(10.).into(), px(20.).into()),
+            aspect_ratio: Some(2.0),
+            ..Default::default()
+        };
+        let full = |length: Length| matches!(length, Length::Definite(DefiniteLength::Fraction(f)) if f == 1.0);
+        fit_to_axis(&mut style, Axis::Horizontal, None);
+        assert!(full(style.size.width));
+        assert!(matches!(style.size.height, Length::Auto));
+        assert_eq!(style.aspect_ratio, Some(2.0));
+
+        fit_to_axis(&mut style, Axis::Vertical, Some(0.5));
+        assert!(matches!(style.size.width, Length::Auto));
+        assert!(full(style.size.height));
+        assert_eq!(style.aspect_ratio, Some(0.5));
+    }
+
+    #[test]
+    fn test_frame_index_at() {
+        let frame = |value| ImageBuffer::from_pixel(1, 1, Bgra([value, 0, 0, 255]));
+        let frames = |loop_count| {
+            ImageData::from_frames(
+                [
+                    (frame(0), Duration::from_millis(100)),
+                    (frame(1), Duration::from_millis(50)),
+                    // Too short to be respected, so it's shown for 100ms.
+                    (frame(2), Duration::from_millis(10)),
+                ],
+                loop_count,
+            )
+        };
+        let at = |data: &ImageData, millis| {
+            frame_index_at(Frames::Decoded(data), Duration::from_millis(millis))
+        };
+
+        let looping = frames(LoopCount::Infinite);
+        assert_eq!(at(&looping, 0), 0);
+        assert_eq!(at(&looping, 99), 0);
+        assert_eq!(at(&looping, 100), 1);
+        assert_eq!(at(&looping, 150), 2);
+        assert_eq!(at(&looping, 249), 2);
+        assert_eq!(at(&looping, 250), 0);
+        assert_eq!(at(&looping, 2_600), 1);
+
+        let twice = frames(LoopCount::Finite(2));
+        assert_eq!(at(&twice, 350), 1);
+        assert_eq!(at(&twice, 500), 2);
+
+        let still = ImageData::new(frame(7));
+        assert_eq!(at(&still, 1_000), 0);
+        assert_eq!(still.single_frame(0).as_bytes(), still.as_bytes());
+        assert_eq!(
+            looping.single_frame(2).as_bytes(),
+            frame(2).as_raw().as_slice()
+        );
+        assert!(!looping.single_frame(1).is_animated());
+    }
+
+    #[test]
+    fn test_sharpen_increases_contrast_at_edges() {
+        let mut buffer = ImageBuffer::from_pixel(8, 1, Bgra([64, 64, 64, 255]));
+        for x in 4..8 {
+            buffer.put_pixel(x, 0, Bgra([192, 192, 192, 255]));
+        }
+        assert_eq!(sharpen(&buffer, 0.), buffer);
+
+        let sharpened = sharpen(&buffer, 1.);
+        assert!(sharpened.get_pixel(3, 0).0[0] < 64);
+        assert!(sharpened.get_pixel(4, 0).0[0] > 192);
+        assert_eq!(sharpened.get_pixel(4, 0).0[3], 255);
+
+        let data = Arc::new(ImageData::new(buffer));
+        assert_eq!(
+            img(data.clone()).sharpen(0.).decode_options.sharpen_amount,
+            None
+        );
+        assert_eq!(
+            img(data).sharpen(10.).decode_options.sharpen_amount,
+            Some(MAX_SHARPEN_AMOUNT.to_bits())
+        );
+    }
+
+    #[test]
+    fn test_blur_keeps_dimensions() {
+        let buffer = ImageBuffer::from_pixel(64, 48, Bgra([0, 0, 255, 255]));
+        assert_eq!(blur(&buffer, 2.).dimensions(), (64, 48));
+        // Wide blurs are computed on a downsampled copy.
+        assert_eq!(blur(&buffer, 20.).dimensions(), (64, 48));
+    }
+
+    #[test]
+    fn test_animated_data_plays_like_a_decoded_animation() {
+        let frame = |value| {
+            Arc::new(ImageData::new(ImageBuffer::from_pixel(
+                2,
+                1,
+                Bgra([value, 0, 0, 255]),
+            )))
+        };
+        let delays = vec![Duration::from_millis(100), Duration::from_millis(50)];
+        assert!(AnimatedImageData::new(Vec::new(), Vec::new(), LoopCount::Infinite).is_err());
+        assert!(
+            AnimatedImageData::new(vec![frame(0)], delays.clone(), LoopCount::Infinite).is_err()
+        );
+        let wide = Arc::new(ImageData::new(ImageBuffer::from_pixel(3, 1, Bgra([0; 4]))));
+        assert!(
+            AnimatedImageData::new(vec![frame(0), wide], delays.clone(), LoopCount::Infinite)
+                .is_err()
+        );
+
+        let animation =
+            AnimatedImageData::new(vec![frame(0), frame(1)], delays, LoopCount::Finite(1)).unwrap();
+        let frames = Frames::Separate(&animation);
+        assert_eq!(frames.id(), animation.frames()[0].id);
+        assert_eq!(frames.count(), 2);
+        assert_eq!(frames.loop_count(), LoopCount::Finite(1));
+        assert_eq!(frame_index_at(frames, Duration::from_millis(120)), 1);
+        assert_eq!(frame_index_at(frames, Duration::from_secs(1)), 1);
+    }
+
+    #[test]
+    fn test_raw_frame_conversion() {
+        let frame_size = size(DevicePixels(2), DevicePixels(1));
+        assert!(RawFrame::new(vec![0; 7], FrameFormat::Rgba8, frame_size).is_err());
+        let negative_size = size(DevicePixels(-2), DevicePixels(-1));
+        assert!(RawFrame::new(vec![0; 8], FrameFormat::Rgba8, negative_size).is_err());
+        let huge_size = size(DevicePixels(i32::MAX), DevicePixels(i32::MAX));
+        assert!(RawFrame::new(vec![0; 8], FrameFormat::Rgba8, huge_size).is_err());
+
+        let rgba = RawFrame::new(vec![1, 2, 3, 4, 5, 6, 7, 8], FrameFormat::Rgba8, frame_size);
+        assert_eq!(
+            rgba.unwrap().to_bgra().into_raw(),
+            vec![3, 2, 1, 4, 7, 6, 5, 8]
+        );
+
+        // Black and white luma sharing one neutral chroma sample.
+        let nv12 = RawFrame::new(vec![16, 235, 128, 128], FrameFormat::Nv12, frame_size);
+        assert_eq!(
+            nv12.unwrap().to_bgra().into_raw(),
+            vec![0, 0, 0, 255, 255, 255, 255, 255]
+        );
+    }
+
+    #[gpui::test]
+    fn test_load_listeners_fire_once(cx: &mut TestAppContext) {
+        let cx = cx.add_empty_window();
+        let data = Arc::new(ImageData::new(ImageBuffer::from_pixel(
+            2,
+            2,
+            Bgra([0, 0, 0, 255]),
+        )));
+        let loads = Rc::new(Cell::new(0));
+        for _ in 0..3 {
+            let loads = loads.clone();
+            cx.draw(point(px(0.), px(0.)), size(px(100.), px(100.)), |_| {
+                img(data.clone())
+                    .id("image")
+                    .on_load(move |_, _| loads.set(loads.get() + 1))
+            });
+        }
+        assert_eq!(loads.get(), 1);
+
+        // Without an id, there's nowhere to remember the image was already reported.
+        let loads_without_id = Rc::new(Cell::new(0));
+        for _ in 0..3 {
+            let loads = loads_without_id.clone();
+            cx.draw(point(px(0.), px(0.)), size(px(100.), px(100.)), |_| {
+                img(data.clone()).on_load(move |_, _| loads.set(loads.get() + 1))
+            });
+        }
+        assert_eq!(loads_without_id.get(), 0);
+    }
+
+    #[gpui::test]
+    fn test_concurrent_size_queries_share_a_load(cx: &mut TestAppContext) {
+        let cx = cx.add_empty_window();
+        let source = ImageSource::from(Arc::<[u8]>::from(&b"not an image"[..]));
+        cx.update(|cx| {
+            let _first = cx.image_size(source.clone());
+            let _second = cx.image_size(source.clone());
+            let _thumbnail = cx.image_thumbnail(
+                source.clone(),
+                size(DevicePixels(8), DevicePixels(8)),
+                ObjectFit::Cover,
+            );
+            assert_eq!(cx.loading_assets.len(), 2);
+        });
+    }
+
+    #[gpui::test]
+    fn test_alt_text_labels_the_hitbox(cx: &mut TestAppContext) {
+        let cx = cx.add_empty_window();
+        let data = Arc::new(ImageData::new(ImageBuffer::from_pixel(
+            2,
+            2,
+            Bgra([0, 0, 0, 255]),
+        )));
+
+        let (_, hitbox) = cx.draw(point(px(0.), px(0.)), size(px(100.), px(100.)), |_| {
+            img(data.clone()).alt("A black square")
+        });
+        assert_eq!(
+            hitbox.and_then(|hitbox| hitbox.label),
+            Some("A black square".into())
+        );
+
+        let (_, hitbox) = cx.draw(point(px(0.), px(0.)), size(px(100.), px(100.)), |_| {
+            img(data.clone()).alt_decorative()
+        });
+        assert!(hitbox.is_none());
     }
 }
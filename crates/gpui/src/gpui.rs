@@ -77,6 +77,13 @@ mod element;
 mod elements;
 mod executor;
 mod geometry;
+mod image_decoder;
+mod image_disk_cache;
+mod image_fetch;
+mod image_icc;
+mod image_load_queue;
+mod image_tiff;
+mod image_zoom;
 mod input;
 mod interactive;
 mod key_dispatch;
@@ -126,6 +133,13 @@ pub use elements::*;
 pub use executor::*;
 pub use geometry::*;
 pub use gpui_macros::{register_action, test, IntoElement, Render};
+use image_decoder::*;
+use image_disk_cache::*;
+pub use image_fetch::*;
+use image_icc::*;
+pub use image_load_queue::*;
+use image_tiff::*;
+pub use image_zoom::*;
 pub use input::*;
 pub use interactive::*;
 use key_dispatch::*;
@@ -141,6 +155,10 @@ pub use style::*;
 pub use styled::*;
 pub use subscription::*;
 use svg_renderer::*;
+pub use svg_renderer::{
+    fontdb, ImageRendering, ShapeRendering, SvgFailurePolicy, SvgFontLoading, SvgOptions,
+    SvgQuality, TextRendering,
+};
 pub use taffy::{AvailableSpace, LayoutId};
 #[cfg(any(test, feature = "test-support"))]
 pub use test::*;
@@ -0,0 +1,876 @@
+use std::io::Cursor;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use image::{
+    codecs::{gif::GifDecoder, ico::IcoDecoder, jpeg::JpegDecoder, png::PngDecoder},
+    error::{DecodingError, UnsupportedErrorKind},
+    AnimationDecoder, Bgra, ColorType, DynamicImage, Frame, Frames, GenericImageView, ImageBuffer,
+    ImageDecoder, ImageError, ImageFormat, RgbaImage,
+};
+
+use crate::{
+    by_size_preference, decode_tiff_page, size, DecodeOptions, DevicePixels, FrameTiming,
+    ImageCacheError, ImageData, LoopCount, Size,
+};
+
+/// Read the dimensions of a raster image from its header, swapped if `respect_orientation`
+/// is set and its EXIF orientation rotates it a quarter turn.
+pub(crate) fn raster_size(
+    bytes: &[u8],
+    format: ImageFormat,
+    respect_orientation: bool,
+) -> Result<Size<DevicePixels>, ImageError> {
+    // Only the header needs to be decoded to find the dimensions.
+    let (width, height) =
+        image::io::Reader::with_format(Cursor::new(bytes), format).into_dimensions()?;
+    let rotated = respect_orientation
+        && matches!(format, ImageFormat::Jpeg | ImageFormat::Tiff)
+        && matches!(exif_orientation(bytes, format), Some(5..=8));
+    Ok(if rotated {
+        size(height.into(), width.into())
+    } else {
+        size(width.into(), height.into())
+    })
+}
+
+/// One of the images in an ICO, as listed in its directory.
+struct IcoEntry {
+    size: Size<DevicePixels>,
+    /// The entry's 16 bytes in the directory.
+    directory_entry: [u8; 16],
+    /// Where the image's PNG or BMP data is in the file.
+    data: Range<usize>,
+}
+
+/// List the images in an ICO, skipping any whose data starts past the end of the file.
+fn ico_entries(bytes: &[u8]) -> Vec<IcoEntry> {
+    let Some(count) = bytes.get(4..6) else {
+        return Vec::new();
+    };
+    let count = u16::from_le_bytes([count[0], count[1]]);
+    (0..usize::from(count))
+        .filter_map(|index| {
+            let start = 6 + index * 16;
+            let directory_entry: [u8; 16] = bytes.get(start..start + 16)?.try_into().ok()?;
+            let read_u32 = |start: usize| {
+                let field = &directory_entry[start..start + 4];
+                u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize
+            };
+            let length = read_u32(8);
+            let offset = read_u32(12);
+            if offset >= bytes.len() {
+                return None;
+            }
+            // A width or height of 0 stands for 256, the largest an ICO's directory can hold.
+            let dimension = |byte: u8| DevicePixels(if byte == 0 { 256 } else { byte.into() });
+            Some(IcoEntry {
+                size: size(dimension(directory_entry[0]), dimension(directory_entry[1])),
+                directory_entry,
+                data: offset..offset.saturating_add(length).min(bytes.len()),
+            })
+        })
+        .collect()
+}
+
+/// Decode the image in an ICO that best suits `display_size`: the smallest that's at least
+/// that large, or the largest if none are or no size is given. Images that fail to decode
+/// are skipped in favor of the next best one.
+pub(crate) fn decode_ico(
+    bytes: &[u8],
+    display_size: Option<Size<DevicePixels>>,
+) -> Result<ImageData, ImageCacheError> {
+    let entries = ico_entries(bytes);
+    let candidates = entries
+        .iter()
+        .map(|entry| (entry.size, entry))
+        .collect::<Vec<_>>();
+    let display_size =
+        display_size.unwrap_or_else(|| size(DevicePixels(i32::MAX), DevicePixels(i32::MAX)));
+    for entry in by_size_preference(&candidates, display_size) {
+        // Decode the entry as an ICO of its own, so the decoder can't pick another one.
+        let data = &bytes[entry.data.clone()];
+        let mut ico = Vec::with_capacity(22 + data.len());
+        ico.extend_from_slice(&bytes[..4]);
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&entry.directory_entry[..8]);
+        ico.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&22u32.to_le_bytes());
+        ico.extend_from_slice(data);
+        let decoded = IcoDecoder::new(Cursor::new(ico)).and_then(DynamicImage::from_decoder);
+        if let Ok(image) = decoded {
+            return Ok(ImageData::new(image.into_bgra8())
+                .with_natural_size(entry.size)
+                .with_embedded_sizes(entries.iter().map(|entry| entry.size).collect()));
+        }
+    }
+    Err(ImageCacheError::NoDecodableFrames)
+}
+
+pub(crate) fn decode_gif(bytes: &[u8], max_pixels: u64) -> Result<ImageData, ImageCacheError> {
+    let frames = decode_frames(
+        GifDecoder::new(Cursor::new(bytes))?.into_frames(),
+        max_pixels,
+    )?;
+    if frames.is_empty() {
+        return Err(ImageError::Decoding(DecodingError::new(
+            ImageFormat::Gif.into(),
+            "GIF contains no frames",
+        ))
+        .into());
+    }
+    Ok(ImageData::from_frames(frames, gif_loop_count(bytes)))
+}
+
+/// Decode every frame of an animated PNG, along with how long each is shown for.
+pub(crate) fn decode_apng(
+    bytes: &[u8],
+    loop_count: LoopCount,
+    max_pixels: u64,
+) -> Result<ImageData, ImageCacheError> {
+    let frames = decode_frames(
+        PngDecoder::new(Cursor::new(bytes))?.apng().into_frames(),
+        max_pixels,
+    )?;
+    if frames.is_empty() {
+        return Err(ImageError::Decoding(DecodingError::new(
+            ImageFormat::Png.into(),
+            "APNG contains no frames",
+        ))
+        .into());
+    }
+    Ok(ImageData::from_frames(frames, loop_count))
+}
+
+/// Decode an animated GIF or PNG a frame at a time, until `find` picks the frame decoded
+/// last given the delays of those decoded so far, or `None` if the image isn't animated.
+/// Only the chosen frame is kept, so picking an early frame doesn't decode the rest of the
+/// animation. If `find` only picks a frame once every delay is known, the frames are
+/// decoded again up to that one. Returns the frame and its index, along with the timing of
+/// the frames that were decoded.
+pub(crate) fn decode_animation_frame(
+    bytes: &[u8],
+    format: ImageFormat,
+    max_pixels: u64,
+    mut find: impl FnMut(&FrameTiming) -> Option<usize>,
+) -> Result<Option<(usize, ImageData, FrameTiming)>, ImageCacheError> {
+    let Some((frames, loop_count)) = animation_frames(bytes, format)? else {
+        return Ok(None);
+    };
+    let into_data =
+        |frame: Frame| ImageData::new(DynamicImage::ImageRgba8(frame.into_buffer()).into_bgra8());
+    let no_frames = || -> ImageCacheError {
+        ImageError::Decoding(DecodingError::new(
+            format.into(),
+            "animation contains no frames",
+        ))
+        .into()
+    };
+
+    let mut pixel_counter = PixelCounter::new(max_pixels);
+    let mut timing = FrameTiming::default();
+    let mut last_frame = None;
+    for frame in frames {
+        let frame = frame?;
+        pixel_counter.add(frame.buffer())?;
+        timing.delays.push(frame.delay().into());
+        let frame_index = timing.delays.len() - 1;
+        if find(&timing) == Some(frame_index) {
+            return Ok(Some((frame_index, into_data(frame), timing)));
+        }
+        last_frame = Some(frame);
+    }
+
+    timing.loop_count = Some(loop_count);
+    let last_frame = last_frame.ok_or_else(no_frames)?;
+    let last_frame_index = timing.delays.len() - 1;
+    let (frame_index, frame) = match find(&timing) {
+        Some(frame_index) if frame_index < last_frame_index => {
+            // The frames up to this one were decoded within the limit the first time.
+            let frame = match animation_frames(bytes, format)? {
+                Some((mut frames, _)) => frames.nth(frame_index).transpose()?,
+                None => None,
+            };
+            (frame_index, frame.ok_or_else(no_frames)?)
+        }
+        _ => (last_frame_index, last_frame),
+    };
+    Ok(Some((frame_index, into_data(frame), timing)))
+}
+
+/// The frames of an animated GIF or PNG, to be decoded one at a time, along with how many
+/// times the animation plays, or `None` if the image isn't animated.
+fn animation_frames(
+    bytes: &[u8],
+    format: ImageFormat,
+) -> Result<Option<(Frames<'_>, LoopCount)>, ImageCacheError> {
+    if format == ImageFormat::Gif {
+        let frames = GifDecoder::new(Cursor::new(bytes))?.into_frames();
+        return Ok(Some((frames, gif_loop_count(bytes))));
+    }
+    match apng_loop_count(bytes, format) {
+        Some(loop_count) => {
+            let frames = PngDecoder::new(Cursor::new(bytes))?.apng().into_frames();
+            Ok(Some((frames, loop_count)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Decode an animation's frames, along with how long each is shown for. Each frame is
+/// checked against `max_pixels` only through the header's dimensions, so the frames'
+/// pixels are also totaled as they're decoded, and decoding fails with
+/// [`ImageCacheError::TooLarge`] once there are more than `max_pixels` of them.
+fn decode_frames(
+    frames: Frames<'_>,
+    max_pixels: u64,
+) -> Result<Vec<(ImageBuffer<Bgra<u8>, Vec<u8>>, Duration)>, ImageCacheError> {
+    let mut pixel_counter = PixelCounter::new(max_pixels);
+    let mut decoded = Vec::new();
+    for frame in frames {
+        let frame = frame?;
+        pixel_counter.add(frame.buffer())?;
+        let delay: Duration = frame.delay().into();
+        decoded.push((
+            DynamicImage::ImageRgba8(frame.into_buffer()).into_bgra8(),
+            delay,
+        ));
+    }
+    Ok(decoded)
+}
+
+/// A running total of the pixels in an animation's frames.
+struct PixelCounter {
+    max_pixels: u64,
+    pixels: u64,
+    height: u32,
+}
+
+impl PixelCounter {
+    fn new(max_pixels: u64) -> Self {
+        Self {
+            max_pixels,
+            pixels: 0,
+            height: 0,
+        }
+    }
+
+    /// Count a decoded frame, failing once the frames counted so far have more than
+    /// `max_pixels` between them. The error reports the frames as if stacked on top of
+    /// each other, since an animation's frames all share its dimensions.
+    fn add(&mut self, frame: &RgbaImage) -> Result<(), ImageCacheError> {
+        let (width, height) = frame.dimensions();
+        self.pixels = self
+            .pixels
+            .saturating_add(u64::from(width) * u64::from(height));
+        self.height = self.height.saturating_add(height);
+        if self.pixels > self.max_pixels {
+            Err(ImageCacheError::TooLarge {
+                width,
+                height: self.height,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Decode a single page of a multi-page TIFF, or a single frame of an animated GIF. Other
+/// formats only have a single page.
+pub(crate) fn decode_page(
+    bytes: &[u8],
+    format: ImageFormat,
+    page: usize,
+    options: &DecodeOptions,
+    max_pixels: u64,
+) -> Result<ImageData, ImageCacheError> {
+    match format {
+        ImageFormat::Tiff => Ok(ImageData::new(
+            decode_tiff_page(bytes, page, max_pixels)?.into_bgra8(),
+        )),
+        ImageFormat::Gif => {
+            let mut frames = GifDecoder::new(Cursor::new(bytes))?.into_frames();
+            let mut pixel_counter = PixelCounter::new(max_pixels);
+            let mut page_count = 0;
+            while let Some(frame) = frames.next().transpose()? {
+                // Every frame up to the page is decoded, so they all count toward the limit.
+                pixel_counter.add(frame.buffer())?;
+                if page_count == page {
+                    return Ok(ImageData::new(
+                        DynamicImage::ImageRgba8(frame.into_buffer()).into_bgra8(),
+                    ));
+                }
+                page_count += 1;
+            }
+            Err(ImageCacheError::PageOutOfRange { page, page_count })
+        }
+        _ if page == 0 => {
+            let DecodedRaster { image, partial } = decode_raster(bytes, format, options)?;
+            Ok(ImageData::new(image.into_bgra8()).with_partial(partial))
+        }
+        _ => Err(ImageCacheError::PageOutOfRange {
+            page,
+            page_count: 1,
+        }),
+    }
+}
+
+/// The default for [`crate::AppContext::set_image_max_pixels`].
+pub(crate) const DEFAULT_MAX_IMAGE_PIXELS: u64 = 128 * 1024 * 1024;
+
+/// Fail with [`ImageCacheError::TooLarge`] if the dimensions in a raster image's header
+/// exceed `max_pixels`, before any memory is allocated for its pixels. Images whose header
+/// can't be read are left for the decoder to report.
+pub(crate) fn check_header_size(
+    bytes: &[u8],
+    format: ImageFormat,
+    max_pixels: u64,
+) -> Result<(), ImageCacheError> {
+    match image::io::Reader::with_format(Cursor::new(bytes), format).into_dimensions() {
+        Ok((width, height)) => check_size(width, height, max_pixels),
+        Err(_) => Ok(()),
+    }
+}
+
+pub(crate) fn check_size(width: u32, height: u32, max_pixels: u64) -> Result<(), ImageCacheError> {
+    if u64::from(width) * u64::from(height) > max_pixels {
+        Err(ImageCacheError::TooLarge { width, height })
+    } else {
+        Ok(())
+    }
+}
+
+/// A decoded raster image, which may be missing the part of the image its encoded data
+/// was truncated before.
+pub(crate) struct DecodedRaster {
+    pub(crate) image: DynamicImage,
+    pub(crate) partial: bool,
+}
+
+/// Decode a still raster image, applying its EXIF orientation and any maximum size.
+pub(crate) fn decode_raster(
+    bytes: &[u8],
+    format: ImageFormat,
+    options: &DecodeOptions,
+) -> Result<DecodedRaster, ImageCacheError> {
+    let orientation = if options.respect_exif_orientation {
+        exif_orientation(bytes, format)
+    } else {
+        None
+    };
+
+    let decode = || -> Result<DynamicImage, ImageError> {
+        match (format, options.max_decode_size) {
+            (ImageFormat::Jpeg, Some(max_size)) => {
+                // JPEGs can be decoded at 1/2, 1/4 or 1/8 scale without ever producing the
+                // full resolution pixels. The orientation hasn't been applied yet, so a
+                // rotated image's maximum size is rotated too.
+                let max_size = if matches!(orientation, Some(5..=8)) {
+                    size(max_size.height, max_size.width)
+                } else {
+                    max_size
+                };
+                let to_u16 =
+                    |dimension: DevicePixels| u32::from(dimension).min(u16::MAX as u32) as u16;
+                let mut decoder = JpegDecoder::new(Cursor::new(bytes))?;
+                decoder.scale(to_u16(max_size.width), to_u16(max_size.height))?;
+                DynamicImage::from_decoder(decoder)
+            }
+            _ => image::load_from_memory_with_format(bytes, format),
+        }
+    };
+    let (mut image, partial) = match decode() {
+        Ok(image) => (image, false),
+        Err(error) if is_truncated(bytes, format) => match decode_partial(bytes, format) {
+            Some(image) => (image, true),
+            None => return Err(ImageCacheError::Truncated(Arc::new(error))),
+        },
+        Err(error) => return Err(error.into()),
+    };
+
+    if let Some(orientation) = orientation {
+        image = apply_exif_orientation(image, orientation);
+    }
+
+    if let Some(max_size) = options.max_decode_size {
+        let max_width = u32::from(max_size.width).max(1);
+        let max_height = u32::from(max_size.height).max(1);
+        if image.width() > max_width || image.height() > max_height {
+            image = image.thumbnail(max_width, max_height);
+        }
+    }
+
+    Ok(DecodedRaster { image, partial })
+}
+
+/// Report a raster format that has no decoder, such as AVIF, as
+/// [`ImageCacheError::UnknownFormat`] rather than as an error from the `image` crate.
+pub(crate) fn unsupported_format(
+    error: ImageCacheError,
+    format: ImageFormat,
+    content_type: Option<&str>,
+) -> ImageCacheError {
+    let is_unsupported = match &error {
+        ImageCacheError::Image(error) => match &**error {
+            ImageError::Unsupported(error) => {
+                matches!(error.kind(), UnsupportedErrorKind::Format(_))
+            }
+            _ => false,
+        },
+        _ => false,
+    };
+    if is_unsupported {
+        ImageCacheError::UnknownFormat {
+            sniffed: Some(format),
+            content_type: content_type.map(str::to_string),
+        }
+    } else {
+        error
+    }
+}
+
+/// Whether an image that failed to decode is missing the marker its format ends with,
+/// which suggests that its data was cut short rather than corrupted.
+fn is_truncated(bytes: &[u8], format: ImageFormat) -> bool {
+    match format {
+        ImageFormat::Png => !bytes.ends_with(b"IEND\xae\x42\x60\x82"),
+        ImageFormat::Jpeg => !bytes.ends_with(&[0xff, 0xd9]),
+        ImageFormat::Gif => !bytes.ends_with(&[0x3b]),
+        _ => false,
+    }
+}
+
+/// Decode as much of a truncated image as possible. Only PNGs are supported, since their
+/// rows are decoded one after another. The rows that are missing are left transparent, or
+/// black for images without an alpha channel.
+fn decode_partial(bytes: &[u8], format: ImageFormat) -> Option<DynamicImage> {
+    if format != ImageFormat::Png {
+        return None;
+    }
+    let decoder = PngDecoder::new(Cursor::new(bytes)).ok()?;
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    let mut buffer = vec![0; usize::try_from(decoder.total_bytes()).ok()?];
+    // The rows decoded before the data ran out are kept in the buffer.
+    decoder.read_image(&mut buffer).ok();
+    match color_type {
+        ColorType::L8 => ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLuma8),
+        ColorType::La8 => {
+            ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLumaA8)
+        }
+        ColorType::Rgb8 => {
+            ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageRgb8)
+        }
+        ColorType::Rgba8 => {
+            ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageRgba8)
+        }
+        _ => None,
+    }
+}
+
+/// Find the EXIF orientation tag of a JPEG or TIFF image, a value from 1 to 8.
+fn exif_orientation(bytes: &[u8], format: ImageFormat) -> Option<u16> {
+    const ORIENTATION_TAG: u16 = 0x0112;
+
+    let tiff = match format {
+        ImageFormat::Tiff => bytes,
+        ImageFormat::Jpeg => {
+            // Walk the JPEG segments looking for the APP1 segment holding the EXIF data.
+            let mut offset = 2;
+            loop {
+                let marker = bytes.get(offset..offset + 2)?;
+                let length = u16::from_be_bytes([*bytes.get(offset + 2)?, *bytes.get(offset + 3)?]);
+                let segment = bytes.get(offset + 4..offset + 2 + length as usize)?;
+                match marker {
+                    [0xFF, 0xE1] if segment.starts_with(b"Exif\0\0") => break &segment[6..],
+                    // Start of scan, no metadata follows.
+                    [0xFF, 0xDA] => return None,
+                    [0xFF, _] => offset += 2 + length as usize,
+                    _ => return None,
+                }
+            }
+        }
+        _ => return None,
+    };
+
+    let big_endian = match tiff.get(0..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd_offset = read_u32(4)? as usize;
+    let entry_count = read_u16(ifd_offset)? as usize;
+    (0..entry_count).find_map(|index| {
+        let entry_offset = ifd_offset + 2 + index * 12;
+        if read_u16(entry_offset)? == ORIENTATION_TAG {
+            read_u16(entry_offset + 8).filter(|orientation| (1..=8).contains(orientation))
+        } else {
+            None
+        }
+    })
+}
+
+/// Rotate and flip an image so that it's displayed upright, given its EXIF orientation.
+fn apply_exif_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Read the loop count from a GIF's `NETSCAPE2.0` application extension. GIFs without
+/// the extension play once. The blocks are walked in order, so image data that happens to
+/// contain the extension's identifier isn't mistaken for it.
+fn gif_loop_count(bytes: &[u8]) -> LoopCount {
+    gif_loop_count_extension(bytes).unwrap_or(LoopCount::Finite(1))
+}
+
+fn gif_loop_count_extension(bytes: &[u8]) -> Option<LoopCount> {
+    const HEADER_LEN: usize = 6;
+    const SCREEN_DESCRIPTOR_LEN: usize = 7;
+    const IMAGE_DESCRIPTOR_LEN: usize = 9;
+    let color_table_len = |flags: u8| {
+        if flags & 0x80 == 0 {
+            0
+        } else {
+            3 << ((flags & 0x07) + 1)
+        }
+    };
+    // Skip a sequence of sub-blocks, returning the offset after its terminator.
+    let skip_sub_blocks = |mut offset: usize| -> Option<usize> {
+        loop {
+            let len = *bytes.get(offset)? as usize;
+            offset += 1;
+            if len == 0 {
+                return Some(offset);
+            }
+            offset += len;
+        }
+    };
+
+    let screen_flags = *bytes.get(HEADER_LEN + 4)?;
+    let mut offset = HEADER_LEN + SCREEN_DESCRIPTOR_LEN + color_table_len(screen_flags);
+    loop {
+        match *bytes.get(offset)? {
+            // An extension, made of a label and a sequence of sub-blocks.
+            0x21 => {
+                let label = *bytes.get(offset + 1)?;
+                let data_start = offset + 2;
+                if label == 0xFF && bytes.get(data_start..data_start + 12)? == b"\x0BNETSCAPE2.0" {
+                    return match bytes.get(data_start + 12..data_start + 16)? {
+                        &[3, 1, low, high] => match u16::from_le_bytes([low, high]) {
+                            0 => Some(LoopCount::Infinite),
+                            repeats => Some(LoopCount::Finite(u32::from(repeats) + 1)),
+                        },
+                        _ => None,
+                    };
+                }
+                offset = skip_sub_blocks(data_start)?;
+            }
+            // An image, made of a descriptor, an optional color table, the LZW minimum code
+            // size and a sequence of sub-blocks holding the pixel data.
+            0x2C => {
+                let image_flags = *bytes.get(offset + IMAGE_DESCRIPTOR_LEN)?;
+                let data_start = offset + 1 + IMAGE_DESCRIPTOR_LEN + color_table_len(image_flags);
+                offset = skip_sub_blocks(data_start + 1)?;
+            }
+            // The trailer, or data that isn't part of a valid GIF.
+            _ => return None,
+        }
+    }
+}
+
+/// Find how many times an animated PNG plays, or `None` if the image isn't an animated PNG.
+/// APNGs are identified by an `acTL` chunk before the image data, so only the chunk headers
+/// up to the first `IDAT` chunk are read.
+pub(crate) fn apng_loop_count(bytes: &[u8], format: ImageFormat) -> Option<LoopCount> {
+    const SIGNATURE_LEN: usize = 8;
+    if format != ImageFormat::Png {
+        return None;
+    }
+    let mut offset = SIGNATURE_LEN;
+    loop {
+        let length = u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let chunk_type = bytes.get(offset + 4..offset + 8)?;
+        let data_start = offset + 8;
+        match chunk_type {
+            b"IDAT" => return None,
+            b"acTL" => {
+                // The animation control chunk holds the number of frames, then of plays.
+                let plays = bytes.get(data_start + 4..data_start + 8)?;
+                return match u32::from_be_bytes(plays.try_into().ok()?) {
+                    0 => Some(LoopCount::Infinite),
+                    plays => Some(LoopCount::Finite(plays)),
+                };
+            }
+            // Skip the chunk's data and its trailing CRC.
+            _ => offset = data_start.checked_add(length)?.checked_add(4)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{codecs::gif::GifEncoder, Delay};
+
+    #[test]
+    fn test_gif_loop_count() {
+        // A header and a 1x1 logical screen without a global color table.
+        let header = b"GIF89a\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        assert_eq!(gif_loop_count(&header), LoopCount::Finite(1));
+
+        // An image whose pixel data contains the extension's identifier.
+        let mut image = header.clone();
+        image.extend_from_slice(b"\x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02");
+        image.extend_from_slice(b"\x12\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00\x3B");
+        assert_eq!(gif_loop_count(&image), LoopCount::Finite(1));
+
+        let mut infinite = header.clone();
+        infinite.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+        assert_eq!(gif_loop_count(&infinite), LoopCount::Infinite);
+
+        let mut twice = header;
+        twice.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x01\x00\x00");
+        assert_eq!(gif_loop_count(&twice), LoopCount::Finite(2));
+    }
+
+    #[test]
+    fn test_apng_loop_count() {
+        let chunk = |chunk_type: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(chunk_type);
+            chunk.extend_from_slice(data);
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(b"IHDR", &[0; 13]));
+
+        let mut still = png.clone();
+        still.extend(chunk(b"IDAT", &[0; 4]));
+        still.extend(chunk(b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]));
+        assert_eq!(apng_loop_count(&still, ImageFormat::Png), None);
+
+        let mut infinite = png.clone();
+        infinite.extend(chunk(b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]));
+        infinite.extend(chunk(b"IDAT", &[0; 4]));
+        assert_eq!(
+            apng_loop_count(&infinite, ImageFormat::Png),
+            Some(LoopCount::Infinite)
+        );
+        assert_eq!(apng_loop_count(&infinite, ImageFormat::Gif), None);
+
+        let mut three_times = png;
+        three_times.extend(chunk(b"acTL", &[0, 0, 0, 2, 0, 0, 0, 3]));
+        assert_eq!(
+            apng_loop_count(&three_times, ImageFormat::Png),
+            Some(LoopCount::Finite(3))
+        );
+    }
+
+    #[test]
+    fn test_decode_raster_recovers_truncated_pngs() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_fn(64, 64, |x, y| {
+            image::Rgba([x as u8 * 4, y as u8 * 4, 0, 255])
+        }));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let options = DecodeOptions::default();
+
+        let decoded = decode_raster(&png, ImageFormat::Png, &options).unwrap();
+        assert!(!decoded.partial);
+
+        let truncated = &png[..png.len() / 2];
+        let decoded = decode_raster(truncated, ImageFormat::Png, &options).unwrap();
+        assert!(decoded.partial);
+        assert_eq!((decoded.image.width(), decoded.image.height()), (64, 64));
+
+        let mut corrupt = png.clone();
+        corrupt[png.len() / 2..png.len() - 12].fill(0);
+        assert!(matches!(
+            decode_raster(&corrupt, ImageFormat::Png, &options),
+            Err(ImageCacheError::Image(_))
+        ));
+    }
+
+    #[test]
+    fn test_images_over_the_pixel_limit_are_not_decoded() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::new(64, 32));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+
+        assert!(check_header_size(&png, ImageFormat::Png, DEFAULT_MAX_IMAGE_PIXELS).is_ok());
+        assert!(check_header_size(&png, ImageFormat::Png, 64 * 32).is_ok());
+        assert!(matches!(
+            check_header_size(&png, ImageFormat::Png, 64 * 32 - 1),
+            Err(ImageCacheError::TooLarge {
+                width: 64,
+                height: 32
+            })
+        ));
+        // Headers that can't be read are reported by the decoder instead.
+        assert!(check_header_size(&png[..8], ImageFormat::Png, 0).is_ok());
+    }
+
+    #[test]
+    fn test_animation_frames_are_only_decoded_up_to_the_one_shown() {
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames((0..4).map(|_| {
+                let delay = Delay::from_numer_denom_ms(100, 1);
+                Frame::from_parts(ImageBuffer::new(8, 8), 0, 0, delay)
+            }))
+            .unwrap();
+        let decode = |time| {
+            decode_animation_frame(&gif, ImageFormat::Gif, u64::MAX, |timing: &FrameTiming| {
+                timing.frame_index_at(time)
+            })
+            .unwrap()
+            .unwrap()
+        };
+
+        let (frame_index, frame, timing) = decode(Duration::from_millis(150));
+        assert_eq!(frame_index, 1);
+        assert_eq!(frame.size(), size(DevicePixels(8), DevicePixels(8)));
+        assert_eq!(timing.delays.len(), 2);
+        assert_eq!(timing.loop_count, None);
+
+        // Finding a frame past the first loop takes every frame's delay, and a GIF that
+        // doesn't say how often it loops plays once.
+        let (frame_index, _, timing) = decode(Duration::from_secs(10));
+        assert_eq!(frame_index, 3);
+        assert_eq!(timing.delays.len(), 4);
+        assert_eq!(timing.loop_count, Some(LoopCount::Finite(1)));
+
+        let mut png = Vec::new();
+        DynamicImage::new_rgba8(1, 1)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        assert!(
+            decode_animation_frame(&png, ImageFormat::Png, u64::MAX, |_| Some(0))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_animations_over_the_pixel_limit_are_not_decoded() {
+        // Every frame is well under the limit on its own, but not all of them together.
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames((0..64).map(|_| Frame::new(ImageBuffer::new(8, 8))))
+            .unwrap();
+
+        assert_eq!(decode_gif(&gif, 64 * 8 * 8).unwrap().frame_count(), 64);
+        assert!(matches!(
+            decode_gif(&gif, 64 * 8 * 8 - 1),
+            Err(ImageCacheError::TooLarge {
+                width: 8,
+                height: 512
+            })
+        ));
+
+        let options = DecodeOptions::default();
+        assert!(decode_page(&gif, ImageFormat::Gif, 15, &options, 16 * 8 * 8).is_ok());
+        assert!(matches!(
+            decode_page(&gif, ImageFormat::Gif, 16, &options, 16 * 8 * 8),
+            Err(ImageCacheError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_ico_picks_the_best_size() {
+        let png = |side: u32| {
+            let mut png = Vec::new();
+            DynamicImage::ImageRgba8(ImageBuffer::new(side, side))
+                .write_to(&mut png, image::ImageOutputFormat::Png)
+                .unwrap();
+            png
+        };
+        let images = [png(16), png(32)];
+        let mut ico = vec![0, 0, 1, 0, 2, 0];
+        let mut offset = 6 + 16 * images.len();
+        for (side, image) in [16u8, 32].into_iter().zip(&images) {
+            ico.extend_from_slice(&[side, side, 0, 0, 1, 0, 32, 0]);
+            ico.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            ico.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += image.len();
+        }
+        for image in &images {
+            ico.extend_from_slice(image);
+        }
+
+        let square = |side| size(DevicePixels(side), DevicePixels(side));
+        let decoded_size = |display_size| decode_ico(&ico, display_size).unwrap().size();
+        assert_eq!(decoded_size(Some(square(10))), square(16));
+        assert_eq!(decoded_size(Some(square(20))), square(32));
+        assert_eq!(decoded_size(Some(square(100))), square(32));
+        assert_eq!(decoded_size(None), square(32));
+        assert_eq!(
+            decode_ico(&ico, None).unwrap().embedded_sizes(),
+            [square(16), square(32)]
+        );
+
+        // Images that fail to decode are skipped.
+        let mut corrupt = ico.clone();
+        let second_image = corrupt.len() - images[1].len();
+        corrupt[second_image..].fill(0);
+        assert_eq!(decode_ico(&corrupt, None).unwrap().size(), square(16));
+        assert!(matches!(
+            decode_ico(&ico[..6], None),
+            Err(ImageCacheError::NoDecodableFrames)
+        ));
+    }
+
+    #[test]
+    fn test_exif_orientation() {
+        // A little-endian TIFF with a single IFD entry holding the orientation tag.
+        let tiff = [
+            b'I', b'I', 42, 0, 8, 0, 0, 0, // header
+            1, 0, // entry count
+            0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0, // orientation = 6
+        ];
+        assert_eq!(exif_orientation(&tiff, ImageFormat::Tiff), Some(6));
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+        assert_eq!(exif_orientation(&jpeg, ImageFormat::Jpeg), Some(6));
+
+        assert_eq!(
+            exif_orientation(&[0xFF, 0xD8, 0xFF, 0xDA], ImageFormat::Jpeg),
+            None
+        );
+    }
+}
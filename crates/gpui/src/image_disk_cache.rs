@@ -0,0 +1,169 @@
+use collections::FxHasher;
+use parking_lot::Mutex;
+use std::{
+    fs,
+    hash::Hasher,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+/// A directory of remote images' encoded bytes, persisted between runs of the app so
+/// that they don't have to be downloaded again. Entries are named after a hash of their
+/// source and store the source itself too, so that a hash collision is a miss rather than
+/// the wrong image. The least recently used entries are removed once the directory grows
+/// past its size limit.
+#[derive(Clone, Debug)]
+pub(crate) struct ImageDiskCache {
+    directory: Arc<Path>,
+    max_bytes: u64,
+    /// The size of the entries in the directory, or `None` until it's first written to.
+    /// The directory is only listed again once this crosses the limit.
+    total_bytes: Arc<Mutex<Option<u64>>>,
+}
+
+impl ImageDiskCache {
+    pub fn new(directory: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            directory: directory.into().into(),
+            max_bytes,
+            total_bytes: Default::default(),
+        }
+    }
+
+    /// Read the bytes stored for the given source, marking them as recently used.
+    pub fn read(&self, source: &str) -> Option<Vec<u8>> {
+        let path = self.entry_path(source);
+        let mut bytes = fs::read(&path).ok()?;
+        let header = entry_header(source);
+        if !bytes.starts_with(&header) {
+            return None;
+        }
+        if let Ok(file) = fs::File::options().append(true).open(&path) {
+            file.set_modified(SystemTime::now()).ok();
+        }
+        bytes.drain(..header.len());
+        Some(bytes)
+    }
+
+    /// Store the bytes for the given source, then evict old entries if the directory has
+    /// grown past the limit.
+    pub fn write(&self, source: &str, bytes: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+
+        // Write to a temporary file first, so a concurrent read never sees a partial entry.
+        let path = self.entry_path(source);
+        let temporary_path = path.with_extension("tmp");
+        let mut entry = entry_header(source);
+        entry.extend_from_slice(bytes);
+        fs::write(&temporary_path, &entry)?;
+        let replaced_len = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        fs::rename(&temporary_path, &path)?;
+
+        let mut total_bytes = self.total_bytes.lock();
+        let mut new_total_bytes = match *total_bytes {
+            Some(total_bytes) => (total_bytes + entry.len() as u64).saturating_sub(replaced_len),
+            None => self.entries()?.iter().map(|(_, len, _)| len).sum(),
+        };
+        if new_total_bytes > self.max_bytes {
+            new_total_bytes = self.evict()?;
+        }
+        *total_bytes = Some(new_total_bytes);
+        Ok(())
+    }
+
+    fn entry_path(&self, source: &str) -> PathBuf {
+        let mut hasher = FxHasher::default();
+        hasher.write(source.as_bytes());
+        self.directory.join(format!("{:016x}", hasher.finish()))
+    }
+
+    /// The modification time, size and path of each entry in the directory.
+    fn entries(&self) -> io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && entry.path().extension().is_none() {
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Remove the least recently used entries until the directory is within the limit,
+    /// returning the size of the entries that are left.
+    fn evict(&self) -> io::Result<u64> {
+        let mut entries = self.entries()?;
+        let mut total_bytes = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        entries.sort_unstable_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in entries {
+            if total_bytes <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total_bytes -= len,
+                // Another process may have evicted the same entry.
+                Err(error) if error.kind() == ErrorKind::NotFound => total_bytes -= len,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(total_bytes)
+    }
+}
+
+/// What an entry starts with: the length of its source, then the source itself.
+fn entry_header(source: &str) -> Vec<u8> {
+    let mut header = (source.len() as u64).to_le_bytes().to_vec();
+    header.extend_from_slice(source.as_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_evicts_least_recently_used_entries() {
+        let directory =
+            std::env::temp_dir().join(format!("gpui-image-disk-cache-test-{}", std::process::id()));
+        fs::remove_dir_all(&directory).ok();
+        // Each entry takes 4 bytes for the image, and 9 for its one-byte source.
+        let cache = ImageDiskCache::new(&directory, 30);
+
+        cache.write("1", &[0; 4]).unwrap();
+        cache.write("2", &[0; 4]).unwrap();
+        // Make sure the modification times are distinguishable.
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.read("1"), Some(vec![0; 4]));
+
+        cache.write("3", &[0; 4]).unwrap();
+        assert_eq!(cache.read("1"), Some(vec![0; 4]));
+        assert_eq!(cache.read("2"), None);
+        assert_eq!(cache.read("3"), Some(vec![0; 4]));
+
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_entries_for_other_sources_are_not_read() {
+        let directory = std::env::temp_dir().join(format!(
+            "gpui-image-disk-cache-collision-test-{}",
+            std::process::id()
+        ));
+        fs::remove_dir_all(&directory).ok();
+        let cache = ImageDiskCache::new(&directory, u64::MAX);
+
+        // Pretend the two sources' hashes collide.
+        cache.write("a", b"image a").unwrap();
+        fs::rename(cache.entry_path("a"), cache.entry_path("b")).unwrap();
+        assert_eq!(cache.read("b"), None);
+
+        cache.write("b", b"image b").unwrap();
+        assert_eq!(cache.read("b"), Some(b"image b".to_vec()));
+
+        fs::remove_dir_all(&directory).ok();
+    }
+}
@@ -0,0 +1,1115 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use collections::FxHashMap;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use futures::{
+    channel::oneshot,
+    future::{self, BoxFuture, WeakShared},
+    AsyncRead, AsyncReadExt, Future, FutureExt,
+};
+use image::ImageFormat;
+use memmap2::Mmap;
+use parking_lot::Mutex;
+use util::{
+    http::{self, HttpClient},
+    ResultExt,
+};
+
+use crate::{
+    hash, Asset, AssetCache, AssetSource, BackgroundExecutor, EncodedFormat, ImageCacheError,
+    ImageDiskCache, ImageEvent, ImageInstrumentation, ImageLoadQueue, SharedString, SharedUri,
+    UriOrPath, WindowContext,
+};
+
+/// Encoded image bytes, along with the raster format to decode them as, or `None` for SVG.
+pub(crate) struct EncodedImage {
+    pub(crate) bytes: Arc<EncodedBytes>,
+    pub(crate) format: Option<ImageFormat>,
+    /// The `Content-Type` the image was served with, or the MIME type of a `data:` URI.
+    pub(crate) content_type: Option<String>,
+}
+
+/// The bytes of an encoded image, either held in memory or, for remote images larger than
+/// [`RetryPolicy::stream_to_disk_above`], mapped from the temporary file they were
+/// downloaded to.
+pub(crate) enum EncodedBytes {
+    Memory(Vec<u8>),
+    Mapped {
+        map: Mmap,
+        // Keep the file open for as long as it's mapped.
+        _file: fs::File,
+    },
+}
+
+impl Deref for EncodedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            EncodedBytes::Memory(bytes) => &bytes[..],
+            EncodedBytes::Mapped { map, .. } => &map[..],
+        }
+    }
+}
+
+/// Everything needed to read an image's encoded bytes, captured from the app so that
+/// it can be used on a background thread.
+#[derive(Clone)]
+pub(crate) struct EncodedImageReader {
+    client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+    policy: RetryPolicy,
+    disk_cache: Option<ImageDiskCache>,
+    progress: ImageLoadProgress,
+    asset_cache: AssetCache,
+    fetches: ImageFetches,
+    fetch_queue: ImageLoadQueue,
+    assets: Arc<dyn AssetSource>,
+    instrumentation: ImageInstrumentation,
+}
+
+/// Remote images currently being fetched, keyed by the hash of their source, so that
+/// concurrent loads of the same image, such as of its size and of its contents, share a
+/// single request.
+#[derive(Clone, Default)]
+pub(crate) struct ImageFetches(Arc<Mutex<FxHashMap<u64, WeakShared<Fetch>>>>);
+
+type Fetch = BoxFuture<'static, Result<(Arc<EncodedBytes>, Option<String>), ImageCacheError>>;
+
+/// How much of each remote image currently being downloaded has been received, keyed by
+/// the hash of its source.
+#[derive(Clone, Default)]
+pub(crate) struct ImageLoadProgress(Arc<Mutex<ImageLoadProgressState>>);
+
+#[derive(Default)]
+struct ImageLoadProgressState {
+    progress: FxHashMap<u64, LoadProgress>,
+    /// Those waiting for the progress of each image to change.
+    listeners: FxHashMap<u64, Vec<oneshot::Sender<()>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct LoadProgress {
+    pub(crate) loaded: usize,
+    pub(crate) total: Option<usize>,
+}
+
+/// Where a remote image whose request was redirected was fetched from in the end. It's
+/// cached by the fetch that followed the redirect rather than loaded, so that it's removed
+/// from the [`AssetCache`] along with the image itself.
+pub(crate) enum ImageRedirect {}
+
+impl Asset for ImageRedirect {
+    type Source = UriOrPath;
+    type Output = Option<SharedUri>;
+
+    fn load(
+        _source: Self::Source,
+        _cx: &mut WindowContext,
+    ) -> impl Future<Output = Self::Output> + Send + 'static {
+        future::ready(None)
+    }
+
+    fn size_in_bytes(output: &Self::Output) -> usize {
+        output.as_ref().map_or(0, |uri| uri.len())
+    }
+
+    fn scope(source: &Self::Source) -> Option<SharedString> {
+        source.scope()
+    }
+
+    fn uri(source: &Self::Source) -> Option<SharedUri> {
+        source.uri()
+    }
+}
+
+impl ImageLoadProgress {
+    pub(crate) fn get(&self, key: u64) -> Option<LoadProgress> {
+        self.0.lock().progress.get(&key).copied()
+    }
+
+    /// Wait for the progress of an image's download to change, or for the download to
+    /// finish. Each receiver is only woken once, so however quickly a download progresses,
+    /// an element that waits again each time it's drawn is redrawn at most once a frame.
+    pub(crate) fn changed(&self, key: u64) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.0.lock();
+        let listeners = state.listeners.entry(key).or_default();
+        listeners.retain(|listener| !listener.is_canceled());
+        listeners.push(tx);
+        rx
+    }
+
+    fn set(&self, key: u64, progress: LoadProgress) {
+        let mut state = self.0.lock();
+        state.progress.insert(key, progress);
+        state.notify(key);
+    }
+
+    fn remove(&self, key: u64) {
+        let mut state = self.0.lock();
+        state.progress.remove(&key);
+        state.notify(key);
+    }
+}
+
+impl ImageLoadProgressState {
+    fn notify(&mut self, key: u64) {
+        for listener in self.listeners.remove(&key).into_iter().flatten() {
+            listener.send(()).ok();
+        }
+    }
+}
+
+impl EncodedImageReader {
+    pub(crate) fn new(cx: &WindowContext) -> Self {
+        Self {
+            client: cx.http_client(),
+            executor: cx.background_executor().clone(),
+            policy: cx.image_fetch_policy(),
+            disk_cache: cx.image_disk_cache(),
+            progress: cx.image_load_progress.clone(),
+            asset_cache: cx.asset_cache.clone(),
+            fetches: cx.image_fetches.clone(),
+            fetch_queue: cx.image_fetch_queue(),
+            assets: cx.asset_source().clone(),
+            instrumentation: cx.image_instrumentation.clone(),
+        }
+    }
+
+    pub(crate) async fn read(self, source: UriOrPath) -> Result<EncodedImage, ImageCacheError> {
+        let mut mime_type = None;
+        let mut content_type = None;
+        // Scoped sources are read like the source they wrap. Their scope only keeps them
+        // from sharing fetches and cached images with other scopes.
+        let bytes = match source.unscoped() {
+            UriOrPath::Path(uri) => Arc::new(EncodedBytes::Memory(fs::read(uri.as_ref())?)),
+            UriOrPath::Asset(path) => {
+                let bytes = self
+                    .assets
+                    .load(path)
+                    .map_err(|error| ImageCacheError::Asset(error.to_string().into()))?;
+                Arc::new(EncodedBytes::Memory(bytes.into_owned()))
+            }
+            UriOrPath::Uri { uri, .. } if uri.starts_with("data:") => {
+                let (bytes, declared_mime_type) = parse_data_uri(uri)?;
+                mime_type = Some(declared_mime_type);
+                Arc::new(EncodedBytes::Memory(bytes))
+            }
+            UriOrPath::Uri { uri, options } => {
+                let headers = options.headers.as_deref().unwrap_or(&[]);
+                let (bytes, served_as) = self.fetch(&source, uri, headers).await?;
+                content_type = served_as;
+                bytes
+            }
+            UriOrPath::Bytes(bytes) => Arc::new(EncodedBytes::Memory(bytes.to_vec())),
+            UriOrPath::Reader(reader) => {
+                let mut bytes = Vec::new();
+                (reader.open)().read_to_end(&mut bytes).await?;
+                Arc::new(EncodedBytes::Memory(bytes))
+            }
+            UriOrPath::Scoped { .. } => unreachable!("scopes aren't nested"),
+        };
+
+        let declared_format = match source.unscoped() {
+            UriOrPath::Uri { options, .. } => options.format,
+            _ => None,
+        };
+        let format = match (declared_format, mime_type.as_deref()) {
+            // A declared format takes precedence over both the MIME type and the contents.
+            (Some(EncodedFormat::Raster(format)), _) => Some(format),
+            (Some(EncodedFormat::Svg), _) => None,
+            (_, Some("image/svg+xml")) => None,
+            (_, Some(mime_type)) => {
+                image_format_from_mime_type(mime_type).or_else(|| guess_format(&bytes))
+            }
+            (_, None) => guess_format(&bytes),
+        };
+
+        let declared_svg = mime_type.as_deref() == Some("image/svg+xml")
+            || declared_format == Some(EncodedFormat::Svg);
+        let content_type = content_type.or(mime_type);
+        // Anything that isn't a raster image is parsed as an SVG, so make sure it could be
+        // one, rather than failing with a confusing error from the SVG parser.
+        if format.is_none() && !declared_svg && !could_be_svg(&bytes) {
+            return Err(ImageCacheError::UnknownFormat {
+                sniffed: None,
+                content_type,
+            });
+        }
+
+        Ok(EncodedImage {
+            bytes,
+            format,
+            content_type,
+        })
+    }
+
+    /// Fetch a remote image, joining a fetch of the same source that's already in flight.
+    async fn fetch(
+        &self,
+        source: &UriOrPath,
+        uri: &str,
+        headers: &[(SharedString, SharedString)],
+    ) -> Result<(Arc<EncodedBytes>, Option<String>), ImageCacheError> {
+        let key = hash(source);
+        // Images that expire need to be fetched again, rather than read from the disk, and
+        // the disk cache is shared by every scope.
+        let use_disk_cache = match source {
+            UriOrPath::Uri { options, .. } => options.ttl.is_none(),
+            UriOrPath::Scoped { .. } => false,
+            _ => true,
+        };
+        let fetch = {
+            let mut fetches = self.fetches.0.lock();
+            match fetches.get(&key).and_then(WeakShared::upgrade) {
+                Some(fetch) => fetch,
+                None => {
+                    let reader = self.clone();
+                    let source = source.clone();
+                    let uri = uri.to_string();
+                    let headers = headers.to_vec();
+                    let fetch = async move {
+                        reader
+                            .download(&source, &uri, &headers, use_disk_cache)
+                            .await
+                            .map(|(bytes, content_type)| (Arc::new(bytes), content_type))
+                    }
+                    .boxed()
+                    .shared();
+                    // Only hold on to the fetch weakly, so it's still cancelled once every
+                    // load waiting for it has been.
+                    fetches.extend(fetch.downgrade().map(|fetch| (key, fetch)));
+                    fetch
+                }
+            }
+        };
+        let result = fetch.clone().await;
+
+        let mut fetches = self.fetches.0.lock();
+        if fetches
+            .get(&key)
+            .and_then(WeakShared::upgrade)
+            .map_or(true, |current| current.ptr_eq(&fetch))
+        {
+            fetches.remove(&key);
+        }
+        result
+    }
+
+    /// Download a remote image, going through the disk cache if one is configured and
+    /// `use_disk_cache` is set. Returns the image's body, along with its `Content-Type`,
+    /// which isn't known for images read from the disk cache.
+    async fn download(
+        &self,
+        source: &UriOrPath,
+        uri: &str,
+        headers: &[(SharedString, SharedString)],
+        use_disk_cache: bool,
+    ) -> Result<(EncodedBytes, Option<String>), ImageCacheError> {
+        let key = hash(source);
+        let started = Instant::now();
+        self.instrumentation.report(|| ImageEvent::FetchStarted {
+            uri: uri.to_string().into(),
+        });
+        let fetched = |bytes: &[u8], cache_hit: bool| ImageEvent::FetchFinished {
+            uri: uri.to_string().into(),
+            bytes: bytes.len(),
+            cache_hit,
+            duration: started.elapsed(),
+        };
+
+        // Headers, such as for authorization, can change the response, so they're part of
+        // what the image is cached on disk by.
+        let disk_cache = self
+            .disk_cache
+            .as_ref()
+            .filter(|_| use_disk_cache)
+            .map(|cache| {
+                let mut entry_source = uri.to_string();
+                for (name, value) in headers {
+                    entry_source.push_str(&format!("\n{name}: {value}"));
+                }
+                (cache, entry_source)
+            });
+        if let Some(bytes) = disk_cache
+            .as_ref()
+            .and_then(|(cache, entry_source)| cache.read(entry_source))
+        {
+            self.instrumentation.report(|| fetched(&bytes[..], true));
+            return Ok((EncodedBytes::Memory(bytes), None));
+        }
+
+        let _permit = self.fetch_queue.acquire(key).await;
+        let on_progress = |loaded: usize, total: Option<usize>| {
+            self.progress.set(key, LoadProgress { loaded, total })
+        };
+        let result = fetch_with_policy(
+            self.client.as_ref(),
+            &self.executor,
+            self.policy,
+            uri,
+            headers,
+            &on_progress,
+        )
+        .await;
+        self.progress.remove(key);
+        let (bytes, redirected_to, content_type) = result?;
+        self.instrumentation.report(|| fetched(&bytes[..], false));
+        if let Some(uri) = redirected_to {
+            self.asset_cache
+                .insert::<ImageRedirect>(source.clone(), Some(uri.into()));
+        }
+        if let Some((disk_cache, entry_source)) = disk_cache {
+            disk_cache.write(&entry_source, &bytes).log_err();
+        }
+        Ok((bytes, content_type))
+    }
+}
+
+/// Controls how remote images are fetched. The default policy never times out
+/// and doesn't retry failed fetches.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryPolicy {
+    /// The number of times a fetch that failed with a connection error, a timeout,
+    /// or a server error (5xx) is retried before giving up.
+    pub max_retries: usize,
+    /// How long to wait before the first retry. The delay doubles with every retry, up to
+    /// a minute, or to this delay if it's longer.
+    pub backoff: Duration,
+    /// How long a single fetch attempt may take before it's abandoned.
+    pub timeout: Option<Duration>,
+    /// When set, response bodies longer than this many bytes are written to a temporary
+    /// file and decoded from a memory map of it, rather than being buffered in memory.
+    pub stream_to_disk_above: Option<usize>,
+}
+
+/// Decode the payload of a `data:` URI, returning it along with its declared MIME type.
+fn parse_data_uri(uri: &str) -> Result<(Vec<u8>, String), ImageCacheError> {
+    let invalid = |reason: &'static str| ImageCacheError::InvalidDataUri(reason.into());
+
+    let rest = uri
+        .strip_prefix("data:")
+        .ok_or_else(|| invalid("missing `data:` scheme"))?;
+    let (metadata, payload) = rest
+        .split_once(',')
+        .ok_or_else(|| invalid("missing `,` before the payload"))?;
+    let mut parameters = metadata.split(';');
+    let mime_type = parameters
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let is_base64 = parameters.any(|parameter| parameter.eq_ignore_ascii_case("base64"));
+
+    let bytes = if is_base64 {
+        let payload =
+            percent_decode(payload).ok_or_else(|| invalid("malformed percent-encoding"))?;
+        let payload = payload
+            .into_iter()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+        base64::decode(payload)
+            .map_err(|error| ImageCacheError::InvalidDataUri(error.to_string().into()))?
+    } else {
+        percent_decode(payload).ok_or_else(|| invalid("malformed percent-encoding"))?
+    };
+
+    Ok((bytes, mime_type))
+}
+
+fn percent_decode(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = input.bytes();
+    let mut output = Vec::with_capacity(input.len());
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = (bytes.next()? as char).to_digit(16)?;
+            let low = (bytes.next()? as char).to_digit(16)?;
+            output.push((high * 16 + low) as u8);
+        } else {
+            output.push(byte);
+        }
+    }
+    Some(output)
+}
+
+/// Detect a raster image's format from its leading bytes, or return `None` if it isn't
+/// one (in which case it's treated as an SVG).
+fn guess_format(bytes: &[u8]) -> Option<ImageFormat> {
+    // `image::guess_format` only recognizes AVIF files whose `ftyp` box happens to be 32
+    // bytes long, and takes any RIFF container (such as a WAV file) to be WebP.
+    if let Some(b"ftypavif" | b"ftypavis") = bytes.get(4..12) {
+        return Some(ImageFormat::Avif);
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) != Some(&b"WEBP"[..]) {
+        return None;
+    }
+    image::guess_format(bytes).ok()
+}
+
+fn image_format_from_mime_type(mime_type: &str) -> Option<ImageFormat> {
+    match mime_type {
+        "image/x-icon" | "image/vnd.microsoft.icon" => Some(ImageFormat::Ico),
+        _ => ImageFormat::from_extension(mime_type.strip_prefix("image/")?),
+    }
+}
+
+/// The longest a [`RetryPolicy`] waits between retries, unless its initial backoff is
+/// longer.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+async fn fetch_with_policy(
+    client: &dyn HttpClient,
+    executor: &BackgroundExecutor,
+    policy: RetryPolicy,
+    uri: &str,
+    headers: &[(SharedString, SharedString)],
+    on_progress: &(dyn Fn(usize, Option<usize>) + Send + Sync),
+) -> Result<(EncodedBytes, Option<String>, Option<String>), ImageCacheError> {
+    let mut backoff = policy.backoff;
+    let mut retries = 0;
+    loop {
+        let result = if let Some(timeout) = policy.timeout {
+            let timer = executor.timer(timeout);
+            smol::future::or(
+                fetch(
+                    client,
+                    uri,
+                    headers,
+                    policy.stream_to_disk_above,
+                    on_progress,
+                ),
+                async move {
+                    timer.await;
+                    Err(ImageCacheError::Timeout)
+                },
+            )
+            .await
+        } else {
+            fetch(
+                client,
+                uri,
+                headers,
+                policy.stream_to_disk_above,
+                on_progress,
+            )
+            .await
+        };
+
+        match result {
+            Err(error) if error.is_transient() && retries < policy.max_retries => {
+                retries += 1;
+                executor.timer(backoff).await;
+                backoff = backoff
+                    .saturating_mul(2)
+                    .min(MAX_RETRY_BACKOFF.max(policy.backoff));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The most redirects followed when fetching an image, so that a redirect loop fails
+/// rather than fetching forever.
+const MAX_REDIRECTS: usize = 10;
+
+/// Fetch a remote image, following any redirects. Returns the image's body, along with
+/// the URI it was redirected to if it was and the response's `Content-Type`.
+async fn fetch(
+    client: &dyn HttpClient,
+    uri: &str,
+    headers: &[(SharedString, SharedString)],
+    stream_to_disk_above: Option<usize>,
+    on_progress: &(dyn Fn(usize, Option<usize>) + Send + Sync),
+) -> Result<(EncodedBytes, Option<String>, Option<String>), ImageCacheError> {
+    let headers = headers
+        .iter()
+        .map(|(name, value)| (name.as_ref(), value.as_ref()))
+        .collect::<Vec<_>>();
+
+    // Redirects are followed here rather than by the client, so that they're bounded and
+    // followed the same way by every client.
+    let mut current_uri = uri.to_string();
+    let mut redirects = 0;
+    let mut response = loop {
+        // The headers may include credentials, so only send them to the original origin.
+        let headers = if is_same_origin(uri, &current_uri) {
+            &headers[..]
+        } else {
+            &[]
+        };
+        let response = client
+            .get_with_headers(&current_uri, headers, ().into(), false)
+            .await?;
+        let location = response
+            .status()
+            .is_redirection()
+            .then(|| response.headers().get("location")?.to_str().ok())
+            .flatten();
+        let Some(location) = location else {
+            break response;
+        };
+        if redirects == MAX_REDIRECTS {
+            return Err(ImageCacheError::TooManyRedirects);
+        }
+        redirects += 1;
+        current_uri = http::Url::parse(&current_uri)
+            .and_then(|base| base.join(location))
+            .map_err(|_| ImageCacheError::InvalidRedirect(location.to_string().into()))?
+            .into();
+    };
+
+    let total = response
+        .headers()
+        .get("content-length")
+        .and_then(|length| length.to_str().ok()?.parse().ok());
+    let body = read_body(response.body_mut(), stream_to_disk_above, |loaded| {
+        on_progress(loaded, total)
+    })
+    .await?;
+    if !response.status().is_success() {
+        return Err(ImageCacheError::BadStatus {
+            status: response.status(),
+            body: String::from_utf8_lossy(&body).into_owned(),
+        });
+    }
+    let content_encoding = response
+        .headers()
+        .get("content-encoding")
+        .and_then(|encoding| encoding.to_str().ok());
+    let body = decode_content_encoding(content_encoding, body)?;
+    let content_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|content_type| Some(content_type.to_str().ok()?.to_string()));
+    Ok((
+        body,
+        (current_uri != uri).then_some(current_uri),
+        content_type,
+    ))
+}
+
+/// The most bytes a compressed image body is decompressed to, so that a small body can't
+/// exhaust memory by decompressing to a huge one.
+const MAX_DECOMPRESSED_BODY_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Undo the `Content-Encoding` a server compressed an image's body with. The default
+/// HTTP client decompresses bodies itself, but may leave the header in place, so bodies
+/// that aren't compressed are passed through. `gzip` and `deflate` are decompressed here
+/// for clients that don't. Other encodings, including `br`, are only ever decompressed
+/// by the client, so bodies it left compressed fail with
+/// [`ImageCacheError::UnsupportedContentEncoding`].
+fn decode_content_encoding(
+    content_encoding: Option<&str>,
+    body: EncodedBytes,
+) -> Result<EncodedBytes, ImageCacheError> {
+    let Some(content_encoding) = content_encoding else {
+        return Ok(body);
+    };
+
+    // Encodings are listed in the order they were applied, so they're undone in reverse.
+    let mut body = body;
+    for encoding in content_encoding.rsplit(',') {
+        let encoding = encoding.trim().to_ascii_lowercase();
+        body = match encoding.as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" if body.starts_with(&[0x1f, 0x8b]) => {
+                decompress(GzDecoder::new(&body[..]), MAX_DECOMPRESSED_BODY_SIZE)?
+            }
+            "deflate" if is_zlib_header(&body) => {
+                decompress(ZlibDecoder::new(&body[..]), MAX_DECOMPRESSED_BODY_SIZE)?
+            }
+            "gzip" | "x-gzip" | "deflate" => body,
+            _ if guess_format(&body).is_some() || looks_like_svg(&body) => body,
+            _ => return Err(ImageCacheError::UnsupportedContentEncoding(encoding.into())),
+        };
+    }
+    Ok(body)
+}
+
+/// Read a decompressed body, failing with [`ImageCacheError::DecompressedTooLarge`] rather
+/// than reading more than `max_size` bytes of it.
+fn decompress(decoder: impl Read, max_size: u64) -> Result<EncodedBytes, ImageCacheError> {
+    let mut decoded = Vec::new();
+    decoder
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut decoded)?;
+    if decoded.len() as u64 > max_size {
+        return Err(ImageCacheError::DecompressedTooLarge);
+    }
+    Ok(EncodedBytes::Memory(decoded))
+}
+
+fn is_zlib_header(bytes: &[u8]) -> bool {
+    match bytes {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && ((u16::from(*cmf) << 8) | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Whether the bytes start like an SVG document, or any other XML, after an optional
+/// byte order mark and whitespace.
+fn looks_like_svg(bytes: &[u8]) -> bool {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'<')
+}
+
+/// Whether the bytes could be an SVG: XML with an `<svg>` element that isn't an HTML
+/// page, or anything compressed with gzip, which the SVG parser decompresses itself.
+fn could_be_svg(bytes: &[u8]) -> bool {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        return true;
+    }
+    if !looks_like_svg(bytes) {
+        return false;
+    }
+    let start = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let start = &start[start
+        .iter()
+        .take_while(|byte| byte.is_ascii_whitespace())
+        .count()..];
+    let start = start[..start.len().min(14)].to_ascii_lowercase();
+    let is_html = start.starts_with(b"<!doctype html") || start.starts_with(b"<html");
+    !is_html && bytes.windows(4).any(|window| window == b"<svg")
+}
+
+fn is_same_origin(a: &str, b: &str) -> bool {
+    match (http::Url::parse(a), http::Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.origin() == b.origin(),
+        _ => a == b,
+    }
+}
+
+/// Read a response body in chunks, reporting the number of bytes read so far after each
+/// one. The body is kept in memory, or moved into a temporary file once it turns out to be
+/// longer than `stream_to_disk_above` bytes.
+async fn read_body(
+    body: &mut (impl AsyncRead + Unpin),
+    stream_to_disk_above: Option<usize>,
+    on_progress: impl Fn(usize),
+) -> Result<EncodedBytes, ImageCacheError> {
+    let mut bytes = Vec::new();
+    let mut file: Option<fs::File> = None;
+    let mut loaded = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = body.read(&mut buffer).await?;
+        if len == 0 {
+            break;
+        }
+        loaded += len;
+        on_progress(loaded);
+
+        if let Some(file) = file.as_mut() {
+            file.write_all(&buffer[..len])?;
+        } else {
+            bytes.extend_from_slice(&buffer[..len]);
+            if stream_to_disk_above.map_or(false, |threshold| bytes.len() > threshold) {
+                let mut temporary_file = tempfile::tempfile()?;
+                temporary_file.write_all(&bytes)?;
+                bytes = Vec::new();
+                file = Some(temporary_file);
+            }
+        }
+    }
+
+    let Some(file) = file else {
+        return Ok(EncodedBytes::Memory(bytes));
+    };
+    // SAFETY: The file is an anonymous temporary file that nothing else can modify.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(EncodedBytes::Mapped { map, _file: file })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_raster, unsupported_format, DecodeOptions};
+    use image::{error::ImageFormatHint, GenericImageView, ImageError};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_parse_data_uri() {
+        let (bytes, mime_type) = parse_data_uri("data:image/png;base64,iVBORw0K").unwrap();
+        assert_eq!(bytes, b"\x89PNG\r\n");
+        assert_eq!(mime_type, "image/png");
+        assert_eq!(
+            image_format_from_mime_type(&mime_type),
+            Some(ImageFormat::Png)
+        );
+
+        let (bytes, mime_type) = parse_data_uri("data:image/svg+xml,%3Csvg%2F%3E").unwrap();
+        assert_eq!(bytes, b"<svg/>");
+        assert_eq!(mime_type, "image/svg+xml");
+
+        assert!(matches!(
+            parse_data_uri("data:image/png;base64"),
+            Err(ImageCacheError::InvalidDataUri(_))
+        ));
+        assert!(matches!(
+            parse_data_uri("data:image/png;base64,!!!"),
+            Err(ImageCacheError::InvalidDataUri(_))
+        ));
+    }
+
+    #[test]
+    fn test_guess_format() {
+        // A 1x1 lossy WebP image.
+        let webp =
+            base64::decode("UklGRiIAAABXRUJQVlA4IBYAAAAwAQCdASoBAAEADsD+JaQAA3AAAAAA").unwrap();
+        assert_eq!(guess_format(&webp), Some(ImageFormat::WebP));
+        let image = decode_raster(&webp, ImageFormat::WebP, &DecodeOptions::default())
+            .unwrap()
+            .image;
+        assert_eq!((image.width(), image.height()), (1, 1));
+
+        assert_eq!(
+            guess_format(b"\0\0\0\x1cftypavif\0\0\0\0"),
+            Some(ImageFormat::Avif)
+        );
+        assert_eq!(guess_format(b"RIFF\0\0\0\0WAVEfmt "), None);
+        assert_eq!(guess_format(b"<svg/>"), None);
+    }
+
+    #[test]
+    fn test_read_body_streams_large_bodies_to_disk() {
+        let body = (0..100u8).collect::<Vec<_>>();
+
+        let read = |stream_to_disk_above: Option<usize>| {
+            let loaded = std::cell::Cell::new(0);
+            let bytes = smol::block_on(read_body(
+                &mut futures::io::Cursor::new(&body),
+                stream_to_disk_above,
+                |bytes_read| loaded.set(bytes_read),
+            ))
+            .unwrap();
+            assert_eq!(loaded.get(), body.len());
+            bytes
+        };
+
+        let bytes = read(None);
+        assert!(matches!(bytes, EncodedBytes::Memory(_)));
+        assert_eq!(&*bytes, &body[..]);
+
+        let bytes = read(Some(100));
+        assert!(matches!(bytes, EncodedBytes::Memory(_)));
+
+        let bytes = read(Some(10));
+        assert!(matches!(bytes, EncodedBytes::Mapped { .. }));
+        assert_eq!(&*bytes, &body[..]);
+    }
+
+    #[test]
+    fn test_fetch_follows_bounded_redirects() {
+        let client = http::FakeHttpClient::create(|request| async move {
+            let authorized = request.headers().contains_key("authorization");
+            let response = match request.uri().to_string().as_str() {
+                "https://a.example/image" => http::Response::builder()
+                    .status(302)
+                    .header("location", "/moved"),
+                "https://a.example/moved" => http::Response::builder()
+                    .status(301)
+                    .header("location", "https://b.example/image"),
+                // Credentials for one origin aren't sent to another.
+                "https://b.example/image" if !authorized => http::Response::builder()
+                    .status(200)
+                    .header("content-type", "image/png"),
+                "https://a.example/loop" => http::Response::builder()
+                    .status(302)
+                    .header("location", "/loop"),
+                _ => http::Response::builder().status(404),
+            };
+            Ok(response.body("image".into()).unwrap())
+        });
+        let fetch = |uri: &'static str| {
+            smol::block_on(fetch(
+                client.as_ref(),
+                uri,
+                &[("Authorization".into(), "secret".into())],
+                None,
+                &|_, _| {},
+            ))
+        };
+
+        let (body, redirected_to, content_type) = fetch("https://a.example/image").unwrap();
+        assert_eq!(&*body, b"image");
+        assert_eq!(redirected_to.as_deref(), Some("https://b.example/image"));
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+
+        assert!(matches!(
+            fetch("https://a.example/loop"),
+            Err(ImageCacheError::TooManyRedirects)
+        ));
+    }
+
+    #[test]
+    fn test_fetch_with_policy_retries_server_errors() {
+        use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+        let executor = BackgroundExecutor::new(Arc::new(crate::TestDispatcher::new(
+            StdRng::seed_from_u64(0),
+        )));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = http::FakeHttpClient::create({
+            let requests = requests.clone();
+            move |_| {
+                let status = if requests.fetch_add(1, SeqCst) < 2 {
+                    503
+                } else {
+                    200
+                };
+                async move {
+                    Ok(http::Response::builder()
+                        .status(status)
+                        .body("image".into())
+                        .unwrap())
+                }
+            }
+        });
+        let fetch = |max_retries| {
+            requests.store(0, SeqCst);
+            let result = Arc::new(Mutex::new(None));
+            executor
+                .spawn({
+                    let client = client.clone();
+                    let executor = executor.clone();
+                    let result = result.clone();
+                    async move {
+                        let policy = RetryPolicy {
+                            max_retries,
+                            backoff: Duration::from_secs(1),
+                            ..RetryPolicy::default()
+                        };
+                        *result.lock() = Some(
+                            fetch_with_policy(
+                                client.as_ref(),
+                                &executor,
+                                policy,
+                                "https://example.com/image",
+                                &[],
+                                &|_, _| {},
+                            )
+                            .await,
+                        );
+                    }
+                })
+                .detach();
+            result
+        };
+
+        let result = fetch(2);
+        executor.run_until_parked();
+        assert_eq!(requests.load(SeqCst), 1);
+        assert!(result.lock().is_none());
+        // Retries back off, waiting twice as long each time.
+        executor.advance_clock(Duration::from_secs(1));
+        assert_eq!(requests.load(SeqCst), 2);
+        assert!(result.lock().is_none());
+        executor.advance_clock(Duration::from_secs(2));
+        assert_eq!(requests.load(SeqCst), 3);
+        let (body, _, _) = result.lock().take().unwrap().unwrap();
+        assert_eq!(&*body, b"image");
+
+        let result = fetch(1);
+        executor.advance_clock(Duration::from_secs(1));
+        assert_eq!(requests.load(SeqCst), 2);
+        assert!(matches!(
+            result.lock().take(),
+            Some(Err(ImageCacheError::BadStatus { status, .. })) if status.as_u16() == 503
+        ));
+    }
+
+    #[test]
+    fn test_fetch_with_policy_times_out_stalled_responses() {
+        let executor = BackgroundExecutor::new(Arc::new(crate::TestDispatcher::new(
+            StdRng::seed_from_u64(0),
+        )));
+        let client = http::FakeHttpClient::create(|_| future::pending());
+        let result = Arc::new(Mutex::new(None));
+        executor
+            .spawn({
+                let executor = executor.clone();
+                let result = result.clone();
+                async move {
+                    let policy = RetryPolicy {
+                        timeout: Some(Duration::from_secs(5)),
+                        ..RetryPolicy::default()
+                    };
+                    *result.lock() = Some(
+                        fetch_with_policy(
+                            client.as_ref(),
+                            &executor,
+                            policy,
+                            "https://example.com/image",
+                            &[],
+                            &|_, _| {},
+                        )
+                        .await,
+                    );
+                }
+            })
+            .detach();
+
+        executor.advance_clock(Duration::from_secs(4));
+        assert!(result.lock().is_none());
+        executor.advance_clock(Duration::from_secs(1));
+        assert!(matches!(
+            result.lock().take(),
+            Some(Err(ImageCacheError::Timeout))
+        ));
+    }
+
+    #[test]
+    fn test_image_events_report_fetches_and_disk_cache_hits() {
+        let directory =
+            std::env::temp_dir().join(format!("gpui-image-events-test-{}", std::process::id()));
+        fs::remove_dir_all(&directory).ok();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let reader = EncodedImageReader {
+            client: http::FakeHttpClient::create(|_| async move {
+                Ok(http::Response::builder()
+                    .status(200)
+                    .body("image".into())
+                    .unwrap())
+            }),
+            executor: BackgroundExecutor::new(Arc::new(crate::TestDispatcher::new(
+                StdRng::seed_from_u64(0),
+            ))),
+            policy: RetryPolicy::default(),
+            disk_cache: Some(ImageDiskCache::new(&directory, u64::MAX)),
+            progress: ImageLoadProgress::default(),
+            asset_cache: AssetCache::new(),
+            fetches: ImageFetches::default(),
+            fetch_queue: ImageLoadQueue::new(1),
+            assets: Arc::new(()),
+            instrumentation: ImageInstrumentation(Some(Arc::new({
+                let events = events.clone();
+                move |event: &ImageEvent| events.lock().push(event.clone())
+            }))),
+        };
+
+        let source = UriOrPath::from(SharedUri::from("https://example.com/image"));
+        for _ in 0..2 {
+            let image = smol::block_on(reader.clone().read(source.clone())).unwrap();
+            assert_eq!(&**image.bytes, b"image");
+        }
+
+        let events = events
+            .lock()
+            .iter()
+            .map(|event| match event {
+                ImageEvent::FetchStarted { uri } => format!("start {uri}"),
+                ImageEvent::FetchFinished {
+                    uri,
+                    bytes,
+                    cache_hit,
+                    ..
+                } => format!("finish {uri} {bytes} {cache_hit}"),
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                "start https://example.com/image",
+                "finish https://example.com/image 5 false",
+                "start https://example.com/image",
+                "finish https://example.com/image 5 true",
+            ]
+        );
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_unknown_formats() {
+        assert!(could_be_svg(
+            b"\xef\xbb\xbf\n<?xml version=\"1.0\"?>\n<svg/>"
+        ));
+        assert!(could_be_svg(&[0x1f, 0x8b, 0x08]));
+        assert!(!could_be_svg(
+            b"  <!DOCTYPE html><html><body><svg/></body></html>"
+        ));
+        assert!(!could_be_svg(
+            b"<?xml version=\"1.0\"?><Error>AccessDenied</Error>"
+        ));
+        assert!(!could_be_svg(b"{\"error\": \"not found\"}"));
+
+        let error = ImageError::Unsupported(ImageFormatHint::Exact(ImageFormat::Avif).into());
+        assert!(matches!(
+            unsupported_format(error.into(), ImageFormat::Avif, Some("image/avif")),
+            ImageCacheError::UnknownFormat {
+                sniffed: Some(ImageFormat::Avif),
+                content_type: Some(content_type),
+            } if content_type == "image/avif"
+        ));
+        assert!(matches!(
+            unsupported_format(ImageCacheError::Timeout, ImageFormat::Avif, None),
+            ImageCacheError::Timeout
+        ));
+    }
+
+    #[test]
+    fn test_load_progress_wakes_listeners_once() {
+        let progress = ImageLoadProgress::default();
+        let mut changed = progress.changed(1);
+        let mut other = progress.changed(2);
+        let loaded = LoadProgress {
+            loaded: 10,
+            total: Some(20),
+        };
+        progress.set(1, loaded);
+        assert_eq!(progress.get(1), Some(loaded));
+        assert_eq!(changed.try_recv(), Ok(Some(())));
+        assert_eq!(other.try_recv(), Ok(None));
+        progress.remove(2);
+        assert_eq!(other.try_recv(), Ok(Some(())));
+
+        // Listeners that stopped waiting are dropped rather than piling up.
+        for _ in 0..3 {
+            drop(progress.changed(3));
+        }
+        let _changed = progress.changed(3);
+        assert_eq!(progress.0.lock().listeners[&3].len(), 1);
+    }
+
+    #[test]
+    fn test_decode_content_encoding() {
+        use flate2::{write::GzEncoder, Compression};
+
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1"/>"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(svg).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let decode = |encoding, body: &[u8]| {
+            decode_content_encoding(encoding, EncodedBytes::Memory(body.to_vec()))
+                .map(|body| body.to_vec())
+        };
+
+        assert_eq!(decode(Some("gzip"), &gzipped).unwrap(), svg);
+        assert_eq!(decode(Some("identity, GZIP"), &gzipped).unwrap(), svg);
+        // Bodies the client already decompressed are left alone.
+        assert_eq!(decode(Some("gzip"), svg).unwrap(), svg);
+        assert_eq!(decode(Some("br"), svg).unwrap(), svg);
+        assert_eq!(decode(None, &gzipped).unwrap(), gzipped);
+        assert!(matches!(
+            decode(Some("br"), &[0x1b, 0x2f, 0x00, 0x00]),
+            Err(ImageCacheError::UnsupportedContentEncoding(encoding)) if encoding == "br"
+        ));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 4096]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert_eq!(
+            decompress(GzDecoder::new(&bomb[..]), 4096).unwrap().len(),
+            4096
+        );
+        assert!(matches!(
+            decompress(GzDecoder::new(&bomb[..]), 4095),
+            Err(ImageCacheError::DecompressedTooLarge)
+        ));
+    }
+}
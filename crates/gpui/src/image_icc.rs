@@ -0,0 +1,167 @@
+use std::io::Read;
+use std::sync::Arc;
+
+use flate2::read::ZlibDecoder;
+use image::ImageFormat;
+
+use crate::ColorSpace;
+
+/// Find the color space a PNG or JPEG image declares, along with its embedded ICC profile.
+pub(crate) fn read_color_space(
+    bytes: &[u8],
+    format: ImageFormat,
+) -> (ColorSpace, Option<Arc<[u8]>>) {
+    let icc_profile = match format {
+        ImageFormat::Png => {
+            // Walk the PNG chunks, which must declare the color space before the image data.
+            let mut offset = 8;
+            loop {
+                let Some(length) = bytes.get(offset..offset + 4) else {
+                    break None;
+                };
+                let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+                let Some(data) = bytes.get(offset + 8..offset + 8 + length) else {
+                    break None;
+                };
+                match &bytes[offset + 4..offset + 8] {
+                    b"sRGB" => return (ColorSpace::Srgb, None),
+                    b"iCCP" => {
+                        // The profile's name and compression method precede the profile.
+                        let profile = data
+                            .iter()
+                            .position(|byte| *byte == 0)
+                            .and_then(|name_end| data.get(name_end + 2..));
+                        let mut icc_profile = Vec::new();
+                        break profile.and_then(|profile| {
+                            ZlibDecoder::new(profile)
+                                .read_to_end(&mut icc_profile)
+                                .ok()
+                                .map(|_| icc_profile)
+                        });
+                    }
+                    b"IDAT" => break None,
+                    _ => offset += 12 + length,
+                }
+            }
+        }
+        ImageFormat::Jpeg => {
+            // Large profiles are split across several APP2 segments, which are numbered.
+            let mut chunks = Vec::new();
+            let mut offset = 2;
+            while let Some(marker) = bytes.get(offset..offset + 2) {
+                let Some(length) = bytes.get(offset + 2..offset + 4) else {
+                    break;
+                };
+                let length = u16::from_be_bytes([length[0], length[1]]) as usize;
+                let Some(segment) = bytes.get(offset + 4..offset + 2 + length) else {
+                    break;
+                };
+                match marker {
+                    [0xFF, 0xE2] if segment.starts_with(b"ICC_PROFILE\0") && segment.len() > 14 => {
+                        chunks.push((segment[12], &segment[14..]))
+                    }
+                    [0xFF, 0xDA] => break,
+                    [0xFF, _] => {}
+                    _ => break,
+                }
+                offset += 2 + length;
+            }
+            chunks.sort_by_key(|(sequence_number, _)| *sequence_number);
+            (!chunks.is_empty()).then(|| {
+                chunks
+                    .into_iter()
+                    .flat_map(|(_, chunk)| chunk)
+                    .copied()
+                    .collect()
+            })
+        }
+        _ => None,
+    };
+
+    match icc_profile {
+        Some(icc_profile) => {
+            let color_space = match icc_profile_description(&icc_profile) {
+                Some(description) if description.contains("Display P3") => ColorSpace::DisplayP3,
+                Some(description) if description.starts_with("sRGB") => ColorSpace::Srgb,
+                _ => ColorSpace::Other,
+            };
+            (color_space, Some(icc_profile.into()))
+        }
+        None => (ColorSpace::Unspecified, None),
+    }
+}
+
+/// Read the description of an ICC profile, such as "sRGB IEC61966-2.1" or "Display P3".
+fn icc_profile_description(profile: &[u8]) -> Option<String> {
+    let read_u32 = |offset: usize| -> Option<usize> {
+        Some(u32::from_be_bytes(profile.get(offset..offset + 4)?.try_into().ok()?) as usize)
+    };
+
+    // The tag table follows the 128 byte header.
+    let tag_count = read_u32(128)?;
+    let tag_offset = (0..tag_count).find_map(|index| {
+        let entry = 132 + index * 12;
+        (profile.get(entry..entry + 4)? == b"desc").then(|| read_u32(entry + 4))?
+    })?;
+
+    match profile.get(tag_offset..tag_offset + 4)? {
+        // ICC v2 profiles describe themselves with an ASCII string.
+        b"desc" => {
+            let length = read_u32(tag_offset + 8)?;
+            let text = profile.get(tag_offset + 12..tag_offset + 12 + length)?;
+            let text = text.split(|byte| *byte == 0).next()?;
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        // ICC v4 profiles use a list of localized UTF-16 strings. Read the first.
+        b"mluc" => {
+            let length = read_u32(tag_offset + 20)?;
+            let offset = read_u32(tag_offset + 24)?;
+            let text = profile.get(tag_offset + offset..tag_offset + offset + length)?;
+            let text = text
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            Some(String::from_utf16_lossy(&text))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_color_space_from_jpeg_icc_profile() {
+        let description = b"Display P3\0";
+        let mut profile = vec![0; 128];
+        profile.extend_from_slice(&1u32.to_be_bytes());
+        profile.extend_from_slice(b"desc");
+        profile.extend_from_slice(&144u32.to_be_bytes());
+        profile.extend_from_slice(&(12 + description.len() as u32).to_be_bytes());
+        profile.extend_from_slice(b"desc\0\0\0\0");
+        profile.extend_from_slice(&(description.len() as u32).to_be_bytes());
+        profile.extend_from_slice(description);
+
+        // Split the profile across two APP2 segments, out of order.
+        let (first, second) = profile.split_at(100);
+        let mut jpeg = vec![0xFF, 0xD8];
+        for (sequence_number, chunk) in [(2, second), (1, first)] {
+            jpeg.extend_from_slice(&[0xFF, 0xE2]);
+            jpeg.extend_from_slice(&(16 + chunk.len() as u16).to_be_bytes());
+            jpeg.extend_from_slice(b"ICC_PROFILE\0");
+            jpeg.extend_from_slice(&[sequence_number, 2]);
+            jpeg.extend_from_slice(chunk);
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+
+        let (color_space, icc_profile) = read_color_space(&jpeg, ImageFormat::Jpeg);
+        assert_eq!(color_space, ColorSpace::DisplayP3);
+        assert_eq!(icc_profile.as_deref(), Some(&profile[..]));
+
+        assert_eq!(
+            read_color_space(&[0xFF, 0xD8, 0xFF, 0xDA], ImageFormat::Jpeg),
+            (ColorSpace::Unspecified, None)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+use collections::FxHashMap;
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+
+/// How urgently an image is fetched and decoded relative to other images waiting their
+/// turn. See [`crate::Img::load_priority`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LoadPriority {
+    /// Loaded once nothing more urgent is waiting, such as images scrolled out of view.
+    Low,
+    /// The priority of images that aren't loaded by an element, such as thumbnails.
+    #[default]
+    Normal,
+    /// Loaded before anything less urgent, such as images in view.
+    High,
+}
+
+/// How many remote images are downloaded at once unless set otherwise, which is as many
+/// connections as browsers typically open to a single host.
+pub(crate) const DEFAULT_IMAGE_FETCH_CONCURRENCY: usize = 6;
+
+/// Beyond this many priorities, those of images that are no longer waiting are forgotten.
+const MAX_LOAD_PRIORITIES: usize = 1024;
+
+/// Limits how many images are fetched, or decoded, at once, letting the waiting images with
+/// the highest [`LoadPriority`] go first. Priorities are keyed by the hash of each image's
+/// source and can change while it waits, so an image that scrolls into view overtakes those
+/// queued before it. See [`crate::AppContext::set_image_fetch_concurrency`] and
+/// [`crate::AppContext::set_image_decode_concurrency`].
+#[derive(Clone)]
+pub(crate) struct ImageLoadQueue(Arc<Mutex<LoadQueueState>>);
+
+struct LoadQueueState {
+    concurrency: usize,
+    running: usize,
+    next_order: u64,
+    waiters: Vec<LoadWaiter>,
+    priorities: FxHashMap<u64, LoadPriority>,
+}
+
+struct LoadWaiter {
+    key: u64,
+    order: u64,
+    sender: oneshot::Sender<LoadPermit>,
+}
+
+/// A turn to load an image, passed on to the next waiting image once it's dropped.
+pub(crate) struct LoadPermit(Option<Arc<Mutex<LoadQueueState>>>);
+
+impl ImageLoadQueue {
+    pub(crate) fn new(concurrency: usize) -> Self {
+        Self(Arc::new(Mutex::new(LoadQueueState {
+            concurrency: concurrency.max(1),
+            running: 0,
+            next_order: 0,
+            waiters: Vec::new(),
+            priorities: FxHashMap::default(),
+        })))
+    }
+
+    /// Set how many images can be loaded at once. Raising it lets waiting images start
+    /// straight away, while lowering it holds them back until fewer than the new limit are
+    /// running, without interrupting those that already started.
+    pub(crate) fn set_concurrency(&self, concurrency: usize) {
+        let mut state = self.0.lock();
+        state.concurrency = concurrency.max(1);
+        while state.running < state.concurrency {
+            let Some(waiter) = state.take_next_waiter() else {
+                break;
+            };
+            // The permit can't be dropped while the queue is locked, as that would pass the
+            // turn on, so a waiter that stopped waiting gives it up here instead.
+            if let Err(mut permit) = waiter.sender.send(LoadPermit(Some(self.0.clone()))) {
+                permit.0 = None;
+                continue;
+            }
+            state.running += 1;
+        }
+    }
+
+    /// Set the priority of the image with the given source key, whether it's already
+    /// waiting its turn or hasn't started to wait yet.
+    pub(crate) fn set_priority(&self, key: u64, priority: LoadPriority) {
+        let mut state = self.0.lock();
+        let state = &mut *state;
+        if state.priorities.len() >= MAX_LOAD_PRIORITIES && !state.priorities.contains_key(&key) {
+            let waiters = &state.waiters;
+            state
+                .priorities
+                .retain(|key, _| waiters.iter().any(|waiter| waiter.key == *key));
+        }
+        state.priorities.insert(key, priority);
+    }
+
+    /// Wait for a turn to load the image with the given source key.
+    pub(crate) async fn acquire(&self, key: u64) -> LoadPermit {
+        let receiver = {
+            let mut state = self.0.lock();
+            if state.running < state.concurrency {
+                state.running += 1;
+                state.priorities.remove(&key);
+                return LoadPermit(Some(self.0.clone()));
+            }
+            let (sender, receiver) = oneshot::channel();
+            let order = state.next_order;
+            state.next_order += 1;
+            state.waiters.push(LoadWaiter { key, order, sender });
+            receiver
+        };
+        // Waiters are only dropped once they've been given a turn, or have stopped waiting.
+        receiver.await.unwrap_or(LoadPermit(None))
+    }
+}
+
+impl LoadQueueState {
+    /// Take the waiter with the highest priority, and of those, the one that's waited the
+    /// longest.
+    fn take_next_waiter(&mut self) -> Option<LoadWaiter> {
+        self.waiters.retain(|waiter| !waiter.sender.is_canceled());
+        let priorities = &self.priorities;
+        let (index, _) = self.waiters.iter().enumerate().max_by_key(|(_, waiter)| {
+            let priority = priorities.get(&waiter.key).copied().unwrap_or_default();
+            (priority, Reverse(waiter.order))
+        })?;
+        let waiter = self.waiters.remove(index);
+        self.priorities.remove(&waiter.key);
+        Some(waiter)
+    }
+}
+
+impl Drop for LoadPermit {
+    fn drop(&mut self) {
+        let Some(queue) = self.0.take() else {
+            return;
+        };
+        loop {
+            let waiter = {
+                let mut state = queue.lock();
+                // Once the concurrency is lowered, turns aren't passed on until fewer than
+                // the new limit are running.
+                let waiter = if state.running > state.concurrency {
+                    None
+                } else {
+                    state.take_next_waiter()
+                };
+                if waiter.is_none() {
+                    state.running -= 1;
+                }
+                waiter
+            };
+            let Some(waiter) = waiter else {
+                return;
+            };
+            // The turn passes straight to the waiting image, unless it stopped waiting in
+            // the meantime, in which case it's offered to the next one.
+            match waiter.sender.send(LoadPermit(Some(queue.clone()))) {
+                Ok(()) => return,
+                Err(mut permit) => permit.0 = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_decode_queue_runs_higher_priorities_first() {
+        let queue = ImageLoadQueue::new(1);
+        let running = queue.acquire(0).now_or_never().unwrap();
+        let mut low = Box::pin(queue.acquire(1));
+        let mut high = Box::pin(queue.acquire(2));
+        let mut later_high = Box::pin(queue.acquire(3));
+        let mut abandoned = Box::pin(queue.acquire(4));
+        for waiter in [&mut low, &mut high, &mut later_high, &mut abandoned] {
+            assert!(waiter.now_or_never().is_none());
+        }
+        queue.set_priority(1, LoadPriority::Low);
+        queue.set_priority(2, LoadPriority::High);
+        queue.set_priority(3, LoadPriority::High);
+
+        drop(running);
+        let running = (&mut high).now_or_never().unwrap();
+        assert!((&mut later_high).now_or_never().is_none());
+
+        // Raising the priority of a waiting image lets it overtake those queued after it.
+        queue.set_priority(1, LoadPriority::High);
+        drop(running);
+        assert!((&mut later_high).now_or_never().is_none());
+        let running = (&mut low).now_or_never().unwrap();
+
+        // Images that stop waiting don't take a turn.
+        queue.set_priority(4, LoadPriority::High);
+        drop(abandoned);
+        drop(running);
+        let running = (&mut later_high).now_or_never().unwrap();
+        drop(running);
+        assert!(queue.acquire(5).now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_load_queue_concurrency_changes_in_place() {
+        let queue = ImageLoadQueue::new(1);
+        let first = queue.acquire(0).now_or_never().unwrap();
+        let mut second = Box::pin(queue.acquire(1));
+        let mut third = Box::pin(queue.acquire(2));
+        assert!((&mut second).now_or_never().is_none());
+        assert!((&mut third).now_or_never().is_none());
+
+        // Raising the limit lets images that are already waiting start.
+        queue.set_concurrency(2);
+        let second = (&mut second).now_or_never().unwrap();
+        assert!((&mut third).now_or_never().is_none());
+
+        // Lowering it holds them back until fewer than the new limit are running.
+        queue.set_concurrency(1);
+        drop(first);
+        assert!((&mut third).now_or_never().is_none());
+        drop(second);
+        assert!((&mut third).now_or_never().is_some());
+    }
+}
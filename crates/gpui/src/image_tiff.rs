@@ -0,0 +1,83 @@
+use std::io::Cursor;
+
+use image::{error::DecodingError, DynamicImage, ImageBuffer, ImageError, ImageFormat};
+use tiff::decoder::{Decoder, DecodingResult};
+
+use crate::{check_size, ImageCacheError};
+
+/// Decode a single page of a TIFF without decoding the pages before it. Each page has its
+/// own dimensions, so they're checked against `max_pixels` again.
+pub(crate) fn decode_tiff_page(
+    bytes: &[u8],
+    page: usize,
+    max_pixels: u64,
+) -> Result<DynamicImage, ImageCacheError> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))?;
+    for page_count in 1..=page {
+        if !decoder.more_images() {
+            return Err(ImageCacheError::PageOutOfRange { page, page_count });
+        }
+        decoder.next_image()?;
+    }
+
+    let (width, height) = decoder.dimensions()?;
+    check_size(width, height, max_pixels)?;
+    let color_type = decoder.colortype()?;
+    let image = match (color_type, decoder.read_image()?) {
+        (tiff::ColorType::Gray(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (tiff::ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        (tiff::ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        (tiff::ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        _ => None,
+    };
+    image.ok_or_else(|| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormat::Tiff.into(),
+            format!("unsupported color type {color_type:?} on page {page}"),
+        ))
+        .into()
+    })
+}
+
+impl From<tiff::TiffError> for ImageCacheError {
+    fn from(error: tiff::TiffError) -> Self {
+        ImageError::Decoding(DecodingError::new(ImageFormat::Tiff.into(), error)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn test_decode_tiff_page() {
+        let mut tiff = Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut tiff).unwrap();
+        encoder
+            .write_image::<tiff::encoder::colortype::Gray8>(2, 2, &[0; 4])
+            .unwrap();
+        encoder
+            .write_image::<tiff::encoder::colortype::RGB8>(3, 1, &[255; 9])
+            .unwrap();
+        let tiff = tiff.into_inner();
+
+        let page = decode_tiff_page(&tiff, 1, u64::MAX).unwrap();
+        assert_eq!((page.width(), page.height()), (3, 1));
+        assert!(matches!(
+            decode_tiff_page(&tiff, 2, u64::MAX),
+            Err(ImageCacheError::PageOutOfRange {
+                page: 2,
+                page_count: 2
+            })
+        ));
+    }
+}
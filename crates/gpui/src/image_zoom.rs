@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{
+    point, Bounds, DispatchPhase, Hitbox, MouseButton, MouseDownEvent, MouseMoveEvent,
+    MouseUpEvent, Pixels, Point, ScrollWheelEvent, WindowContext,
+};
+
+/// The limits of a [`crate::Img::zoomable`] image's zoom, which is relative to the size
+/// the image is fitted to by its [`crate::ObjectFit`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ZoomConfig {
+    /// The smallest zoom. Defaults to `1.0`, so the image can't be zoomed out past its fit.
+    pub min: f32,
+    /// The largest zoom. Defaults to `8.0`.
+    pub max: f32,
+    /// How much the zoom changes for each pixel scrolled, as a fraction of the current
+    /// zoom. Defaults to `0.005`.
+    pub speed: f32,
+}
+
+impl Default for ZoomConfig {
+    fn default() -> Self {
+        Self {
+            min: 1.,
+            max: 8.,
+            speed: 0.005,
+        }
+    }
+}
+
+/// The zoom and pan of a [`crate::Img::zoomable`] image, shared with its event listeners.
+pub(crate) struct ZoomState {
+    scale: f32,
+    /// How far the zoomed image is moved from where zooming would put it, relative to the
+    /// element's origin.
+    offset: Point<Pixels>,
+    /// Where the mouse was last seen while dragging the image.
+    drag_position: Option<Point<Pixels>>,
+    /// The element's bounds, as last painted.
+    bounds: Bounds<Pixels>,
+    /// The image's bounds before it's zoomed, as last painted.
+    content_bounds: Bounds<Pixels>,
+}
+
+impl Default for ZoomState {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            offset: Point::default(),
+            drag_position: None,
+            bounds: Bounds::default(),
+            content_bounds: Bounds::default(),
+        }
+    }
+}
+
+impl ZoomState {
+    /// Record where the element and image are painted, keeping the zoom within the
+    /// configured limits and the image covering the element.
+    pub(crate) fn update(
+        &mut self,
+        bounds: Bounds<Pixels>,
+        content_bounds: Bounds<Pixels>,
+        config: ZoomConfig,
+    ) {
+        self.bounds = bounds;
+        self.content_bounds = content_bounds;
+        self.scale = self.scale.clamp(config.min, config.max.max(config.min));
+        self.clamp_offset();
+    }
+
+    /// The bounds to paint the image at once it's zoomed.
+    pub(crate) fn apply(&self, content_bounds: Bounds<Pixels>) -> Bounds<Pixels> {
+        Bounds {
+            origin: self.bounds.origin
+                + self.offset
+                + (content_bounds.origin - self.bounds.origin) * self.scale,
+            size: content_bounds.size * self.scale,
+        }
+    }
+
+    /// Zoom to `scale`, keeping the point of the image under `position` in place. Returns
+    /// whether the zoom changed.
+    fn zoom_toward(&mut self, scale: f32, position: Point<Pixels>, config: ZoomConfig) -> bool {
+        let scale = scale.clamp(config.min, config.max.max(config.min));
+        if scale == self.scale {
+            return false;
+        }
+        let anchor = position - self.bounds.origin;
+        self.offset = anchor - (anchor - self.offset) * (scale / self.scale);
+        self.scale = scale;
+        self.clamp_offset();
+        true
+    }
+
+    /// Move the zoomed image by `delta`. Returns whether it moved.
+    fn pan(&mut self, delta: Point<Pixels>) -> bool {
+        let offset = self.offset;
+        self.offset = self.offset + delta;
+        self.clamp_offset();
+        self.offset != offset
+    }
+
+    /// Keep an image that's larger than the element covering it, and one that's smaller
+    /// within it.
+    fn clamp_offset(&mut self) {
+        let start = (self.content_bounds.origin - self.bounds.origin) * self.scale;
+        let size = self.content_bounds.size * self.scale;
+        let clamp = |offset: Pixels, start: Pixels, length: Pixels, bounds_length: Pixels| {
+            let slack = bounds_length - length;
+            (start + offset)
+                .max(slack.min(Pixels::ZERO))
+                .min(slack.max(Pixels::ZERO))
+                - start
+        };
+        self.offset = point(
+            clamp(self.offset.x, start.x, size.width, self.bounds.size.width),
+            clamp(self.offset.y, start.y, size.height, self.bounds.size.height),
+        );
+    }
+}
+
+/// Zoom the image toward the cursor when it's scrolled over and pan it when it's dragged,
+/// calling `on_zoom` with the new zoom and redrawing the parent view after each change.
+pub(crate) fn paint_zoom_listeners(
+    zoom: &Rc<RefCell<ZoomState>>,
+    config: ZoomConfig,
+    on_zoom: Option<Box<dyn Fn(f32, &mut WindowContext) + 'static>>,
+    hitbox: &Hitbox,
+    cx: &mut WindowContext,
+) {
+    let parent_id = cx.parent_view_id();
+    let redraw = move |cx: &mut WindowContext| {
+        if let Some(parent_id) = parent_id {
+            cx.notify(parent_id)
+        } else {
+            cx.refresh()
+        }
+    };
+
+    let line_height = cx.line_height();
+    cx.on_mouse_event({
+        let zoom = zoom.clone();
+        let hitbox = hitbox.clone();
+        move |event: &ScrollWheelEvent, phase, cx| {
+            if phase != DispatchPhase::Bubble || !hitbox.is_hovered(cx) {
+                return;
+            }
+            cx.stop_propagation();
+            let delta = event.delta.pixel_delta(line_height).y;
+            let scale = {
+                let mut zoom = zoom.borrow_mut();
+                let scale = zoom.scale * (1. + config.speed).powf(delta.0);
+                if !zoom.zoom_toward(scale, event.position, config) {
+                    return;
+                }
+                zoom.scale
+            };
+            if let Some(on_zoom) = &on_zoom {
+                on_zoom(scale, cx);
+            }
+            redraw(cx);
+        }
+    });
+
+    cx.on_mouse_event({
+        let zoom = zoom.clone();
+        let hitbox = hitbox.clone();
+        move |event: &MouseDownEvent, phase, cx| {
+            if phase == DispatchPhase::Bubble
+                && event.button == MouseButton::Left
+                && hitbox.is_hovered(cx)
+            {
+                zoom.borrow_mut().drag_position = Some(event.position);
+            }
+        }
+    });
+
+    cx.on_mouse_event({
+        let zoom = zoom.clone();
+        move |event: &MouseMoveEvent, phase, cx| {
+            if phase != DispatchPhase::Bubble {
+                return;
+            }
+            let mut zoom = zoom.borrow_mut();
+            let Some(drag_position) = zoom.drag_position else {
+                return;
+            };
+            if event.pressed_button != Some(MouseButton::Left) {
+                zoom.drag_position = None;
+                return;
+            }
+            zoom.drag_position = Some(event.position);
+            if zoom.pan(event.position - drag_position) {
+                drop(zoom);
+                redraw(cx);
+            }
+        }
+    });
+
+    cx.on_mouse_event({
+        let zoom = zoom.clone();
+        move |_: &MouseUpEvent, phase, _| {
+            if phase == DispatchPhase::Bubble {
+                zoom.borrow_mut().drag_position = None;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{px, size};
+
+    #[test]
+    fn test_zoom_keeps_the_cursor_still_and_the_image_covering() {
+        let bounds = Bounds {
+            origin: point(px(0.), px(0.)),
+            size: size(px(200.), px(100.)),
+        };
+        let config = ZoomConfig::default();
+        let mut zoom = ZoomState::default();
+        zoom.update(bounds, bounds, config);
+        assert_eq!(zoom.apply(bounds), bounds);
+
+        // The point under the cursor stays there.
+        let cursor = point(px(50.), px(25.));
+        assert!(zoom.zoom_toward(2., cursor, config));
+        let zoomed = zoom.apply(bounds);
+        assert_eq!(zoomed.size, size(px(400.), px(200.)));
+        assert_eq!(zoomed.origin, point(px(-50.), px(-25.)));
+
+        // Panning stops at the image's edges.
+        assert!(zoom.pan(point(px(100.), px(0.))));
+        assert_eq!(zoom.apply(bounds).origin, point(px(0.), px(-25.)));
+        assert!(!zoom.pan(point(px(10.), px(0.))));
+
+        assert!(zoom.zoom_toward(100., cursor, config));
+        assert_eq!(zoom.scale, 8.);
+        assert!(!zoom.zoom_toward(200., cursor, config));
+
+        // Zooming back out puts the image back in its fitted bounds.
+        assert!(zoom.zoom_toward(0.5, cursor, config));
+        assert_eq!(zoom.apply(bounds), bounds);
+    }
+}
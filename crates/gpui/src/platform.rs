@@ -343,6 +343,9 @@ pub(crate) trait PlatformAtlas: Send + Sync {
         key: &AtlasKey,
         build: &mut dyn FnMut() -> Result<(Size<DevicePixels>, Cow<'a, [u8]>)>,
     ) -> Result<AtlasTile>;
+
+    /// Free the space occupied by the tile for the given key, if any, so it can be reused.
+    fn remove(&self, key: &AtlasKey);
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
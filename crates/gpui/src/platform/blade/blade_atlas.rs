@@ -128,6 +128,16 @@ impl PlatformAtlas for BladeAtlas {
             Ok(tile)
         }
     }
+
+    fn remove(&self, key: &AtlasKey) {
+        let mut lock = self.0.lock();
+        if let Some(tile) = lock.tiles_by_key.remove(key) {
+            let textures = &mut lock.storage[tile.texture_id.kind];
+            textures[tile.texture_id.index as usize]
+                .allocator
+                .deallocate(tile.tile_id.into());
+        }
+    }
 }
 
 impl BladeAtlasState {
@@ -3,9 +3,9 @@
 
 use super::{BladeAtlas, BladeBelt, BladeBeltDescriptor, PATH_TEXTURE_FORMAT};
 use crate::{
-    AtlasTextureKind, AtlasTile, Bounds, ContentMask, Hsla, MonochromeSprite, Path, PathId,
-    PathVertex, PolychromeSprite, PrimitiveBatch, Quad, ScaledPixels, Scene, Shadow, Size,
-    Underline,
+    AtlasTextureKind, AtlasTile, BlendMode, Bounds, ContentMask, Hsla, MonochromeSprite, Path,
+    PathId, PathVertex, PolychromeSprite, PrimitiveBatch, Quad, Sampling, ScaledPixels, Scene,
+    Shadow, Size, Underline,
 };
 use bytemuck::{Pod, Zeroable};
 use collections::HashMap;
@@ -169,6 +169,25 @@ struct PathSprite {
     tile: AtlasTile,
 }
 
+/// The blend state for polychrome sprites painted with the given mode. The sprite shader
+/// outputs straight alpha for [`BlendMode::Normal`], and premultiplied alpha otherwise.
+fn blend_state(blend_mode: BlendMode) -> gpu::BlendState {
+    let (src_factor, dst_factor) = match blend_mode {
+        BlendMode::Normal => return gpu::BlendState::ALPHA_BLENDING,
+        BlendMode::Multiply => (gpu::BlendFactor::Dst, gpu::BlendFactor::OneMinusSrcAlpha),
+        BlendMode::Add => (gpu::BlendFactor::One, gpu::BlendFactor::One),
+        BlendMode::Screen => (gpu::BlendFactor::One, gpu::BlendFactor::OneMinusSrc),
+    };
+    gpu::BlendState {
+        color: gpu::BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: gpu::BlendOperation::Add,
+        },
+        alpha: gpu::BlendComponent::OVER,
+    }
+}
+
 struct BladePipelines {
     quads: gpu::RenderPipeline,
     shadows: gpu::RenderPipeline,
@@ -176,7 +195,8 @@ struct BladePipelines {
     paths: gpu::RenderPipeline,
     underlines: gpu::RenderPipeline,
     mono_sprites: gpu::RenderPipeline,
-    poly_sprites: gpu::RenderPipeline,
+    /// One pipeline for each [`BlendMode`], in the order of [`BlendMode::ALL`].
+    poly_sprites: Vec<gpu::RenderPipeline>,
     surfaces: gpu::RenderPipeline,
 }
 
@@ -303,23 +323,28 @@ impl BladePipelines {
                     write_mask: gpu::ColorWrites::default(),
                 }],
             }),
-            poly_sprites: gpu.create_render_pipeline(gpu::RenderPipelineDesc {
-                name: "poly-sprites",
-                data_layouts: &[&ShaderPolySpritesData::layout()],
-                vertex: shader.at("vs_poly_sprite"),
-                vertex_fetches: &[],
-                primitive: gpu::PrimitiveState {
-                    topology: gpu::PrimitiveTopology::TriangleStrip,
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                fragment: shader.at("fs_poly_sprite"),
-                color_targets: &[gpu::ColorTargetState {
-                    format: surface_info.format,
-                    blend: Some(gpu::BlendState::ALPHA_BLENDING),
-                    write_mask: gpu::ColorWrites::default(),
-                }],
-            }),
+            poly_sprites: BlendMode::ALL
+                .iter()
+                .map(|blend_mode| {
+                    gpu.create_render_pipeline(gpu::RenderPipelineDesc {
+                        name: "poly-sprites",
+                        data_layouts: &[&ShaderPolySpritesData::layout()],
+                        vertex: shader.at("vs_poly_sprite"),
+                        vertex_fetches: &[],
+                        primitive: gpu::PrimitiveState {
+                            topology: gpu::PrimitiveTopology::TriangleStrip,
+                            ..Default::default()
+                        },
+                        depth_stencil: None,
+                        fragment: shader.at("fs_poly_sprite"),
+                        color_targets: &[gpu::ColorTargetState {
+                            format: surface_info.format,
+                            blend: Some(blend_state(*blend_mode)),
+                            write_mask: gpu::ColorWrites::default(),
+                        }],
+                    })
+                })
+                .collect(),
             surfaces: gpu.create_render_pipeline(gpu::RenderPipelineDesc {
                 name: "surfaces",
                 data_layouts: &[&ShaderSurfacesData::layout()],
@@ -351,6 +376,7 @@ pub struct BladeRenderer {
     path_tiles: HashMap<PathId, AtlasTile>,
     atlas: Arc<BladeAtlas>,
     atlas_sampler: gpu::Sampler,
+    nearest_atlas_sampler: gpu::Sampler,
     #[cfg(target_os = "macos")]
     core_video_texture_cache: CVMetalTextureCache,
 }
@@ -388,6 +414,12 @@ impl BladeRenderer {
             min_filter: gpu::FilterMode::Linear,
             ..Default::default()
         });
+        let nearest_atlas_sampler = gpu.create_sampler(gpu::SamplerDesc {
+            name: "atlas nearest",
+            mag_filter: gpu::FilterMode::Nearest,
+            min_filter: gpu::FilterMode::Nearest,
+            ..Default::default()
+        });
 
         #[cfg(target_os = "macos")]
         let core_video_texture_cache = unsafe {
@@ -405,6 +437,7 @@ impl BladeRenderer {
             path_tiles: HashMap::default(),
             atlas,
             atlas_sampler,
+            nearest_atlas_sampler,
             #[cfg(target_os = "macos")]
             core_video_texture_cache,
         }
@@ -633,18 +666,24 @@ impl BladeRenderer {
                     }
                     PrimitiveBatch::PolychromeSprites {
                         texture_id,
+                        blend_mode,
+                        sampling,
                         sprites,
                     } => {
                         let tex_info = self.atlas.get_texture_info(texture_id);
                         let instance_buf =
                             unsafe { self.instance_belt.alloc_data(sprites, &self.gpu) };
-                        let mut encoder = pass.with(&self.pipelines.poly_sprites);
+                        let mut encoder =
+                            pass.with(&self.pipelines.poly_sprites[blend_mode as usize]);
                         encoder.bind(
                             0,
                             &ShaderPolySpritesData {
                                 globals,
                                 t_sprite: tex_info.raw_view,
-                                s_sprite: self.atlas_sampler,
+                                s_sprite: match sampling {
+                                    Sampling::Linear => self.atlas_sampler,
+                                    Sampling::Nearest => self.nearest_atlas_sampler,
+                                },
                                 b_poly_sprites: instance_buf,
                             },
                         );
@@ -176,6 +176,19 @@ fn quad_sdf(point: vec2<f32>, bounds: Bounds, corner_radii: Corners) -> f32 {
         corner_radius;
 }
 
+// Approximates the distance from a point to the ellipse filling the bounds, which is
+// accurate enough near the edge to anti-alias it.
+fn ellipse_sdf(point: vec2<f32>, bounds: Bounds) -> f32 {
+    let radii = bounds.size / 2.0;
+    let center_to_point = point - (bounds.origin + radii);
+    let k0 = length(center_to_point / radii);
+    let k1 = length(center_to_point / (radii * radii));
+    if (k1 == 0.0) {
+        return -min(radii.x, radii.y);
+    }
+    return k0 * (k0 - 1.0) / k1;
+}
+
 // --- quads --- //
 
 struct Quad {
@@ -527,11 +540,21 @@ fn fs_mono_sprite(input: MonoSpriteVarying) -> @location(0) vec4<f32> {
 
 struct PolychromeSprite {
     order: u32,
-    grayscale: u32,
+    desaturation: f32,
+    opacity: f32,
+    blend_mode: u32,
+    rotation: u32,
+    // Whether the sprite is flipped horizontally, flipped vertically and clipped to an
+    // ellipse, in its first, second and third bytes respectively. The fourth byte holds
+    // its sampling, which is applied by binding the matching sampler for its batch.
+    flags: u32,
     bounds: Bounds,
     content_mask: Bounds,
     corner_radii: Corners,
+    tint: Hsla,
     tile: AtlasTile,
+    transformation: TransformationMatrix,
+    clip_bounds: Bounds,
 }
 var<storage, read> b_poly_sprites: array<PolychromeSprite>;
 
@@ -539,6 +562,7 @@ struct PolySpriteVarying {
     @builtin(position) position: vec4<f32>,
     @location(0) tile_position: vec2<f32>,
     @location(1) @interpolate(flat) sprite_id: u32,
+    @location(2) local_position: vec2<f32>,
     @location(3) clip_distances: vec4<f32>,
 }
 
@@ -548,13 +572,33 @@ fn vs_poly_sprite(@builtin(vertex_index) vertex_id: u32, @builtin(instance_index
     let sprite = b_poly_sprites[instance_id];
 
     var out = PolySpriteVarying();
-    out.position = to_device_position(unit_vertex, sprite.bounds);
-    out.tile_position = to_tile_position(unit_vertex, sprite.tile);
+    out.position = to_device_position_transformed(unit_vertex, sprite.bounds, sprite.transformation);
+    // Round the corners of the sprite before it's transformed.
+    out.local_position = unit_vertex * sprite.bounds.size + sprite.bounds.origin;
+    out.tile_position = to_tile_position(orient_unit_vertex(unit_vertex, sprite), sprite.tile);
     out.sprite_id = instance_id;
     out.clip_distances = distance_from_clip_rect(unit_vertex, sprite.bounds, sprite.content_mask);
     return out;
 }
 
+// Map a corner of the sprite's bounds to the corner of the image drawn there,
+// undoing the sprite's flips and then its clockwise rotation.
+fn orient_unit_vertex(unit_vertex: vec2<f32>, sprite: PolychromeSprite) -> vec2<f32> {
+    var vertex = unit_vertex;
+    if ((sprite.flags & 0xFFu) != 0u) {
+        vertex.x = 1.0 - vertex.x;
+    }
+    if ((sprite.flags & 0xFF00u) != 0u) {
+        vertex.y = 1.0 - vertex.y;
+    }
+    switch (sprite.rotation) {
+        case 1u: { return vec2<f32>(vertex.y, 1.0 - vertex.x); }
+        case 2u: { return vec2<f32>(1.0 - vertex.x, 1.0 - vertex.y); }
+        case 3u: { return vec2<f32>(1.0 - vertex.y, vertex.x); }
+        default: { return vertex; }
+    }
+}
+
 @fragment
 fn fs_poly_sprite(input: PolySpriteVarying) -> @location(0) vec4<f32> {
     let sample = textureSample(t_sprite, s_sprite, input.tile_position);
@@ -564,14 +608,24 @@ fn fs_poly_sprite(input: PolySpriteVarying) -> @location(0) vec4<f32> {
     }
 
     let sprite = b_poly_sprites[input.sprite_id];
-    let distance = quad_sdf(input.position.xy, sprite.bounds, sprite.corner_radii);
+    var distance: f32;
+    if ((sprite.flags & 0xFF0000u) != 0u) {
+        distance = ellipse_sdf(input.local_position, sprite.clip_bounds);
+    } else {
+        distance = quad_sdf(input.local_position, sprite.clip_bounds, sprite.corner_radii);
+    }
 
     var color = sample;
-    if ((sprite.grayscale & 0xFFu) != 0u) {
+    if (sprite.desaturation > 0.0) {
         let grayscale = dot(color.rgb, GRAYSCALE_FACTORS);
-        color = vec4<f32>(vec3<f32>(grayscale), sample.a);
+        color = vec4<f32>(mix(color.rgb, vec3<f32>(grayscale), sprite.desaturation), sample.a);
+    }
+    color *= hsla_to_rgba(sprite.tint);
+    color.a *= sprite.opacity * saturate(0.5 - distance);
+    if (sprite.blend_mode != 0u) {
+        // The blend factors of the other modes expect premultiplied colors.
+        color = vec4<f32>(color.rgb * color.a, color.a);
     }
-    color.a *= saturate(0.5 - distance);
     return color;
 }
 
@@ -74,6 +74,20 @@ impl PlatformAtlas for MetalAtlas {
             Ok(tile)
         }
     }
+
+    fn remove(&self, key: &AtlasKey) {
+        let mut lock = self.0.lock();
+        if let Some(tile) = lock.tiles_by_key.remove(key) {
+            let textures = match tile.texture_id.kind {
+                AtlasTextureKind::Monochrome => &mut lock.monochrome_textures,
+                AtlasTextureKind::Polychrome => &mut lock.polychrome_textures,
+                AtlasTextureKind::Path => &mut lock.path_textures,
+            };
+            textures[tile.texture_id.index as usize]
+                .allocator
+                .deallocate(tile.tile_id.into());
+        }
+    }
 }
 
 impl MetalAtlasState {
@@ -1,8 +1,8 @@
 use super::metal_atlas::MetalAtlas;
 use crate::{
-    point, size, AtlasTextureId, AtlasTextureKind, AtlasTile, Bounds, ContentMask, DevicePixels,
-    Hsla, MonochromeSprite, Path, PathId, PathVertex, PolychromeSprite, PrimitiveBatch, Quad,
-    ScaledPixels, Scene, Shadow, Size, Surface, Underline,
+    point, size, AtlasTextureId, AtlasTextureKind, AtlasTile, BlendMode, Bounds, ContentMask,
+    Corners, DevicePixels, Hsla, MonochromeSprite, PaintSurface, Path, PathId, PathVertex,
+    PolychromeSprite, PrimitiveBatch, Quad, ScaledPixels, Scene, Shadow, Size, Underline,
 };
 use block::ConcreteBlock;
 use cocoa::{
@@ -52,7 +52,8 @@ pub(crate) struct MetalRenderer {
     quads_pipeline_state: metal::RenderPipelineState,
     underlines_pipeline_state: metal::RenderPipelineState,
     monochrome_sprites_pipeline_state: metal::RenderPipelineState,
-    polychrome_sprites_pipeline_state: metal::RenderPipelineState,
+    /// One pipeline for each [`BlendMode`], in the order of [`BlendMode::ALL`].
+    polychrome_sprites_pipeline_states: Vec<metal::RenderPipelineState>,
     surfaces_pipeline_state: metal::RenderPipelineState,
     unit_vertices: metal::Buffer,
     #[allow(clippy::arc_with_non_send_sync)]
@@ -162,14 +163,20 @@ impl MetalRenderer {
             "monochrome_sprite_fragment",
             MTLPixelFormat::BGRA8Unorm,
         );
-        let polychrome_sprites_pipeline_state = build_pipeline_state(
-            &device,
-            &library,
-            "polychrome_sprites",
-            "polychrome_sprite_vertex",
-            "polychrome_sprite_fragment",
-            MTLPixelFormat::BGRA8Unorm,
-        );
+        let polychrome_sprites_pipeline_states = BlendMode::ALL
+            .iter()
+            .map(|blend_mode| {
+                build_pipeline_state_with_blend_mode(
+                    &device,
+                    &library,
+                    "polychrome_sprites",
+                    "polychrome_sprite_vertex",
+                    "polychrome_sprite_fragment",
+                    MTLPixelFormat::BGRA8Unorm,
+                    *blend_mode,
+                )
+            })
+            .collect();
         let surfaces_pipeline_state = build_pipeline_state(
             &device,
             &library,
@@ -195,7 +202,7 @@ impl MetalRenderer {
             quads_pipeline_state,
             underlines_pipeline_state,
             monochrome_sprites_pipeline_state,
-            polychrome_sprites_pipeline_state,
+            polychrome_sprites_pipeline_states,
             surfaces_pipeline_state,
             unit_vertices,
             instance_buffer_pool,
@@ -337,9 +344,12 @@ impl MetalRenderer {
                 ),
                 PrimitiveBatch::PolychromeSprites {
                     texture_id,
+                    blend_mode,
                     sprites,
+                    ..
                 } => self.draw_polychrome_sprites(
                     texture_id,
+                    blend_mode,
                     sprites,
                     &mut instance_buffer,
                     &mut instance_offset,
@@ -856,6 +866,7 @@ impl MetalRenderer {
     fn draw_polychrome_sprites(
         &mut self,
         texture_id: AtlasTextureId,
+        blend_mode: BlendMode,
         sprites: &[PolychromeSprite],
         instance_buffer: &mut metal::Buffer,
         instance_offset: &mut usize,
@@ -872,7 +883,9 @@ impl MetalRenderer {
             DevicePixels(texture.width() as i32),
             DevicePixels(texture.height() as i32),
         );
-        command_encoder.set_render_pipeline_state(&self.polychrome_sprites_pipeline_state);
+        command_encoder.set_render_pipeline_state(
+            &self.polychrome_sprites_pipeline_states[blend_mode as usize],
+        );
         command_encoder.set_vertex_buffer(
             SpriteInputIndex::Vertices as u64,
             Some(&self.unit_vertices),
@@ -929,7 +942,7 @@ impl MetalRenderer {
 
     fn draw_surfaces(
         &mut self,
-        surfaces: &[Surface],
+        surfaces: &[PaintSurface],
         instance_buffer: &mut metal::Buffer,
         instance_offset: &mut usize,
         viewport_size: Size<DevicePixels>,
@@ -984,7 +997,7 @@ impl MetalRenderer {
             };
 
             align_offset(instance_offset);
-            let next_offset = *instance_offset + mem::size_of::<Surface>();
+            let next_offset = *instance_offset + mem::size_of::<PaintSurface>();
             if next_offset > INSTANCE_BUFFER_SIZE {
                 return false;
             }
@@ -994,6 +1007,11 @@ impl MetalRenderer {
                 Some(instance_buffer),
                 *instance_offset as u64,
             );
+            command_encoder.set_fragment_buffer(
+                SurfaceInputIndex::Surfaces as u64,
+                Some(instance_buffer),
+                *instance_offset as u64,
+            );
             command_encoder.set_vertex_bytes(
                 SurfaceInputIndex::TextureSize as u64,
                 mem::size_of_val(&texture_size) as u64,
@@ -1016,6 +1034,9 @@ impl MetalRenderer {
                     SurfaceBounds {
                         bounds: surface.bounds,
                         content_mask: surface.content_mask.clone(),
+                        clip_bounds: surface.clip_bounds,
+                        corner_radii: surface.corner_radii.clone(),
+                        clip_ellipse: surface.clip_ellipse,
                     },
                 );
             }
@@ -1034,6 +1055,28 @@ fn build_pipeline_state(
     vertex_fn_name: &str,
     fragment_fn_name: &str,
     pixel_format: metal::MTLPixelFormat,
+) -> metal::RenderPipelineState {
+    build_pipeline_state_with_blend_mode(
+        device,
+        library,
+        label,
+        vertex_fn_name,
+        fragment_fn_name,
+        pixel_format,
+        BlendMode::Normal,
+    )
+}
+
+/// Build a pipeline that blends with the given mode. The fragment function is expected to
+/// output straight alpha for [`BlendMode::Normal`], and premultiplied alpha otherwise.
+fn build_pipeline_state_with_blend_mode(
+    device: &metal::DeviceRef,
+    library: &metal::LibraryRef,
+    label: &str,
+    vertex_fn_name: &str,
+    fragment_fn_name: &str,
+    pixel_format: metal::MTLPixelFormat,
+    blend_mode: BlendMode,
 ) -> metal::RenderPipelineState {
     let vertex_fn = library
         .get_function(vertex_fn_name, None)
@@ -1051,9 +1094,24 @@ fn build_pipeline_state(
     color_attachment.set_blending_enabled(true);
     color_attachment.set_rgb_blend_operation(metal::MTLBlendOperation::Add);
     color_attachment.set_alpha_blend_operation(metal::MTLBlendOperation::Add);
-    color_attachment.set_source_rgb_blend_factor(metal::MTLBlendFactor::SourceAlpha);
+    let (source_rgb_blend_factor, destination_rgb_blend_factor) = match blend_mode {
+        BlendMode::Normal => (
+            metal::MTLBlendFactor::SourceAlpha,
+            metal::MTLBlendFactor::OneMinusSourceAlpha,
+        ),
+        BlendMode::Multiply => (
+            metal::MTLBlendFactor::DestinationColor,
+            metal::MTLBlendFactor::OneMinusSourceAlpha,
+        ),
+        BlendMode::Add => (metal::MTLBlendFactor::One, metal::MTLBlendFactor::One),
+        BlendMode::Screen => (
+            metal::MTLBlendFactor::One,
+            metal::MTLBlendFactor::OneMinusSourceColor,
+        ),
+    };
+    color_attachment.set_source_rgb_blend_factor(source_rgb_blend_factor);
     color_attachment.set_source_alpha_blend_factor(metal::MTLBlendFactor::One);
-    color_attachment.set_destination_rgb_blend_factor(metal::MTLBlendFactor::OneMinusSourceAlpha);
+    color_attachment.set_destination_rgb_blend_factor(destination_rgb_blend_factor);
     color_attachment.set_destination_alpha_blend_factor(metal::MTLBlendFactor::One);
 
     device
@@ -1159,4 +1217,7 @@ pub struct PathSprite {
 pub struct SurfaceBounds {
     pub bounds: Bounds<ScaledPixels>,
     pub content_mask: ContentMask<ScaledPixels>,
+    pub clip_bounds: Bounds<ScaledPixels>,
+    pub corner_radii: Corners<ScaledPixels>,
+    pub clip_ellipse: bool,
 }
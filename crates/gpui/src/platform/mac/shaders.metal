@@ -12,10 +12,12 @@ float4 to_device_position_transformed(float2 unit_vertex, Bounds_ScaledPixels bo
 
 float2 to_tile_position(float2 unit_vertex, AtlasTile tile,
                         constant Size_DevicePixels *atlas_size);
+float2 orient_unit_vertex(float2 unit_vertex, PolychromeSprite sprite);
 float4 distance_from_clip_rect(float2 unit_vertex, Bounds_ScaledPixels bounds,
                                Bounds_ScaledPixels clip_bounds);
 float quad_sdf(float2 point, Bounds_ScaledPixels bounds,
                Corners_ScaledPixels corner_radii);
+float ellipse_sdf(float2 point, Bounds_ScaledPixels bounds);
 float gaussian(float x, float sigma);
 float2 erf(float2 x);
 float blur_along_x(float x, float y, float sigma, float corner,
@@ -333,6 +335,7 @@ fragment float4 monochrome_sprite_fragment(
 struct PolychromeSpriteVertexOutput {
   float4 position [[position]];
   float2 tile_position;
+  float2 local_position;
   uint sprite_id [[flat]];
   float clip_distance [[clip_distance]][4];
 };
@@ -340,6 +343,7 @@ struct PolychromeSpriteVertexOutput {
 struct PolychromeSpriteFragmentInput {
   float4 position [[position]];
   float2 tile_position;
+  float2 local_position;
   uint sprite_id [[flat]];
 };
 
@@ -354,14 +358,20 @@ vertex PolychromeSpriteVertexOutput polychrome_sprite_vertex(
 
   float2 unit_vertex = unit_vertices[unit_vertex_id];
   PolychromeSprite sprite = sprites[sprite_id];
-  float4 device_position =
-      to_device_position(unit_vertex, sprite.bounds, viewport_size);
+  float4 device_position = to_device_position_transformed(
+      unit_vertex, sprite.bounds, sprite.transformation, viewport_size);
+  // Round the corners of the sprite before it's transformed.
+  float2 local_position =
+      unit_vertex * float2(sprite.bounds.size.width, sprite.bounds.size.height) +
+      float2(sprite.bounds.origin.x, sprite.bounds.origin.y);
   float4 clip_distance = distance_from_clip_rect(unit_vertex, sprite.bounds,
                                                  sprite.content_mask.bounds);
-  float2 tile_position = to_tile_position(unit_vertex, sprite.tile, atlas_size);
+  float2 tile_position = to_tile_position(
+      orient_unit_vertex(unit_vertex, sprite), sprite.tile, atlas_size);
   return PolychromeSpriteVertexOutput{
       device_position,
       tile_position,
+      local_position,
       sprite_id,
       {clip_distance.x, clip_distance.y, clip_distance.z, clip_distance.w}};
 }
@@ -373,19 +383,30 @@ fragment float4 polychrome_sprite_fragment(
   PolychromeSprite sprite = sprites[input.sprite_id];
   constexpr sampler atlas_texture_sampler(mag_filter::linear,
                                           min_filter::linear);
+  constexpr sampler nearest_atlas_texture_sampler(mag_filter::nearest,
+                                                  min_filter::nearest);
   float4 sample =
-      atlas_texture.sample(atlas_texture_sampler, input.tile_position);
+      sprite.sampling == Sampling_Nearest
+          ? atlas_texture.sample(nearest_atlas_texture_sampler,
+                                 input.tile_position)
+          : atlas_texture.sample(atlas_texture_sampler, input.tile_position);
   float distance =
-      quad_sdf(input.position.xy, sprite.bounds, sprite.corner_radii);
+      sprite.clip_ellipse
+          ? ellipse_sdf(input.local_position, sprite.clip_bounds)
+          : quad_sdf(input.local_position, sprite.clip_bounds,
+                     sprite.corner_radii);
 
   float4 color = sample;
-  if (sprite.grayscale) {
+  if (sprite.desaturation > 0.) {
     float grayscale = 0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b;
-    color.r = grayscale;
-    color.g = grayscale;
-    color.b = grayscale;
+    color.rgb = mix(color.rgb, float3(grayscale), sprite.desaturation);
+  }
+  color *= hsla_to_rgba(sprite.tint);
+  color.a *= sprite.opacity * saturate(0.5 - distance);
+  if (sprite.blend_mode != BlendMode_Normal) {
+    // The blend factors of the other modes expect premultiplied colors.
+    color.rgb *= color.a;
   }
-  color.a *= saturate(0.5 - distance);
   return color;
 }
 
@@ -477,12 +498,14 @@ fragment float4 path_sprite_fragment(
 struct SurfaceVertexOutput {
   float4 position [[position]];
   float2 texture_position;
+  uint surface_id [[flat]];
   float clip_distance [[clip_distance]][4];
 };
 
 struct SurfaceFragmentInput {
   float4 position [[position]];
   float2 texture_position;
+  uint surface_id [[flat]];
 };
 
 vertex SurfaceVertexOutput surface_vertex(
@@ -505,10 +528,13 @@ vertex SurfaceVertexOutput surface_vertex(
   return SurfaceVertexOutput{
       device_position,
       texture_position,
+      surface_id,
       {clip_distance.x, clip_distance.y, clip_distance.z, clip_distance.w}};
 }
 
 fragment float4 surface_fragment(SurfaceFragmentInput input [[stage_in]],
+                                 constant SurfaceBounds *surfaces
+                                 [[buffer(SurfaceInputIndex_Surfaces)]],
                                  texture2d<float> y_texture
                                  [[texture(SurfaceInputIndex_YTexture)]],
                                  texture2d<float> cb_cr_texture
@@ -523,7 +549,15 @@ fragment float4 surface_fragment(SurfaceFragmentInput input [[stage_in]],
       y_texture.sample(texture_sampler, input.texture_position).r,
       cb_cr_texture.sample(texture_sampler, input.texture_position).rg, 1.0);
 
-  return ycbcrToRGBTransform * ycbcr;
+  SurfaceBounds surface = surfaces[input.surface_id];
+  float distance =
+      surface.clip_ellipse
+          ? ellipse_sdf(input.position.xy, surface.clip_bounds)
+          : quad_sdf(input.position.xy, surface.clip_bounds,
+                     surface.corner_radii);
+  float4 color = ycbcrToRGBTransform * ycbcr;
+  color.a *= saturate(0.5 - distance);
+  return color;
 }
 
 float4 hsla_to_rgba(Hsla hsla) {
@@ -618,6 +652,28 @@ float2 to_tile_position(float2 unit_vertex, AtlasTile tile,
          float2((float)atlas_size->width, (float)atlas_size->height);
 }
 
+// Map a corner of the sprite's bounds to the corner of the image drawn there,
+// undoing the sprite's flips and then its clockwise rotation.
+float2 orient_unit_vertex(float2 unit_vertex, PolychromeSprite sprite) {
+  float2 vertex = unit_vertex;
+  if (sprite.flip_horizontal) {
+    vertex.x = 1. - vertex.x;
+  }
+  if (sprite.flip_vertical) {
+    vertex.y = 1. - vertex.y;
+  }
+  switch (sprite.rotation) {
+  case Rotation_Clockwise90:
+    return float2(vertex.y, 1. - vertex.x);
+  case Rotation_Clockwise180:
+    return float2(1. - vertex.x, 1. - vertex.y);
+  case Rotation_Clockwise270:
+    return float2(1. - vertex.y, vertex.x);
+  default:
+    return vertex;
+  }
+}
+
 float quad_sdf(float2 point, Bounds_ScaledPixels bounds,
                Corners_ScaledPixels corner_radii) {
   float2 half_size = float2(bounds.size.width, bounds.size.height) / 2.;
@@ -648,6 +704,20 @@ float quad_sdf(float2 point, Bounds_ScaledPixels bounds,
   return distance;
 }
 
+// Approximates the distance from a point to the ellipse filling the bounds, which is
+// accurate enough near the edge to anti-alias it.
+float ellipse_sdf(float2 point, Bounds_ScaledPixels bounds) {
+  float2 radii = float2(bounds.size.width, bounds.size.height) / 2.;
+  float2 center = float2(bounds.origin.x, bounds.origin.y) + radii;
+  float2 center_to_point = point - center;
+  float k0 = length(center_to_point / radii);
+  float k1 = length(center_to_point / (radii * radii));
+  if (k1 == 0.) {
+    return -min(radii.x, radii.y);
+  }
+  return k0 * (k0 - 1.) / k1;
+}
+
 // A standard gaussian function, used for weighting samples
 float gaussian(float x, float sigma) {
   return exp(-(x * x) / (2. * sigma * sigma)) / (sqrt(2. * M_PI_F) * sigma);
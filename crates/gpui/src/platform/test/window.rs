@@ -328,4 +328,8 @@ impl PlatformAtlas for TestAtlas {
 
         Ok(state.tiles[key].clone())
     }
+
+    fn remove(&self, key: &crate::AtlasKey) {
+        self.0.lock().tiles.remove(key);
+    }
 }
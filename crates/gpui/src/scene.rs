@@ -23,7 +23,7 @@ pub(crate) struct Scene {
     pub(crate) underlines: Vec<Underline>,
     pub(crate) monochrome_sprites: Vec<MonochromeSprite>,
     pub(crate) polychrome_sprites: Vec<PolychromeSprite>,
-    pub(crate) surfaces: Vec<Surface>,
+    pub(crate) surfaces: Vec<PaintSurface>,
 }
 
 impl Scene {
@@ -183,7 +183,7 @@ pub(crate) enum Primitive {
     Underline(Underline),
     MonochromeSprite(MonochromeSprite),
     PolychromeSprite(PolychromeSprite),
-    Surface(Surface),
+    Surface(PaintSurface),
 }
 
 impl Primitive {
@@ -231,9 +231,9 @@ struct BatchIterator<'a> {
     polychrome_sprites: &'a [PolychromeSprite],
     polychrome_sprites_start: usize,
     polychrome_sprites_iter: Peekable<slice::Iter<'a, PolychromeSprite>>,
-    surfaces: &'a [Surface],
+    surfaces: &'a [PaintSurface],
     surfaces_start: usize,
-    surfaces_iter: Peekable<slice::Iter<'a, Surface>>,
+    surfaces_iter: Peekable<slice::Iter<'a, PaintSurface>>,
 }
 
 impl<'a> Iterator for BatchIterator<'a> {
@@ -357,7 +357,10 @@ impl<'a> Iterator for BatchIterator<'a> {
                 })
             }
             PrimitiveKind::PolychromeSprite => {
-                let texture_id = self.polychrome_sprites_iter.peek().unwrap().tile.texture_id;
+                let first_sprite = self.polychrome_sprites_iter.peek().unwrap();
+                let texture_id = first_sprite.tile.texture_id;
+                let blend_mode = first_sprite.blend_mode;
+                let sampling = first_sprite.sampling;
                 let sprites_start = self.polychrome_sprites_start;
                 let mut sprites_end = self.polychrome_sprites_start + 1;
                 self.polychrome_sprites_iter.next();
@@ -366,6 +369,8 @@ impl<'a> Iterator for BatchIterator<'a> {
                     .next_if(|sprite| {
                         (sprite.order, batch_kind) < max_order_and_kind
                             && sprite.tile.texture_id == texture_id
+                            && sprite.blend_mode == blend_mode
+                            && sprite.sampling == sampling
                     })
                     .is_some()
                 {
@@ -374,6 +379,8 @@ impl<'a> Iterator for BatchIterator<'a> {
                 self.polychrome_sprites_start = sprites_end;
                 Some(PrimitiveBatch::PolychromeSprites {
                     texture_id,
+                    blend_mode,
+                    sampling,
                     sprites: &self.polychrome_sprites[sprites_start..sprites_end],
                 })
             }
@@ -409,9 +416,11 @@ pub(crate) enum PrimitiveBatch<'a> {
     },
     PolychromeSprites {
         texture_id: AtlasTextureId,
+        blend_mode: BlendMode,
+        sampling: Sampling,
         sprites: &'a [PolychromeSprite],
     },
-    Surfaces(&'a [Surface]),
+    Surfaces(&'a [PaintSurface]),
 }
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
@@ -588,6 +597,28 @@ impl TransformationMatrix {
         }
     }
 
+    /// The transformation that undoes this one, if it doesn't collapse everything onto a
+    /// line or a point.
+    pub fn inverse(&self) -> Option<Self> {
+        let [[a, b], [c, d]] = self.rotation_scale;
+        let determinant = a * d - b * c;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        let rotation_scale = [
+            [d / determinant, -b / determinant],
+            [-c / determinant, a / determinant],
+        ];
+        let [x, y] = self.translation;
+        Some(Self {
+            rotation_scale,
+            translation: [
+                -(rotation_scale[0][0] * x + rotation_scale[0][1] * y),
+                -(rotation_scale[1][0] * x + rotation_scale[1][1] * y),
+            ],
+        })
+    }
+
     /// Apply transformation to a point, mainly useful for debugging
     pub fn apply(&self, point: Point<Pixels>) -> Point<Pixels> {
         let input = [point.x.0, point.y.0];
@@ -640,17 +671,106 @@ impl From<MonochromeSprite> for Primitive {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// How an image is combined with what's already been painted beneath it. Every mode is
+/// supported by both the Metal renderer used on macOS and the Blade renderer used on
+/// Linux and Windows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum BlendMode {
+    /// Paint the image over the content beneath it.
+    #[default]
+    Normal = 0,
+    /// Multiply the colors of the image with those beneath it, darkening them.
+    Multiply = 1,
+    /// Add the colors of the image to those beneath it, lightening them.
+    Add = 2,
+    /// Multiply the inverse of the colors of the image with those beneath it and invert
+    /// the result, lightening them without saturating as quickly as [`BlendMode::Add`].
+    Screen = 3,
+}
+
+impl BlendMode {
+    pub(crate) const ALL: [BlendMode; 4] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Add,
+        BlendMode::Screen,
+    ];
+}
+
+/// How an image's pixels are sampled when it's drawn at a different size than its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Sampling {
+    /// Blend neighboring pixels, for smoothly scaled photos and illustrations.
+    #[default]
+    Linear = 0,
+    /// Use the nearest pixel, so scaled up pixel art keeps its hard edges.
+    Nearest = 1,
+}
+
+/// A clockwise rotation of an image by a whole number of quarter turns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Rotation {
+    /// Draw the image upright.
+    #[default]
+    None = 0,
+    /// Rotate the image a quarter turn clockwise.
+    Clockwise90 = 1,
+    /// Rotate the image upside down.
+    Clockwise180 = 2,
+    /// Rotate the image three quarter turns clockwise, or a quarter turn counterclockwise.
+    Clockwise270 = 3,
+}
+
+impl Rotation {
+    /// Whether the rotation swaps the image's width and height.
+    pub fn is_sideways(self) -> bool {
+        matches!(self, Rotation::Clockwise90 | Rotation::Clockwise270)
+    }
+
+    /// The size an image of the given size takes up once rotated.
+    pub fn rotate_size<T>(self, size: Size<T>) -> Size<T>
+    where
+        T: Clone + Default + Debug,
+    {
+        if self.is_sideways() {
+            Size {
+                width: size.height,
+                height: size.width,
+            }
+        } else {
+            size
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 #[repr(C)]
 pub(crate) struct PolychromeSprite {
     pub order: DrawOrder,
-    pub grayscale: bool,
+    pub desaturation: f32,
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    pub rotation: Rotation,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// Whether to clip to the ellipse filling `clip_bounds`, rather than to `clip_bounds`
+    /// with its corners rounded by `corner_radii`.
+    pub clip_ellipse: bool,
+    pub sampling: Sampling,
     pub bounds: Bounds<ScaledPixels>,
     pub content_mask: ContentMask<ScaledPixels>,
     pub corner_radii: Corners<ScaledPixels>,
+    pub tint: Hsla,
     pub tile: AtlasTile,
+    pub transformation: TransformationMatrix,
+    pub clip_bounds: Bounds<ScaledPixels>,
 }
 
+impl Eq for PolychromeSprite {}
+
 impl Ord for PolychromeSprite {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match self.order.cmp(&other.order) {
@@ -673,28 +793,33 @@ impl From<PolychromeSprite> for Primitive {
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) struct Surface {
+pub(crate) struct PaintSurface {
     pub order: DrawOrder,
     pub bounds: Bounds<ScaledPixels>,
     pub content_mask: ContentMask<ScaledPixels>,
+    /// The bounds to clip to, rounded by `corner_radii` or as an ellipse if `clip_ellipse`
+    /// is set, like a [`PolychromeSprite`]'s.
+    pub clip_bounds: Bounds<ScaledPixels>,
+    pub corner_radii: Corners<ScaledPixels>,
+    pub clip_ellipse: bool,
     #[cfg(target_os = "macos")]
     pub image_buffer: media::core_video::CVImageBuffer,
 }
 
-impl Ord for Surface {
+impl Ord for PaintSurface {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.order.cmp(&other.order)
     }
 }
 
-impl PartialOrd for Surface {
+impl PartialOrd for PaintSurface {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl From<Surface> for Primitive {
-    fn from(surface: Surface) -> Self {
+impl From<PaintSurface> for Primitive {
+    fn from(surface: PaintSurface) -> Self {
         Primitive::Surface(surface)
     }
 }
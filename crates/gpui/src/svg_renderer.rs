@@ -1,38 +1,338 @@
-use crate::{AssetSource, DevicePixels, IsZero, Result, SharedString, Size};
+use crate::{
+    hash, pixmap_to_bgra, AssetSource, DevicePixels, Hsla, ImageCacheError, ImageData, IsZero,
+    Pixels, Result, SharedString, Size,
+};
 use anyhow::anyhow;
-use resvg::tiny_skia::Pixmap;
-use std::{hash::Hash, sync::Arc};
+use collections::FxHashMap;
+use parking_lot::{const_rwlock, Mutex, RwLock};
+use resvg::tiny_skia::{Color, ColorU8, Pixmap, PixmapPaint, Transform};
+use std::{
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+pub use usvg::{fontdb, ImageRendering, ShapeRendering, TextRendering};
+use util::ResultExt;
 
 #[derive(Clone, PartialEq, Hash, Eq)]
 pub(crate) struct RenderSvgParams {
     pub(crate) path: SharedString,
+    /// The size the SVG is displayed at, independent of the display's scale factor.
+    pub(crate) logical_size: Size<Pixels>,
+    /// The size the SVG is rasterized at.
     pub(crate) size: Size<DevicePixels>,
 }
 
+impl RenderSvgParams {
+    pub(crate) fn new(path: SharedString, logical_size: Size<Pixels>, scale_factor: f32) -> Self {
+        // Render the SVG at twice the size to get a higher quality result.
+        let size = logical_size
+            .map(|pixels| DevicePixels::from((pixels.0 * scale_factor * 2.).ceil() as i32));
+        Self {
+            path,
+            logical_size,
+            size,
+        }
+    }
+}
+
+/// The default limit on the width and height of an SVG rendered at a scale factor of
+/// its declared size. See [`crate::AppContext::set_max_svg_rasterization_size`].
+pub(crate) const DEFAULT_MAX_SVG_RASTERIZATION_SIZE: DevicePixels = DevicePixels(4096);
+
 #[derive(Clone)]
 pub(crate) struct SvgRenderer {
     asset_source: Arc<dyn AssetSource>,
+    /// Fonts used for SVG `<text>`.
+    fonts: SvgFonts,
+    max_rasterization_size: DevicePixels,
+    failure_policy: SvgFailurePolicy,
+    options: SvgOptions,
+    /// The parsed SVGs rendered by [`SvgRenderer::render`], keyed by their asset path and
+    /// shared by every clone of this renderer, so that an icon drawn at several sizes is
+    /// only parsed once.
+    trees: Arc<Mutex<FxHashMap<SharedString, ParsedTree>>>,
+}
+
+/// An SVG asset parsed into a tree, along with what it was parsed from, so that it's
+/// parsed again if the asset or the options change.
+struct ParsedTree {
+    content_hash: u64,
+    options: SvgOptions,
+    fontdb: Arc<fontdb::Database>,
+    tree: Arc<usvg::Tree>,
+}
+
+/// Which fonts are loaded to render text in SVGs. Loading every system font can take a
+/// while on systems with many fonts installed, so apps whose SVGs have no text can skip it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SvgFontLoading {
+    /// Don't load any fonts. Text in SVGs isn't rendered.
+    None,
+    /// Load the system fonts the first time an SVG is rendered.
+    #[default]
+    System,
+    /// Load the font files at the given paths the first time an SVG is rendered. Files
+    /// that fail to load are logged and skipped.
+    Custom(Vec<PathBuf>),
+}
+
+/// The fonts an [`SvgRenderer`] renders text with.
+#[derive(Clone)]
+enum SvgFonts {
+    /// The system fonts, shared by every renderer. See [`svg_fontdb`].
+    System,
+    /// Fonts loaded from the given files once they're first needed, shared by every clone
+    /// of the renderer.
+    Files(Arc<[PathBuf]>, Arc<OnceLock<Arc<fontdb::Database>>>),
+    /// Fonts that have already been loaded.
+    Loaded(Arc<fontdb::Database>),
+}
+
+/// Options that control how SVGs are parsed. The defaults match those of `usvg`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgOptions {
+    /// The resolution used to convert physical units, such as `in` and `pt`, to pixels.
+    pub dpi: f32,
+    /// The size of SVGs that declare neither a size nor a `viewBox`.
+    pub default_size: Size<f32>,
+    /// The font family used for text that doesn't specify one.
+    pub font_family: SharedString,
+    /// The font size used for text that doesn't specify one.
+    pub font_size: f32,
+    /// The languages, in order of preference, used to resolve `systemLanguage` attributes.
+    pub languages: Vec<SharedString>,
+    /// The default `shape-rendering` hint.
+    pub shape_rendering: ShapeRendering,
+    /// The default `text-rendering` hint.
+    pub text_rendering: TextRendering,
+    /// The default `image-rendering` hint.
+    pub image_rendering: ImageRendering,
+    /// Whether SVGs are rendered smoothly or crisply. [`SvgQuality::Crisp`] takes precedence
+    /// over the rendering hints above.
+    pub quality: SvgQuality,
+}
+
+/// What happens when an SVG image can't be rasterized because of its size, such as when
+/// it declares a size of zero or is asked to render larger than can be allocated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SvgFailurePolicy {
+    /// Fail to load the image, with [`ImageCacheError::ZeroSize`] or
+    /// [`ImageCacheError::RenderFailed`].
+    #[default]
+    Error,
+    /// Log the failure and load a 1x1 transparent image instead, so that one bad asset
+    /// leaves an empty space rather than an error.
+    TransparentFallback,
+}
+
+/// How smoothly SVGs are rasterized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SvgQuality {
+    /// Anti-alias shapes and text, and filter embedded raster images smoothly.
+    #[default]
+    Smooth,
+    /// Render shapes and text without anti-aliasing, and scale embedded raster images with
+    /// nearest-neighbor filtering, for crisp pixel-art style graphics. Elements that set
+    /// their own rendering hints keep them.
+    Crisp,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        let defaults = usvg::Options::default();
+        Self {
+            dpi: defaults.dpi,
+            default_size: crate::size(
+                defaults.default_size.width(),
+                defaults.default_size.height(),
+            ),
+            font_family: defaults.font_family.into(),
+            font_size: defaults.font_size,
+            languages: defaults.languages.into_iter().map(Into::into).collect(),
+            shape_rendering: defaults.shape_rendering,
+            text_rendering: defaults.text_rendering,
+            image_rendering: defaults.image_rendering,
+            quality: SvgQuality::default(),
+        }
+    }
+}
+
+impl Eq for SvgOptions {}
+
+impl Hash for SvgOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.dpi.to_bits().hash(state);
+        self.default_size.width.to_bits().hash(state);
+        self.default_size.height.to_bits().hash(state);
+        self.font_family.hash(state);
+        self.font_size.to_bits().hash(state);
+        self.languages.hash(state);
+        (self.shape_rendering as u8).hash(state);
+        (self.text_rendering as u8).hash(state);
+        (self.image_rendering as u8).hash(state);
+        self.quality.hash(state);
+    }
+}
+
+impl SvgOptions {
+    fn to_usvg(&self) -> usvg::Options {
+        let defaults = usvg::Options::default();
+        let (shape_rendering, text_rendering, image_rendering) = match self.quality {
+            SvgQuality::Smooth => (
+                self.shape_rendering,
+                self.text_rendering,
+                self.image_rendering,
+            ),
+            SvgQuality::Crisp => (
+                ShapeRendering::CrispEdges,
+                TextRendering::OptimizeSpeed,
+                ImageRendering::OptimizeSpeed,
+            ),
+        };
+        usvg::Options {
+            dpi: self.dpi,
+            default_size: usvg::Size::from_wh(self.default_size.width, self.default_size.height)
+                .unwrap_or(defaults.default_size),
+            font_family: self.font_family.to_string(),
+            font_size: self.font_size,
+            languages: self.languages.iter().map(ToString::to_string).collect(),
+            shape_rendering,
+            text_rendering,
+            image_rendering,
+            ..defaults
+        }
+    }
+}
+
+static SYSTEM_FONTDB: RwLock<Option<Arc<fontdb::Database>>> = const_rwlock(None);
+
+/// The system fonts, shared by every renderer that isn't given its own font database.
+/// Loading them is slow, so this only happens the first time an SVG is rendered or the
+/// fonts are warmed, and again after [`reload_svg_fontdb`]. Callers that arrive while
+/// they're loading wait for them, rather than loading them again.
+fn svg_fontdb() -> Arc<fontdb::Database> {
+    if let Some(fontdb) = SYSTEM_FONTDB.read().as_ref() {
+        return fontdb.clone();
+    }
+    SYSTEM_FONTDB
+        .write()
+        .get_or_insert_with(|| {
+            let mut fontdb = fontdb::Database::new();
+            fontdb.load_system_fonts();
+            Arc::new(fontdb)
+        })
+        .clone()
+}
+
+/// Forget the loaded system fonts, so that they're loaded again the next time an SVG is
+/// rendered, picking up any fonts installed since.
+pub(crate) fn reload_svg_fontdb() {
+    SYSTEM_FONTDB.write().take();
 }
 
 pub enum SvgSize {
+    /// Render into the given size, preserving the SVG's aspect ratio.
     Size(Size<DevicePixels>),
+    /// Render at the SVG's own size, multiplied by the given factor. The result is scaled
+    /// down further if either side would exceed the renderer's maximum rasterization size.
     ScaleFactor(f32),
+    /// Stretch the SVG to exactly fill the given size, scaling each axis independently.
+    Stretch(Size<DevicePixels>),
 }
 
 impl SvgRenderer {
     pub fn new(asset_source: Arc<dyn AssetSource>) -> Self {
-        Self { asset_source }
+        Self {
+            asset_source,
+            fonts: SvgFonts::System,
+            max_rasterization_size: DEFAULT_MAX_SVG_RASTERIZATION_SIZE,
+            failure_policy: SvgFailurePolicy::default(),
+            options: SvgOptions::default(),
+            trees: Default::default(),
+        }
+    }
+
+    pub fn set_options(&mut self, options: SvgOptions) {
+        self.options = options;
     }
 
-    pub fn render(&self, params: &RenderSvgParams) -> Result<Vec<u8>> {
+    /// Render SVGs smoothly or crisply, keeping the rest of the options.
+    pub fn set_quality(&mut self, quality: SvgQuality) {
+        self.options.quality = quality;
+    }
+
+    /// Render SVG text with the fonts in `fontdb` rather than the system fonts.
+    pub fn set_fontdb(&mut self, fontdb: Arc<fontdb::Database>) {
+        self.fonts = SvgFonts::Loaded(fontdb);
+    }
+
+    /// Choose which fonts are loaded to render SVG text, replacing any set with
+    /// [`SvgRenderer::set_fontdb`].
+    pub fn set_font_loading(&mut self, font_loading: SvgFontLoading) {
+        self.fonts = match font_loading {
+            SvgFontLoading::None => SvgFonts::Loaded(Arc::new(fontdb::Database::new())),
+            SvgFontLoading::System => SvgFonts::System,
+            SvgFontLoading::Custom(paths) => SvgFonts::Files(paths.into(), Default::default()),
+        };
+    }
+
+    pub fn set_max_rasterization_size(&mut self, max_size: DevicePixels) {
+        self.max_rasterization_size = max_size;
+    }
+
+    pub fn set_failure_policy(&mut self, failure_policy: SvgFailurePolicy) {
+        self.failure_policy = failure_policy;
+    }
+
+    pub fn set_asset_source(&mut self, asset_source: Arc<dyn AssetSource>) {
+        self.asset_source = asset_source;
+    }
+
+    pub fn asset_source(&self) -> &Arc<dyn AssetSource> {
+        &self.asset_source
+    }
+
+    /// Load the fonts used to render SVG text, if they haven't been loaded yet, so that
+    /// rendering later doesn't wait for them. See [`crate::AppContext::warm_svg_fontdb`].
+    pub(crate) fn warm_fontdb(&self) {
+        self.fontdb();
+    }
+
+    fn fontdb(&self) -> Arc<fontdb::Database> {
+        match &self.fonts {
+            SvgFonts::System => svg_fontdb(),
+            SvgFonts::Files(paths, fontdb) => fontdb
+                .get_or_init(|| {
+                    let mut fontdb = fontdb::Database::new();
+                    for path in paths.iter() {
+                        fontdb.load_font_file(path).log_err();
+                    }
+                    Arc::new(fontdb)
+                })
+                .clone(),
+            SvgFonts::Loaded(fontdb) => fontdb.clone(),
+        }
+    }
+
+    /// Render the SVG asset at `params.path` as an alpha mask, returning the size it was
+    /// rendered at along with it. That's `params.size` unless it's larger than the maximum
+    /// rasterization size, in which case the mask is smaller and scaled up when painted.
+    pub fn render(&self, params: &RenderSvgParams) -> Result<(Size<DevicePixels>, Vec<u8>)> {
         if params.size.is_zero() {
             return Err(anyhow!("can't render at a zero size"));
         }
 
         // Load the tree.
         let bytes = self.asset_source.load(&params.path)?;
+        let tree = self.parsed_tree(&params.path, &bytes)?;
 
-        let pixmap = self.render_pixmap(&bytes, SvgSize::Size(params.size))?;
+        let pixmap = render_tree(
+            &tree,
+            SvgSize::Size(params.size),
+            None,
+            self.max_rasterization_size,
+        )?;
 
         // Convert the pixmap's pixels into an alpha mask.
         let alpha_mask = pixmap
@@ -40,31 +340,452 @@ impl SvgRenderer {
             .iter()
             .map(|p| p.alpha())
             .collect::<Vec<_>>();
-        Ok(alpha_mask)
+        let size = crate::size(
+            DevicePixels(pixmap.width() as i32),
+            DevicePixels(pixmap.height() as i32),
+        );
+        Ok((size, alpha_mask))
     }
 
-    pub fn render_pixmap(&self, bytes: &[u8], size: SvgSize) -> Result<Pixmap, usvg::Error> {
-        let tree = usvg::Tree::from_data(&bytes, &usvg::Options::default())?;
+    /// Render the SVG asset at `params.path` in full color, unlike the alpha mask that
+    /// [`Self::render`] produces for tintable icons. The SVG is composited over
+    /// `background`, flattening its transparency, or left transparent if it's `None`.
+    pub fn render_rgba(
+        &self,
+        params: &RenderSvgParams,
+        background: Option<Hsla>,
+    ) -> Result<ImageData> {
+        if params.size.is_zero() {
+            return Err(anyhow!("can't render at a zero size"));
+        }
 
-        let size = match size {
-            SvgSize::Size(size) => size,
-            SvgSize::ScaleFactor(scale) => crate::size(
-                DevicePixels((tree.size().width() * scale) as i32),
-                DevicePixels((tree.size().height() * scale) as i32),
-            ),
+        let bytes = self.asset_source.load(&params.path)?;
+        let tree = self.parsed_tree(&params.path, &bytes)?;
+        let pixmap = render_tree(
+            &tree,
+            SvgSize::Size(params.size),
+            None,
+            self.max_rasterization_size,
+        )?;
+        let pixmap = match background {
+            Some(background) => flatten(&pixmap, background),
+            None => pixmap,
         };
+        Ok(ImageData::new(pixmap_to_bgra(pixmap)))
+    }
 
-        // Render the SVG to a pixmap with the specified width and height.
-        let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width.into(), size.height.into())
-            .ok_or(usvg::Error::InvalidSize)?;
+    /// Get the parsed tree of the SVG asset at `path`, parsing it only if it hasn't been
+    /// parsed from the same bytes, options and fonts before.
+    fn parsed_tree(&self, path: &SharedString, bytes: &[u8]) -> Result<Arc<usvg::Tree>> {
+        let content_hash = hash(&bytes);
+        let fontdb = self.fontdb();
+        if let Some(parsed) = self.trees.lock().get(path) {
+            if parsed.content_hash == content_hash
+                && parsed.options == self.options
+                && Arc::ptr_eq(&parsed.fontdb, &fontdb)
+            {
+                return Ok(parsed.tree.clone());
+            }
+        }
 
-        let transform = tree.view_box().to_transform(
+        let tree = Arc::new(usvg::Tree::from_data(
+            bytes,
+            &self.options.to_usvg(),
+            &fontdb,
+        )?);
+        self.trees.lock().insert(
+            path.clone(),
+            ParsedTree {
+                content_hash,
+                options: self.options.clone(),
+                fontdb,
+                tree: tree.clone(),
+            },
+        );
+        Ok(tree)
+    }
+
+    /// Get the size an SVG declares for itself, rounded up to whole pixels.
+    pub fn intrinsic_size(&self, bytes: &[u8]) -> Result<Size<DevicePixels>, usvg::Error> {
+        let tree = usvg::Tree::from_data(bytes, &self.options.to_usvg(), &self.fontdb())?;
+        Ok(crate::size(
+            DevicePixels(tree.size().width().ceil() as i32),
+            DevicePixels(tree.size().height().ceil() as i32),
+        ))
+    }
+
+    /// Render an SVG to a pixmap. If a `color` is given, every fill and stroke is
+    /// painted with it instead of the colors authored in the SVG. Fails with
+    /// [`ImageCacheError::RenderFailed`] if the resulting size is empty or too large
+    /// to allocate, unless the renderer's [`SvgFailurePolicy`] falls back to a
+    /// transparent pixmap.
+    pub fn render_pixmap(
+        &self,
+        bytes: &[u8],
+        size: SvgSize,
+        color: Option<Hsla>,
+    ) -> Result<Pixmap, ImageCacheError> {
+        let result = render_pixmap(
+            bytes,
+            size,
+            color,
+            &self.options,
+            &self.fontdb(),
+            self.max_rasterization_size,
+        );
+        match result {
+            Err(error @ (ImageCacheError::ZeroSize | ImageCacheError::RenderFailed(_)))
+                if self.failure_policy == SvgFailurePolicy::TransparentFallback =>
+            {
+                log::warn!("rendering a transparent image in place of an SVG: {error}");
+                Ok(Pixmap::new(1, 1).expect("a 1x1 pixmap can always be allocated"))
+            }
+            result => result,
+        }
+    }
+}
+
+/// Render an SVG to a pixmap, independently of any [`SvgRenderer`]. See
+/// [`SvgRenderer::render_pixmap`].
+pub(crate) fn render_pixmap(
+    bytes: &[u8],
+    size: SvgSize,
+    color: Option<Hsla>,
+    options: &SvgOptions,
+    fontdb: &fontdb::Database,
+    max_rasterization_size: DevicePixels,
+) -> Result<Pixmap, ImageCacheError> {
+    let tree = usvg::Tree::from_data(bytes, &options.to_usvg(), fontdb)?;
+    render_tree(&tree, size, color, max_rasterization_size)
+}
+
+/// Render an already parsed SVG to a pixmap. See [`SvgRenderer::render_pixmap`].
+fn render_tree(
+    tree: &usvg::Tree,
+    size: SvgSize,
+    color: Option<Hsla>,
+    max_rasterization_size: DevicePixels,
+) -> Result<Pixmap, ImageCacheError> {
+    let (size, stretch) = match size {
+        SvgSize::Size(size) => (clamp_size(size, max_rasterization_size), false),
+        SvgSize::ScaleFactor(scale) => {
+            // SVGs can declare arbitrarily large sizes, so cap the longest side rather
+            // than allocating whatever they ask for.
+            let longest_side = tree.size().width().max(tree.size().height());
+            let max_scale = max_rasterization_size.0 as f32 / longest_side;
+            let scale = scale.min(max_scale);
+            (
+                crate::size(
+                    DevicePixels((tree.size().width() * scale) as i32),
+                    DevicePixels((tree.size().height() * scale) as i32),
+                ),
+                false,
+            )
+        }
+        SvgSize::Stretch(size) => (clamp_size(size, max_rasterization_size), true),
+    };
+
+    let invalid_size = || {
+        ImageCacheError::RenderFailed(
+            format!(
+                "can't render an SVG at {}x{} pixels",
+                size.width.0, size.height.0
+            )
+            .into(),
+        )
+    };
+
+    // Render the SVG to a pixmap with the specified width and height.
+    if size.width.0 <= 0 || size.height.0 <= 0 {
+        return Err(invalid_size());
+    }
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width.into(), size.height.into())
+        .ok_or_else(invalid_size)?;
+
+    let transform = if stretch {
+        Transform::from_scale(
+            size.width.0 as f32 / tree.size().width(),
+            size.height.0 as f32 / tree.size().height(),
+        )
+    } else {
+        tree.view_box().to_transform(
             resvg::tiny_skia::Size::from_wh(size.width.0 as f32, size.height.0 as f32)
-                .ok_or(usvg::Error::InvalidSize)?,
+                .ok_or_else(invalid_size)?,
+        )
+    };
+
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+
+    if let Some(color) = color {
+        recolor(&mut pixmap, color);
+    }
+
+    Ok(pixmap)
+}
+
+/// Scale a size down, preserving its aspect ratio, so that its longest side is at most
+/// `max_size`.
+fn clamp_size(size: Size<DevicePixels>, max_size: DevicePixels) -> Size<DevicePixels> {
+    let longest_side = size.width.0.max(size.height.0);
+    if longest_side <= max_size.0 {
+        return size;
+    }
+    let scale = max_size.0 as f32 / longest_side as f32;
+    crate::size(
+        DevicePixels(((size.width.0 as f32 * scale) as i32).max(1)),
+        DevicePixels(((size.height.0 as f32 * scale) as i32).max(1)),
+    )
+}
+
+/// Composite a pixmap over a solid color.
+fn flatten(pixmap: &Pixmap, background: Hsla) -> Pixmap {
+    let mut flattened = Pixmap::new(pixmap.width(), pixmap.height())
+        .expect("a pixmap the size of an existing one can be allocated");
+    let background = background.to_rgb();
+    let background = Color::from_rgba(
+        background.r.clamp(0., 1.),
+        background.g.clamp(0., 1.),
+        background.b.clamp(0., 1.),
+        background.a.clamp(0., 1.),
+    );
+    if let Some(background) = background {
+        flattened.fill(background);
+    }
+    flattened.draw_pixmap(
+        0,
+        0,
+        pixmap.as_ref(),
+        &PixmapPaint::default(),
+        Transform::identity(),
+        None,
+    );
+    flattened
+}
+
+/// Replace the color of every pixel while keeping its coverage. For shapes painted
+/// with a single color this is equivalent to substituting their fill and stroke paint,
+/// and avoids rebuilding the (immutable) `usvg::Tree`.
+fn recolor(pixmap: &mut Pixmap, color: Hsla) {
+    let color = color.to_rgb();
+    let to_u8 = |component: f32| (component.clamp(0., 1.) * 255.).round() as u8;
+    for pixel in pixmap.pixels_mut() {
+        let alpha = pixel.alpha() as f32 / 255. * color.a;
+        *pixel = ColorU8::from_rgba(to_u8(color.r), to_u8(color.g), to_u8(color.b), to_u8(alpha))
+            .premultiply();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::px;
+    use std::borrow::Cow;
+
+    const SVG: &[u8] = br#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4"/>"#;
+
+    #[test]
+    fn test_render_pixmap_rejects_degenerate_sizes() {
+        let renderer = SvgRenderer::new(Arc::new(()));
+        for size in [
+            SvgSize::Size(crate::size(DevicePixels(0), DevicePixels(4))),
+            SvgSize::Stretch(crate::size(DevicePixels(-1), DevicePixels(4))),
+            SvgSize::ScaleFactor(0.),
+            SvgSize::Size(crate::size(DevicePixels(i32::MAX), DevicePixels(i32::MAX))),
+        ] {
+            assert!(matches!(
+                renderer.render_pixmap(SVG, size, None),
+                Err(ImageCacheError::RenderFailed(_))
+            ));
+        }
+
+        let pixmap = renderer
+            .render_pixmap(SVG, SvgSize::ScaleFactor(2.), None)
+            .unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (8, 8));
+    }
+
+    #[test]
+    fn test_render_pixmap_rejects_svgs_without_a_size() {
+        let renderer = SvgRenderer::new(Arc::new(()));
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="0" height="16"/>"#;
+        assert!(matches!(
+            renderer.render_pixmap(svg, SvgSize::ScaleFactor(2.), None),
+            Err(ImageCacheError::ZeroSize)
+        ));
+    }
+
+    #[test]
+    fn test_options_control_unit_conversion() {
+        let mut renderer = SvgRenderer::new(Arc::new(()));
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1in" height="0.5in"/>"#;
+        assert_eq!(
+            renderer.intrinsic_size(svg).unwrap(),
+            crate::size(DevicePixels(96), DevicePixels(48))
+        );
+
+        renderer.set_options(SvgOptions {
+            dpi: 72.,
+            ..Default::default()
+        });
+        assert_eq!(
+            renderer.intrinsic_size(svg).unwrap(),
+            crate::size(DevicePixels(72), DevicePixels(36))
         );
+    }
+
+    #[test]
+    fn test_crisp_quality_disables_anti_aliasing() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8">
+            <circle cx="4" cy="4" r="3.3" fill="black"/>
+        </svg>"#;
+        let has_partial_coverage = |renderer: &SvgRenderer| {
+            renderer
+                .render_pixmap(svg, SvgSize::ScaleFactor(1.), None)
+                .unwrap()
+                .pixels()
+                .iter()
+                .any(|pixel| pixel.alpha() != 0 && pixel.alpha() != 255)
+        };
+
+        let mut renderer = SvgRenderer::new(Arc::new(()));
+        assert!(has_partial_coverage(&renderer));
+        renderer.set_quality(SvgQuality::Crisp);
+        assert!(!has_partial_coverage(&renderer));
+    }
+
+    #[test]
+    fn test_render_reuses_parsed_trees_until_the_asset_changes() {
+        struct Icon(Mutex<&'static [u8]>);
+
+        impl AssetSource for Icon {
+            fn load(&self, _path: &str) -> Result<Cow<'static, [u8]>> {
+                Ok(Cow::Borrowed(*self.0.lock()))
+            }
+
+            fn list(&self, _path: &str) -> Result<Vec<SharedString>> {
+                Ok(Vec::new())
+            }
+        }
+
+        let icon = Arc::new(Icon(Mutex::new(SVG)));
+        let renderer = SvgRenderer::new(icon.clone());
+        let path = SharedString::from("icon.svg");
+        let tree = |renderer: &SvgRenderer| {
+            let bytes = renderer.asset_source().load(&path).unwrap();
+            renderer.parsed_tree(&path, &bytes).unwrap()
+        };
+
+        let first = tree(&renderer);
+        for size in [8., 16., 32.] {
+            let params = RenderSvgParams::new(path.clone(), crate::size(px(size), px(size)), 1.);
+            renderer.render(&params).unwrap();
+        }
+        assert!(Arc::ptr_eq(&first, &tree(&renderer.clone())));
+
+        *icon.0.lock() = br#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8"/>"#;
+        assert!(!Arc::ptr_eq(&first, &tree(&renderer)));
+    }
+
+    #[test]
+    fn test_transparent_fallback_replaces_unrenderable_svgs() {
+        let mut renderer = SvgRenderer::new(Arc::new(()));
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="0" height="16"/>"#;
+        renderer.set_failure_policy(SvgFailurePolicy::TransparentFallback);
+
+        let pixmap = renderer
+            .render_pixmap(svg, SvgSize::ScaleFactor(2.), None)
+            .unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (1, 1));
+        assert_eq!(pixmap.pixel(0, 0).unwrap().alpha(), 0);
+        // SVGs that can't be parsed at all still fail.
+        assert!(renderer
+            .render_pixmap(b"not an svg", SvgSize::ScaleFactor(2.), None)
+            .is_err());
+    }
 
-        resvg::render(&tree, transform, &mut pixmap.as_mut());
+    #[test]
+    fn test_font_loading() {
+        let mut renderer = SvgRenderer::new(Arc::new(()));
+        renderer.set_font_loading(SvgFontLoading::None);
+        assert!(renderer.fontdb().is_empty());
 
-        Ok(pixmap)
+        renderer.set_font_loading(SvgFontLoading::Custom(vec![PathBuf::from(
+            "/nonexistent/font.ttf",
+        )]));
+        let fontdb = renderer.fontdb();
+        assert!(fontdb.is_empty());
+        // The fonts are only loaded once, even by clones of the renderer.
+        assert!(Arc::ptr_eq(&fontdb, &renderer.clone().fontdb()));
+
+        // Warming a clone loads the fonts that the original renders with.
+        renderer.set_font_loading(SvgFontLoading::Custom(Vec::new()));
+        let SvgFonts::Files(_, loaded) = &renderer.fonts else {
+            panic!("expected the fonts to be loaded from files");
+        };
+        assert!(loaded.get().is_none());
+        renderer.clone().warm_fontdb();
+        assert!(Arc::ptr_eq(loaded.get().unwrap(), &renderer.fontdb()));
+    }
+
+    #[test]
+    fn test_render_rgba_keeps_colors_and_flattens_onto_the_background() {
+        struct Icon;
+
+        impl AssetSource for Icon {
+            fn load(&self, _path: &str) -> Result<Cow<'static, [u8]>> {
+                Ok(Cow::Borrowed(
+                    br#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4">
+                        <rect width="2" height="4" fill="red"/>
+                    </svg>"#,
+                ))
+            }
+
+            fn list(&self, _path: &str) -> Result<Vec<SharedString>> {
+                Ok(Vec::new())
+            }
+        }
+
+        let renderer = SvgRenderer::new(Arc::new(Icon));
+        let params = RenderSvgParams::new("icon.svg".into(), crate::size(px(4.), px(4.)), 1.);
+        let pixel = |data: &ImageData, x: usize| {
+            let offset = x * 4;
+            data.as_bytes()[offset..offset + 4].to_vec()
+        };
+
+        let transparent = renderer.render_rgba(&params, None).unwrap();
+        assert_eq!(transparent.size(), params.size);
+        assert_eq!(pixel(&transparent, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&transparent, 7), [0, 0, 0, 0]);
+
+        let flattened = renderer.render_rgba(&params, Some(crate::white())).unwrap();
+        assert_eq!(pixel(&flattened, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&flattened, 7), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_render_pixmap_caps_scaled_size() {
+        let mut renderer = SvgRenderer::new(Arc::new(()));
+        renderer.set_max_rasterization_size(DevicePixels(100));
+
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1000" height="500"/>"#;
+        let pixmap = renderer
+            .render_pixmap(svg, SvgSize::ScaleFactor(2.), None)
+            .unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_render_pixmap_caps_requested_size() {
+        let mut renderer = SvgRenderer::new(Arc::new(()));
+        renderer.set_max_rasterization_size(DevicePixels(100));
+
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"/>"#;
+        for size in [
+            SvgSize::Size(crate::size(DevicePixels(100_000), DevicePixels(50_000))),
+            SvgSize::Stretch(crate::size(DevicePixels(100_000), DevicePixels(50_000))),
+        ] {
+            let pixmap = renderer.render_pixmap(svg, size, None).unwrap();
+            assert_eq!((pixmap.width(), pixmap.height()), (100, 50));
+        }
     }
 }
@@ -25,7 +25,7 @@
 //!   assert!(true)
 //! }
 //! ```
-use crate::{Entity, Subscription, TestAppContext, TestDispatcher};
+use crate::{Entity, ImageData, Subscription, TestAppContext, TestDispatcher};
 use futures::StreamExt as _;
 use rand::prelude::*;
 use smol::channel;
@@ -111,3 +111,25 @@ pub fn observe<T: 'static>(entity: &impl Entity<T>, cx: &mut TestAppContext) ->
 
     Observation { rx, _subscription }
 }
+
+/// Assert that two images have the same dimensions, and that no channel of any of their
+/// pixels differs by more than `tolerance`. See [`ImageData::diff`].
+#[track_caller]
+pub fn assert_images_similar(a: &ImageData, b: &ImageData, tolerance: u8) {
+    let diff = a.diff(b);
+    assert!(
+        diff.dimensions_match,
+        "images have different dimensions: {:?} with {} frames and {:?} with {} frames",
+        a.size(),
+        a.frame_count(),
+        b.size(),
+        b.frame_count(),
+    );
+    assert!(
+        diff.is_within(tolerance),
+        "{} pixels differ, by up to {} in a channel, which is more than the tolerance of {}",
+        diff.mismatched_pixels,
+        diff.max_channel_delta,
+        tolerance,
+    );
+}
@@ -1,19 +1,20 @@
 use crate::{
-    hash, point, prelude::*, px, size, transparent_black, Action, AnyDrag, AnyElement, AnyTooltip,
-    AnyView, AppContext, Arena, Asset, AsyncWindowContext, AvailableSpace, Bounds, BoxShadow,
-    Context, Corners, CursorStyle, DevicePixels, DispatchActionListener, DispatchNodeId,
-    DispatchTree, DisplayId, Edges, Effect, Entity, EntityId, EventEmitter, FileDropEvent, Flatten,
-    FontId, Global, GlobalElementId, GlyphId, Hsla, ImageData, InputHandler, IsZero, KeyBinding,
+    hash, point, prelude::*, px, size, transparent_black, white, Action, AnyDrag, AnyElement,
+    AnyTooltip, AnyView, AppContext, Arena, Asset, AssetLoadGuard, AsyncWindowContext, AtlasKey,
+    AvailableSpace, BlendMode, Bounds, BoxShadow, Context, Corners, CursorStyle, DevicePixels,
+    DispatchActionListener, DispatchNodeId, DispatchTree, DisplayId, Edges, Effect, Entity,
+    EntityId, EventEmitter, FileDropEvent, Flatten, FontId, Global, GlobalElementId, GlyphId, Hsla,
+    ImageCacheError, ImageClip, ImageData, ImageSource, InputHandler, IsZero, KeyBinding,
     KeyContext, KeyDownEvent, KeyEvent, KeyMatch, KeymatchResult, Keystroke, KeystrokeEvent,
-    LayoutId, LineLayoutIndex, Model, ModelContext, Modifiers, ModifiersChangedEvent,
-    MonochromeSprite, MouseButton, MouseEvent, MouseMoveEvent, MouseUpEvent, Path, Pixels,
-    PlatformAtlas, PlatformDisplay, PlatformInput, PlatformInputHandler, PlatformWindow, Point,
-    PolychromeSprite, PromptLevel, Quad, Render, RenderGlyphParams, RenderImageParams,
-    RenderSvgParams, ScaledPixels, Scene, Shadow, SharedString, Size, StrikethroughStyle, Style,
-    SubscriberSet, Subscription, TaffyLayoutEngine, Task, TextStyle, TextStyleRefinement,
-    TransformationMatrix, Underline, UnderlineStyle, View, VisualContext, WeakView,
-    WindowAppearance, WindowBackgroundAppearance, WindowOptions, WindowParams, WindowTextSystem,
-    SUBPIXEL_VARIANTS,
+    LayoutId, LineLayoutIndex, LoadingAsset, Model, ModelContext, Modifiers, ModifiersChangedEvent,
+    MonochromeSprite, MouseButton, MouseEvent, MouseMoveEvent, MouseUpEvent, ObjectFit, Path,
+    Pixels, PlatformAtlas, PlatformDisplay, PlatformInput, PlatformInputHandler, PlatformWindow,
+    Point, PolychromeSprite, PromptLevel, Quad, Render, RenderGlyphParams, RenderImageParams,
+    RenderSvgParams, Rotation, Sampling, ScaledPixels, Scene, Shadow, SharedString, Size,
+    StrikethroughStyle, Style, SubscriberSet, Subscription, TaffyLayoutEngine, Task, TextStyle,
+    TextStyleRefinement, TransformationMatrix, Underline, UnderlineStyle, View, VisualContext,
+    WeakView, WindowAppearance, WindowBackgroundAppearance, WindowOptions, WindowParams,
+    WindowTextSystem, SUBPIXEL_VARIANTS,
 };
 use anyhow::{anyhow, Context as _, Result};
 use collections::{FxHashMap, FxHashSet};
@@ -312,6 +313,12 @@ pub struct Hitbox {
     pub content_mask: ContentMask<Pixels>,
     /// Whether the hitbox occludes other hitboxes inserted prior.
     pub opaque: bool,
+    /// The transformation from the hitbox's bounds to where it's drawn in the window, in
+    /// logical pixels. See [WindowContext::with_hitbox_transformation].
+    pub transformation: TransformationMatrix,
+    /// What's drawn within the hitbox, described for assistive technology, such as an
+    /// image's alt text. See [WindowContext::accessibility_label_at].
+    pub label: Option<SharedString>,
 }
 
 impl Hitbox {
@@ -375,6 +382,10 @@ pub(crate) struct Frame {
     pub(crate) input_handlers: Vec<Option<PlatformInputHandler>>,
     pub(crate) tooltip_requests: Vec<Option<TooltipRequest>>,
     pub(crate) cursor_styles: Vec<CursorStyleRequest>,
+    /// Sprites this frame no longer paints, to remove from the atlas once it's presented.
+    /// Unlike the rest of the frame, these are kept when it's cleared without having been
+    /// presented, and removed after the next frame instead.
+    released_atlas_keys: Vec<AtlasKey>,
     #[cfg(any(test, feature = "test-support"))]
     pub(crate) debug_bounds: FxHashMap<String, Bounds<Pixels>>,
 }
@@ -414,6 +425,7 @@ impl Frame {
             input_handlers: Vec::new(),
             tooltip_requests: Vec::new(),
             cursor_styles: Vec::new(),
+            released_atlas_keys: Vec::new(),
 
             #[cfg(any(test, feature = "test-support"))]
             debug_bounds: FxHashMap::default(),
@@ -436,8 +448,19 @@ impl Frame {
     pub(crate) fn hit_test(&self, position: Point<Pixels>) -> HitTest {
         let mut hit_test = HitTest::default();
         for hitbox in self.hitboxes.iter().rev() {
-            let bounds = hitbox.bounds.intersect(&hitbox.content_mask.bounds);
-            if bounds.contains(&position) {
+            let local_position = if hitbox.transformation == TransformationMatrix::unit() {
+                Some(position)
+            } else {
+                hitbox
+                    .transformation
+                    .inverse()
+                    .map(|inverse| inverse.apply(position))
+            };
+            let hit = local_position.map_or(false, |local_position| {
+                hitbox.content_mask.bounds.contains(&position)
+                    && hitbox.bounds.contains(&local_position)
+            });
+            if hit {
                 hit_test.0.push(hitbox.id);
                 if hitbox.opaque {
                     break;
@@ -447,6 +470,17 @@ impl Frame {
         hit_test
     }
 
+    /// The label of the topmost hitbox at the given position that has one.
+    pub(crate) fn label_at(&self, position: Point<Pixels>) -> Option<SharedString> {
+        self.hit_test(position).0.iter().find_map(|id| {
+            self.hitboxes
+                .iter()
+                .find(|hitbox| hitbox.id == *id)?
+                .label
+                .clone()
+        })
+    }
+
     pub(crate) fn focus_path(&self) -> SmallVec<[FocusId; 8]> {
         self.focus
             .map(|focus_id| self.dispatch_tree.focus_path(focus_id))
@@ -483,7 +517,12 @@ pub struct Window {
     pub(crate) text_style_stack: Vec<TextStyleRefinement>,
     pub(crate) element_offset_stack: Vec<Point<Pixels>>,
     pub(crate) content_mask_stack: Vec<ContentMask<Pixels>>,
+    pub(crate) hitbox_transformation_stack: Vec<TransformationMatrix>,
     pub(crate) requested_autoscroll: Option<Bounds<Pixels>>,
+    asset_load_guards: Option<Vec<AssetLoadGuard>>,
+    /// The sizes each SVG painted at a given logical size has been rasterized at, so the
+    /// rasterizations for a previous scale factor can be released when it changes.
+    svg_variants: FxHashMap<(SharedString, Size<Pixels>), FxHashSet<Size<DevicePixels>>>,
     pub(crate) rendered_frame: Frame,
     pub(crate) next_frame: Frame,
     pub(crate) next_hitbox_id: HitboxId,
@@ -756,7 +795,10 @@ impl Window {
             text_style_stack: Vec::new(),
             element_offset_stack: Vec::new(),
             content_mask_stack: Vec::new(),
+            hitbox_transformation_stack: Vec::new(),
             requested_autoscroll: None,
+            asset_load_guards: None,
+            svg_variants: FxHashMap::default(),
             rendered_frame: Frame::new(DispatchTree::new(cx.keymap.clone(), cx.actions.clone())),
             next_frame: Frame::new(DispatchTree::new(cx.keymap.clone(), cx.actions.clone())),
             next_frame_callbacks,
@@ -827,6 +869,65 @@ impl ContentMask<Pixels> {
     }
 }
 
+/// How [`WindowContext::paint_image`] draws an image. The default paints the first frame
+/// of the whole image unchanged, with square corners.
+#[derive(Clone, Debug)]
+pub struct ImagePaintParams {
+    /// The frame of the image to paint.
+    pub frame_index: usize,
+    /// The region of the image to stretch to fill the bounds, in the image's pixels, or
+    /// `None` for the whole image.
+    pub source: Option<Bounds<DevicePixels>>,
+    /// The shape the image is clipped to, with anti-aliased edges.
+    pub clip: ImageClip,
+    /// The bounds `clip` is placed within, or `None` for the bounds the image is painted in.
+    pub clip_bounds: Option<Bounds<Pixels>>,
+    /// How far each pixel is mixed towards its luminance, from `0.0` for full color to
+    /// `1.0` for fully gray.
+    pub desaturation: f32,
+    /// The color each pixel's red, green, blue and alpha channels are multiplied by, after
+    /// it's desaturated. [`white()`] leaves the image unchanged.
+    pub tint: Hsla,
+    /// The amount each pixel's alpha is multiplied by, after it's tinted.
+    pub opacity: f32,
+    /// How the image is combined with what's beneath it.
+    pub blend_mode: BlendMode,
+    /// How the image's pixels are sampled when it's scaled.
+    pub sampling: Sampling,
+    /// How far the image is rotated clockwise. The bounds should be sized for the rotated
+    /// image.
+    pub rotation: Rotation,
+    /// Whether the image is mirrored across the vertical axis of its bounds, after it's
+    /// rotated.
+    pub flip_horizontal: bool,
+    /// Whether the image is mirrored across the horizontal axis of its bounds, after it's
+    /// rotated.
+    pub flip_vertical: bool,
+    /// A transformation applied to the painted quad last, in scaled pixels like
+    /// [`WindowContext::paint_svg`]'s.
+    pub transformation: TransformationMatrix,
+}
+
+impl Default for ImagePaintParams {
+    fn default() -> Self {
+        Self {
+            frame_index: 0,
+            source: None,
+            clip: ImageClip::RoundedRect(Corners::default()),
+            clip_bounds: None,
+            desaturation: 0.,
+            tint: white(),
+            opacity: 1.,
+            blend_mode: BlendMode::default(),
+            sampling: Sampling::default(),
+            rotation: Rotation::default(),
+            flip_horizontal: false,
+            flip_vertical: false,
+            transformation: TransformationMatrix::unit(),
+        }
+    }
+}
+
 /// Provides access to application state in the context of a single window. Derefs
 /// to an [`AppContext`], so you can also pass a [`WindowContext`] to any method that takes
 /// an [`AppContext`] and call any [`AppContext`] methods.
@@ -1076,7 +1177,11 @@ impl<'a> WindowContext<'a> {
     }
 
     fn window_bounds_changed(&mut self) {
+        let previous_scale_factor = self.window.scale_factor;
         self.window.scale_factor = self.window.platform_window.scale_factor();
+        if self.window.scale_factor != previous_scale_factor {
+            self.release_svg_variants_for_other_scale_factors();
+        }
         self.window.viewport_size = self.window.platform_window.content_size();
         self.window.display_id = self.window.platform_window.display().id();
         self.refresh();
@@ -1087,6 +1192,45 @@ impl<'a> WindowContext<'a> {
             .retain(&(), |callback| callback(self));
     }
 
+    /// Release the rasterizations of SVGs made for any scale factor other than the current
+    /// one, such as those made before the window moved to a display with a different DPI.
+    fn release_svg_variants_for_other_scale_factors(&mut self) {
+        let scale_factor = self.window.scale_factor;
+        let sprite_atlas = self.window.sprite_atlas.clone();
+        self.window
+            .svg_variants
+            .retain(|(path, logical_size), sizes| {
+                let current = RenderSvgParams::new(path.clone(), *logical_size, scale_factor);
+                sizes.retain(|size| {
+                    let retain = *size == current.size;
+                    if !retain {
+                        sprite_atlas.remove(
+                            &RenderSvgParams {
+                                size: *size,
+                                ..current.clone()
+                            }
+                            .into(),
+                        );
+                    }
+                    retain
+                });
+                !sizes.is_empty()
+            });
+    }
+
+    /// The number of sizes the SVG at the given path is currently rasterized at in this
+    /// window, across all the sizes it's displayed at and the scale factors it's been
+    /// displayed with.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn rasterized_svg_count(&self, path: &str) -> usize {
+        self.window
+            .svg_variants
+            .iter()
+            .filter(|((variant_path, _), _)| variant_path.as_ref() == path)
+            .map(|(_, sizes)| sizes.len())
+            .sum()
+    }
+
     /// Returns the bounds of the current window in the global coordinate space, which could span across multiple displays.
     pub fn window_bounds(&self) -> Bounds<DevicePixels> {
         self.window.platform_window.bounds()
@@ -1284,6 +1428,10 @@ impl<'a> WindowContext<'a> {
         let previous_window_active = self.window.rendered_frame.window_active;
         mem::swap(&mut self.window.rendered_frame, &mut self.window.next_frame);
         self.window.next_frame.clear();
+        // Elements that are no longer drawn have released their guards along with their state.
+        self.app
+            .loading_assets
+            .retain(|_, asset| !asset.is_abandoned());
         let current_focus_path = self.window.rendered_frame.focus_path();
         let current_window_active = self.window.rendered_frame.window_active;
 
@@ -1322,10 +1470,13 @@ impl<'a> WindowContext<'a> {
     }
 
     #[profiling::function]
-    fn present(&self) {
+    fn present(&mut self) {
         self.window
             .platform_window
             .draw(&self.window.rendered_frame.scene);
+        for key in self.window.rendered_frame.released_atlas_keys.drain(..) {
+            self.window.sprite_atlas.remove(&key);
+        }
         self.window.needs_present.set(false);
         profiling::finish_frame!();
     }
@@ -1707,6 +1858,37 @@ impl<'a> WindowContext<'a> {
         result
     }
 
+    /// Transform the hitboxes inserted in the given closure, so that mouse events hit them
+    /// where they're drawn with the same transformation rather than at their bounds. The
+    /// transformation is in logical pixels and composes with any enclosing one. This
+    /// method should only be called during the prepaint phase of element drawing.
+    pub fn with_hitbox_transformation<R>(
+        &mut self,
+        transformation: TransformationMatrix,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        debug_assert_eq!(
+            self.window.draw_phase,
+            DrawPhase::Prepaint,
+            "this method can only be called during prepaint"
+        );
+        let transformation = self.hitbox_transformation().compose(transformation);
+        self.window_mut()
+            .hitbox_transformation_stack
+            .push(transformation);
+        let result = f(self);
+        self.window_mut().hitbox_transformation_stack.pop();
+        result
+    }
+
+    fn hitbox_transformation(&self) -> TransformationMatrix {
+        self.window()
+            .hitbox_transformation_stack
+            .last()
+            .copied()
+            .unwrap_or_default()
+    }
+
     /// Perform prepaint on child elements in a "retryable" manner, so that any side effects
     /// of prepaints can be discarded before prepainting again. This is used to support autoscroll
     /// where we need to prepaint children to detect the autoscroll bounds, then adjust the
@@ -1812,44 +1994,112 @@ impl<'a> WindowContext<'a> {
     /// This asset will not be cached by default, see [Self::use_cached_asset]
     pub fn use_asset<A: Asset + 'static>(&mut self, source: &A::Source) -> Option<A::Output> {
         let asset_id = (TypeId::of::<A>(), hash(source));
-        let mut is_first = false;
-        let task = self
-            .loading_assets
-            .remove(&asset_id)
-            .map(|boxed_task| *boxed_task.downcast::<Shared<Task<A::Output>>>().unwrap())
-            .unwrap_or_else(|| {
-                is_first = true;
-                let future = A::load(source.clone(), self);
-                let task = self.background_executor().spawn(future).shared();
-                task
-            });
+        let mut loading_asset = self.loading_assets.remove(&asset_id).unwrap_or_else(|| {
+            let future = A::load(source.clone(), self);
+            let task = self.background_executor().spawn(future).shared();
+            LoadingAsset::new(Box::new(task))
+        });
+        let task = loading_asset
+            .task
+            .downcast_ref::<Shared<Task<A::Output>>>()
+            .unwrap()
+            .clone();
 
         task.clone().now_or_never().or_else(|| {
-            if is_first {
+            if loading_asset.notify.is_none() {
                 let parent_id = self.parent_view_id();
-                self.spawn({
-                    let task = task.clone();
-                    |mut cx| async move {
-                        task.await;
-
-                        cx.on_next_frame(move |cx| {
-                            if let Some(parent_id) = parent_id {
-                                cx.notify(parent_id)
-                            } else {
-                                cx.refresh()
-                            }
-                        });
-                    }
-                })
-                .detach();
+                loading_asset.notify = Some(self.spawn(|mut cx| async move {
+                    task.await;
+
+                    cx.on_next_frame(move |cx| {
+                        if let Some(parent_id) = parent_id {
+                            cx.notify(parent_id)
+                        } else {
+                            cx.refresh()
+                        }
+                    });
+                }));
             }
 
-            self.loading_assets.insert(asset_id, Box::new(task));
+            match self.window.asset_load_guards.as_mut() {
+                Some(guards) => guards.push(loading_asset.guard()),
+                None => loading_asset.pin(),
+            }
+            self.loading_assets.insert(asset_id, loading_asset);
 
             None
         })
     }
 
+    /// Run the given closure, collecting guards for the assets it requests through
+    /// [`Self::use_asset`] that are still loading. Such loads are cancelled at the end of
+    /// a frame once all of their guards have been dropped, which lets an element stop a
+    /// fetch it no longer needs by storing the guards in its element state. Loads that
+    /// are also requested outside of this method always run to completion.
+    pub fn with_asset_load_guards<R>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> (R, Vec<AssetLoadGuard>) {
+        let previous_guards = self.window.asset_load_guards.replace(Vec::new());
+        let result = f(self);
+        let guards = mem::replace(&mut self.window.asset_load_guards, previous_guards);
+        (result, guards.unwrap_or_default())
+    }
+
+    /// Load the intrinsic size of an image in pixels, without rendering it. Where the image
+    /// format allows, only its header is decoded. SVGs report the size declared in the
+    /// document, before any scaling for the display.
+    ///
+    /// Sizes are cached, so querying the same source again resolves immediately, and
+    /// queries made while the size is still loading share the same load.
+    pub fn image_size(
+        &mut self,
+        source: impl Into<ImageSource>,
+    ) -> Task<Result<Size<DevicePixels>, ImageCacheError>> {
+        source.into().size(self)
+    }
+
+    /// Make a thumbnail of an image, scaled and cropped into `size` device pixels according
+    /// to `object_fit`. Fits that leave space around the image, like
+    /// [`ObjectFit::Contain`], produce a thumbnail only as large as the image within it.
+    ///
+    /// Thumbnails are cached by source, size and fit, separately from the full image, which
+    /// is decoded without being cached so that only the thumbnail stays in memory. Remote
+    /// images are still read through the disk cache if one is configured. Requests made
+    /// while the same thumbnail is still being made share it.
+    pub fn image_thumbnail(
+        &mut self,
+        source: impl Into<ImageSource>,
+        size: Size<DevicePixels>,
+        object_fit: ObjectFit,
+    ) -> Task<Result<Arc<ImageData>, ImageCacheError>> {
+        source.into().thumbnail(size, object_fit, self)
+    }
+
+    /// Extract the frame of an animated image, such as a GIF or WebP, that's shown `time`
+    /// after the animation starts, as a still image. Looping animations wrap around, and
+    /// ones that stop after a number of loops give their last frame once they've finished.
+    /// Still images give their only frame.
+    ///
+    /// The whole animation is loaded into the asset cache, as it would be to draw it, and
+    /// each extracted frame is cached by source and frame index.
+    pub fn image_frame_at(
+        &mut self,
+        source: impl Into<ImageSource>,
+        time: Duration,
+    ) -> Task<Result<Arc<ImageData>, ImageCacheError>> {
+        source.into().frame_at(time, self)
+    }
+
+    /// Load an image into the asset cache ahead of drawing it, so it appears without delay
+    /// once an [`crate::Img`] shows it. Images that are already cached resolve immediately.
+    pub fn prefetch_image(
+        &mut self,
+        source: impl Into<ImageSource>,
+    ) -> Task<Result<(), ImageCacheError>> {
+        source.into().prefetch(self)
+    }
+
     /// Obtain the current element offset. This method should only be called during the
     /// prepaint phase of element drawing.
     pub fn element_offset(&self) -> Point<Pixels> {
@@ -2355,11 +2605,21 @@ impl<'a> WindowContext<'a> {
                 .scene
                 .insert_primitive(PolychromeSprite {
                     order: 0,
-                    grayscale: false,
+                    desaturation: 0.,
+                    opacity: 1.0,
+                    blend_mode: BlendMode::Normal,
+                    rotation: Rotation::None,
+                    flip_horizontal: false,
+                    flip_vertical: false,
+                    clip_ellipse: false,
+                    sampling: Sampling::Linear,
                     bounds,
                     corner_radii: Default::default(),
                     content_mask,
+                    tint: white(),
                     tile,
+                    transformation: TransformationMatrix::unit(),
+                    clip_bounds: bounds,
                 });
         }
         Ok(())
@@ -2382,22 +2642,21 @@ impl<'a> WindowContext<'a> {
         );
 
         let scale_factor = self.scale_factor();
+        let params = RenderSvgParams::new(path, bounds.size, scale_factor);
         let bounds = bounds.scale(scale_factor);
-        // Render the SVG at twice the size to get a higher quality result.
-        let params = RenderSvgParams {
-            path,
-            size: bounds
-                .size
-                .map(|pixels| DevicePixels::from((pixels.0 * 2.).ceil() as i32)),
-        };
 
         let tile =
             self.window
                 .sprite_atlas
                 .get_or_insert_with(&params.clone().into(), &mut || {
-                    let bytes = self.svg_renderer.render(&params)?;
-                    Ok((params.size, Cow::Owned(bytes)))
+                    let (size, bytes) = self.svg_renderer.render(&params)?;
+                    Ok((size, Cow::Owned(bytes)))
                 })?;
+        self.window
+            .svg_variants
+            .entry((params.path, params.logical_size))
+            .or_default()
+            .insert(params.size);
         let content_mask = self.content_mask().scale(scale_factor);
 
         self.window
@@ -2416,54 +2675,121 @@ impl<'a> WindowContext<'a> {
         Ok(())
     }
 
-    /// Paint an image into the scene for the next frame at the current z-index.
+    /// Paint a frame of an image into the scene for the next frame at the current z-index,
+    /// filling `bounds` and drawn as described by `params`.
     ///
     /// This method should only be called as part of the paint phase of element drawing.
     pub fn paint_image(
         &mut self,
         bounds: Bounds<Pixels>,
-        corner_radii: Corners<Pixels>,
         data: Arc<ImageData>,
-        grayscale: bool,
+        params: ImagePaintParams,
     ) -> Result<()> {
+        let ImagePaintParams {
+            frame_index,
+            source,
+            clip,
+            clip_bounds,
+            desaturation,
+            tint,
+            opacity,
+            blend_mode,
+            sampling,
+            rotation,
+            flip_horizontal,
+            flip_vertical,
+            transformation,
+        } = params;
         debug_assert_eq!(
             self.window.draw_phase,
             DrawPhase::Paint,
             "this method can only be called during paint"
         );
 
+        let (clip_bounds, corner_radii) = clip.rounded_rect(clip_bounds.unwrap_or(bounds));
         let scale_factor = self.scale_factor();
         let bounds = bounds.scale(scale_factor);
-        let params = RenderImageParams { image_id: data.id };
+        let params = RenderImageParams {
+            image_id: data.id,
+            frame_index,
+        };
 
-        let tile = self
-            .window
-            .sprite_atlas
-            .get_or_insert_with(&params.clone().into(), &mut || {
-                Ok((data.size(), Cow::Borrowed(data.as_bytes())))
-            })?;
+        let mut tile =
+            self.window
+                .sprite_atlas
+                .get_or_insert_with(&params.clone().into(), &mut || {
+                    let bytes = data
+                        .frame_as_bytes(frame_index)
+                        .ok_or_else(|| anyhow!("image has no frame at index {frame_index}"))?;
+                    Ok((data.size(), Cow::Borrowed(bytes)))
+                })?;
+        if let Some(source) = source {
+            // Sample part of the image by narrowing the tile to that region of the atlas.
+            let source = source.intersect(&Bounds {
+                origin: Point::default(),
+                size: data.size(),
+            });
+            if source.size.width.0 <= 0 || source.size.height.0 <= 0 {
+                return Ok(());
+            }
+            tile.bounds = Bounds {
+                origin: tile.bounds.origin + source.origin,
+                size: source.size,
+            };
+        }
         let content_mask = self.content_mask().scale(scale_factor);
-        let corner_radii = corner_radii.scale(scale_factor);
 
         self.window
             .next_frame
             .scene
             .insert_primitive(PolychromeSprite {
                 order: 0,
-                grayscale,
+                desaturation,
+                opacity,
+                blend_mode,
+                rotation,
+                flip_horizontal,
+                flip_vertical,
+                clip_ellipse: clip == ImageClip::Ellipse,
+                sampling,
                 bounds,
                 content_mask,
-                corner_radii,
+                corner_radii: corner_radii.scale(scale_factor),
+                tint,
                 tile,
+                transformation,
+                clip_bounds: clip_bounds.scale(scale_factor),
             });
         Ok(())
     }
 
-    /// Paint a surface into the scene for the next frame at the current z-index.
+    /// Free the space used by every frame of an image in the sprite atlas. Painted images
+    /// otherwise stay in the atlas until the window is closed, so call this for images that
+    /// won't be painted again, such as the previous frame of a video. The space is only
+    /// freed once the frame being drawn has been presented, since the frame on screen may
+    /// still be showing the image until then.
+    pub(crate) fn drop_image(&mut self, data: &ImageData) {
+        let released_atlas_keys = &mut self.window.next_frame.released_atlas_keys;
+        released_atlas_keys.extend((0..data.frame_count()).map(|frame_index| {
+            AtlasKey::from(RenderImageParams {
+                image_id: data.id,
+                frame_index,
+            })
+        }));
+    }
+
+    /// Paint a surface into the scene for the next frame at the current z-index, clipped to
+    /// the `clip` shape within `clip_bounds`, like [`Self::paint_image`].
     ///
     /// This method should only be called as part of the paint phase of element drawing.
     #[cfg(target_os = "macos")]
-    pub fn paint_surface(&mut self, bounds: Bounds<Pixels>, image_buffer: CVImageBuffer) {
+    pub fn paint_surface(
+        &mut self,
+        bounds: Bounds<Pixels>,
+        clip_bounds: Bounds<Pixels>,
+        clip: ImageClip,
+        image_buffer: CVImageBuffer,
+    ) {
         debug_assert_eq!(
             self.window.draw_phase,
             DrawPhase::Paint,
@@ -2473,13 +2799,17 @@ impl<'a> WindowContext<'a> {
         let scale_factor = self.scale_factor();
         let bounds = bounds.scale(scale_factor);
         let content_mask = self.content_mask().scale(scale_factor);
+        let (clip_bounds, corner_radii) = clip.rounded_rect(clip_bounds);
         self.window
             .next_frame
             .scene
-            .insert_primitive(crate::Surface {
+            .insert_primitive(crate::PaintSurface {
                 order: 0,
                 bounds,
                 content_mask,
+                clip_bounds: clip_bounds.scale(scale_factor),
+                corner_radii: corner_radii.scale(scale_factor),
+                clip_ellipse: clip == ImageClip::Ellipse,
                 image_buffer,
             });
     }
@@ -2587,6 +2917,17 @@ impl<'a> WindowContext<'a> {
     ///
     /// This method should only be called as part of the prepaint phase of element drawing.
     pub fn insert_hitbox(&mut self, bounds: Bounds<Pixels>, opaque: bool) -> Hitbox {
+        self.insert_labeled_hitbox(bounds, opaque, None)
+    }
+
+    /// Insert a hitbox that describes what's drawn within it for assistive technology. See
+    /// [`Self::insert_hitbox`] and [`Self::accessibility_label_at`].
+    pub(crate) fn insert_labeled_hitbox(
+        &mut self,
+        bounds: Bounds<Pixels>,
+        opaque: bool,
+        label: Option<SharedString>,
+    ) -> Hitbox {
         debug_assert_eq!(
             self.window.draw_phase,
             DrawPhase::Prepaint,
@@ -2594,6 +2935,7 @@ impl<'a> WindowContext<'a> {
         );
 
         let content_mask = self.content_mask();
+        let transformation = self.hitbox_transformation();
         let window = &mut self.window;
         let id = window.next_hitbox_id;
         window.next_hitbox_id.0 += 1;
@@ -2602,11 +2944,19 @@ impl<'a> WindowContext<'a> {
             bounds,
             content_mask,
             opaque,
+            transformation,
+            label,
         };
         window.next_frame.hitboxes.push(hitbox.clone());
         hitbox
     }
 
+    /// The description of what's drawn at the given position for assistive technology, such
+    /// as an image's alt text, taken from the topmost element there that has one.
+    pub fn accessibility_label_at(&self, position: Point<Pixels>) -> Option<SharedString> {
+        self.window.rendered_frame.label_at(position)
+    }
+
     /// Sets the key context for the current element. This context will be used to translate
     /// keybindings into actions.
     ///
@@ -100,16 +100,28 @@ pub trait HttpClient: Send + Sync {
         body: AsyncBody,
         follow_redirects: bool,
     ) -> BoxFuture<'a, Result<Response<AsyncBody>, Error>> {
-        let request = isahc::Request::builder()
+        self.get_with_headers(uri, &[], body, follow_redirects)
+    }
+
+    fn get_with_headers<'a>(
+        &'a self,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: AsyncBody,
+        follow_redirects: bool,
+    ) -> BoxFuture<'a, Result<Response<AsyncBody>, Error>> {
+        let mut request = isahc::Request::builder()
             .redirect_policy(if follow_redirects {
                 RedirectPolicy::Follow
             } else {
                 RedirectPolicy::None
             })
             .method(Method::GET)
-            .uri(uri)
-            .body(body);
-        match request {
+            .uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        match request.body(body) {
             Ok(request) => self.send(request),
             Err(error) => async move { Err(error.into()) }.boxed(),
         }